@@ -17,26 +17,71 @@ Accepts the following flags:
                           --show-moves    if you want to see the count by first move
                           --parallel      if you want to execute this in parallel
 
+   --epd, -e            Runs an EPD test suite (WAC, ECM, STS, ...). Works as follows:
+                          --epd {file} [--movetime {ms}]
+                        Where:
+                          file            the EPD file, one position per line
+                          --movetime      how long to search each position, 1 second if not given.
+                                          Without it, positions with an acd operation are searched
+                                          to that depth instead
+                        Positions are solved by playing one of the bm moves and none of the am moves.
+                        STS style suites also score the points listed in c0.
+
+   --bench, -b          Searches a fixed set of positions to a fixed depth, counting the nodes. Works as follows:
+                          --bench [depth]
+                        Where:
+                          depth           how deep to search each position, 6 if not given
+                        The node count only changes when the search does, handy to compare versions.
+
+   book-build           Builds a Polyglot opening book out of PGN games. Works as follows:
+                          book-build --pgn {file} --out {file} [--max-ply {n}] [--min-games {n}]
+                                     [--min-elo {rating}] [--merge {file}]
+                        Where:
+                          --pgn           the games to read
+                          --out           where to write the book
+                          --max-ply       how many plies of each game go in the book, 24 if not given
+                          --min-games     how many games a move needs to be played in, 5 if not given
+                          --min-elo       leave out games unless both WhiteElo and BlackElo are this high
+                          --merge         a book to add to the new one, can be the same as --out
+                        Moves are weighted by how they scored, 2 points for a win and 1 for a draw.
+
    --interactive, -i    Interactive board, to play agains Rusty Pigeon in the terminal. Works as follows:
-                          --interactive [fen] [--auto]
+                          --interactive [fen] [--auto] [--black] [--pgn {file}]
                         Where:
                           fen             the fen of the position to start with
                           --auto          if you want the computer to play itself
                           --black         if you want to play as black
+                          --pgn           where to save the game, appending to the file
+                        Moves can be given in SAN (Nf3) or long algebraic notation (g1f3).
+                        Type quit to stop, the game is saved either way.
 
 ";
 
-use std::{env, io::stdin, process::exit, time::Instant};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{stdin, BufRead, BufReader, Write},
+    process::exit,
+    slice,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use rustypigeonlib::constants::START_POS;
 use rustypigeonlib::{
     board::{
         fen::FenParser,
-        models::{Engine, Side},
+        models::{Engine, Position, SharedSearch, Side},
         utils::perft,
     },
+    book::{
+        builder::{BookBuilder, DEFAULT_MAX_PLY, DEFAULT_MIN_GAMES},
+        reader::Book,
+    },
+    endgame::table::EndgameTable,
     evaluate::evaluator::Evaluate,
     movegen::generator::{MoveGenKind, MoveGenerator},
+    pgn::{game::Game, reader::PgnReader},
     uci::{options::GoOptions, protocol::UCI},
 };
 
@@ -63,13 +108,56 @@ fn main() {
         );
     });
 
+    // --epd
+    check_flags(("--epd", "-e"), &|args| {
+        let Some(file) = args.get(2).filter(|file| !file.starts_with('-')) else {
+            println!("Usage: --epd {{file}} [--movetime {{ms}}]");
+            exit(1);
+        };
+        let movetime = get_flag_value("--movetime").map(|movetime| match movetime.trim().parse() {
+            Ok(movetime) => movetime,
+            Err(_) => {
+                println!("Invalid movetime: {movetime}");
+                exit(1);
+            }
+        });
+        do_epd(file, movetime);
+    });
+
+    // --bench
+    check_flags(("--bench", "-b"), &|args| {
+        let depth = match args.get(2) {
+            Some(depth) if !depth.starts_with('-') => depth.trim().parse().unwrap(),
+            _ => 6,
+        };
+        do_bench(depth);
+    });
+
+    // book-build
+    check_flags(("book-build", "--book-build"), &|_| {
+        let (Some(pgn), Some(out)) = (get_flag_value("--pgn"), get_flag_value("--out")) else {
+            println!("Usage: book-build --pgn {{file}} --out {{file}} [--max-ply {{n}}] [--min-games {{n}}]");
+            exit(1);
+        };
+        let max_ply = parse_flag_or_exit("--max-ply").unwrap_or(DEFAULT_MAX_PLY);
+        let min_games = parse_flag_or_exit("--min-games").unwrap_or(DEFAULT_MIN_GAMES);
+        let min_rating = parse_flag_or_exit("--min-elo");
+        do_book_build(&pgn, &out, max_ply, min_games, min_rating, get_flag_value("--merge"));
+    });
+
     // --interactive
     check_flags(("--interactive", "-i"), &|args| {
-        let fen = if args.len() > 3 { &args[2] } else { START_POS };
+        let fen = match args.get(2) {
+            Some(fen) if !fen.starts_with('-') => fen,
+            _ => START_POS,
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let pgn_file = get_flag_value("--pgn").unwrap_or(format!("rusty-pigeon-{now}.pgn"));
         loop_game(
             fen,
             check_flag_present("--black"),
             check_flag_present("--auto"),
+            &pgn_file,
         );
     });
 
@@ -97,18 +185,75 @@ fn check_flag_present(flag: &str) -> bool {
     args.iter().any(|s| s.trim() == flag.trim())
 }
 
+// The argument following a flag, if both are there
+fn get_flag_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    let index = args.iter().position(|s| s.trim() == flag.trim())?;
+    args.get(index + 1).cloned()
+}
+
+// The number following a flag, exiting with a message if it isn't one
+fn parse_flag_or_exit(flag: &str) -> Option<usize> {
+    get_flag_value(flag).map(|value| match value.trim().parse() {
+        Ok(value) => value,
+        Err(_) => {
+            println!("Invalid {flag}: {value}");
+            exit(1);
+        }
+    })
+}
+
+// Parse a FEN given from the command line, exiting with a message if it is invalid
+fn parse_fen_or_exit(fen: &str) -> Position {
+    match FenParser::try_parse(fen) {
+        Ok(position) => position,
+        Err(error) => {
+            println!("Invalid FEN: {error}");
+            exit(1);
+        }
+    }
+}
+
 // Clears the terminal
 fn clear_terminal() {
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
 }
 
 // Play against the engine in a simple ascii mode
-fn loop_game(fen: &str, player_play_as_black: bool, auto_play: bool) {
+fn loop_game(fen: &str, player_play_as_black: bool, auto_play: bool, pgn_file: &str) {
     clear_terminal();
     println!("\nRusty Pigeon.\n");
-    let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+    EndgameTable::solve_on_demand();
+    let mut engine = Engine::from_position(parse_fen_or_exit(fen));
+    let mut game = Game::new(&engine.position);
+    game.set_tag("Event", "Rusty Pigeon interactive game");
+    game.set_tag("Date", &today());
+    let (white, black) = match (auto_play, player_play_as_black) {
+        (true, _) => ("Rusty Pigeon", "Rusty Pigeon"),
+        (false, true) => ("Rusty Pigeon", "Player"),
+        (false, false) => ("Player", "Rusty Pigeon"),
+    };
+    game.set_tag("White", white);
+    game.set_tag("Black", black);
     println!("{}", engine);
+    let mut last_move = String::new();
     loop {
+        if let Some(outcome) = engine.game_status() {
+            println!("{outcome}. Result: {}", outcome.result());
+            game.result = String::from(outcome.result());
+            // The PGN standard only knows "normal" for games over on the board,
+            // the details go in a comment after the last move
+            game.set_tag("Termination", "normal");
+            if let Some(last) = game.moves.last_mut() {
+                let comment = match last.comment.take() {
+                    Some(comment) => format!("{comment}. {outcome}"),
+                    None => format!("{outcome}"),
+                };
+                last.comment = Some(comment);
+            }
+            save_game(&game, pgn_file);
+            exit(0);
+        }
         let is_rusty_turn = match engine.position.side_to_move.0 {
             Side::WHITE => player_play_as_black,
             Side::BLACK => !player_play_as_black,
@@ -118,26 +263,82 @@ fn loop_game(fen: &str, player_play_as_black: bool, auto_play: bool) {
             let moves = MoveGenerator::get_ordered_moves_by_kind(&mut engine, MoveGenKind::All);
             let mut s = String::new();
             println!("Your move: ");
-            stdin().read_line(&mut s).expect("nope");
-            if !moves.iter().any(|m| format!("{m}") == s.trim()) {
-                println!("Invalid move, try again.");
-                continue;
+            let read = stdin().read_line(&mut s).expect("nope");
+            if read == 0 || s.trim() == "quit" {
+                game.set_tag("Termination", "abandoned");
+                save_game(&game, pgn_file);
+                exit(0);
             }
-            engine.apply_algebraic_move(&s);
+            // Both long algebraic ("g1f3") and SAN ("Nf3") are fine
+            let player_move = match moves
+                .iter()
+                .find(|m| format!("{m}").eq_ignore_ascii_case(s.trim()))
+            {
+                Some(m) => m.clone(),
+                None => match engine.position.parse_san(s.trim()) {
+                    Ok(m) => m,
+                    Err(error) => {
+                        println!("{error}, try again.");
+                        continue;
+                    }
+                },
+            };
+            last_move = engine.position.san_line(slice::from_ref(&player_move));
+            engine.apply_move(&player_move);
+            game.push_move(player_move, None, None);
         } else {
             println!("Rusty Pigeon is thinking...");
             Evaluate::search(&mut engine, GoOptions::movetime(5000), None);
-            engine.apply_move(&engine.current_best_move.clone().unwrap());
+            if let Some(best_move) = engine.current_best_move.clone() {
+                let expected_line = engine.position.san_line(&engine.current_pv);
+                last_move = format!(
+                    "{} (expected line: {expected_line})",
+                    engine.position.san_line(slice::from_ref(&best_move)),
+                );
+                engine.apply_move(&best_move);
+                let comment = Some(format!("expected line: {expected_line}"));
+                game.push_move(best_move, comment, engine.current_score);
+            }
         }
         clear_terminal();
         println!("{}", engine);
+        println!("Last move: {last_move}");
         println!();
     }
 }
 
+// Append the game to the pgn file, so the same file can collect more games
+fn save_game(game: &Game, pgn_file: &str) {
+    let saved = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(pgn_file)
+        .and_then(|mut file| writeln!(file, "{}", game.to_pgn()));
+    match saved {
+        Ok(_) => println!("Game saved to {pgn_file}"),
+        Err(error) => println!("Could not save the game to {pgn_file}: {error}"),
+    }
+}
+
+// Today's date as written in a PGN Date tag (UTC)
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64 / 86400;
+    // Days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
 // Check if the engine correctly evaluates possible moves, printing its performance in doing so
 fn do_perft(fen: &str, depth: u8, show_moves: bool, parallel: bool) {
-    let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+    let mut sut = Engine::from_position(parse_fen_or_exit(fen));
     let start = Instant::now();
     let result = perft(&mut sut, depth, true, show_moves, parallel);
     let duration = start.elapsed();
@@ -145,6 +346,191 @@ fn do_perft(fen: &str, depth: u8, show_moves: bool, parallel: bool) {
     exit(0);
 }
 
+// Search every position of an EPD test suite, reporting which ones are solved
+fn do_epd(file: &str, movetime: Option<isize>) {
+    let reader = match File::open(file) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => {
+            println!("Could not open {file}: {error}");
+            exit(1);
+        }
+    };
+    let (mut total, mut solved, mut invalid) = (0, 0, 0);
+    let (mut points, mut max_points) = (0, 0);
+    let mut time_to_solution_ms = 0;
+    // Allocated once, every position is searched from an empty table
+    let shared = Arc::new(SharedSearch::default());
+    for (index, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                println!("line {}: could not read, {error}", index + 1);
+                invalid += 1;
+                continue;
+            }
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let epd = match FenParser::try_parse_epd(&line) {
+            Ok(epd) => epd,
+            Err(error) => {
+                println!("line {}: invalid EPD, {error}", index + 1);
+                invalid += 1;
+                continue;
+            }
+        };
+        let id = epd.id().map_or(format!("line {}", index + 1), String::from);
+        if !epd.has_solution() {
+            println!("{id}: skipped, no bm or am operation");
+            invalid += 1;
+            continue;
+        }
+
+        shared.transposition_table.clear();
+        let mut engine = Engine {
+            position: epd.position.clone(),
+            ..Engine::with_shared(shared.clone())
+        };
+        engine.is_quiet = true;
+        let options = match (movetime, epd.acd()) {
+            (None, Some(depth)) => GoOptions::depth(depth),
+            _ => GoOptions::movetime(movetime.unwrap_or(1000)),
+        };
+        Evaluate::search(&mut engine, options, None);
+        total += 1;
+        let Some(found) = engine.current_best_move.clone() else {
+            println!("{id}: failed, no move found");
+            continue;
+        };
+
+        let san = found.to_san(&epd.position);
+        let mut report = if epd.is_solved_by(&found) {
+            solved += 1;
+            time_to_solution_ms += engine.current_best_move_ms;
+            format!("{id}: solved, {san} found in {} ms", engine.current_best_move_ms)
+        } else {
+            let mut expected = vec![];
+            if let Some(best_moves) = epd.operation("bm") {
+                expected.push(format!("expected {}", best_moves.join(" ")));
+            }
+            if let Some(avoid_moves) = epd.operation("am") {
+                expected.push(format!("avoid {}", avoid_moves.join(" ")));
+            }
+            format!("{id}: failed, played {san}, {}", expected.join(", "))
+        };
+        if let (Some(move_points), Some(position_max)) = (epd.points(&found), epd.max_points()) {
+            points += move_points;
+            max_points += position_max;
+            report.push_str(&format!(" ({move_points}/{position_max} points)"));
+        }
+        println!("{report}");
+    }
+
+    println!();
+    println!("Solved {solved}/{total}");
+    if let Some(average_ms) = time_to_solution_ms.checked_div(solved) {
+        println!("Average time to solution: {average_ms} ms");
+    }
+    if max_points > 0 {
+        println!("Points: {points}/{max_points}");
+    }
+    if invalid > 0 {
+        println!("Invalid positions: {invalid}");
+    }
+    exit(0);
+}
+
+// Write a Polyglot book with the moves played in a PGN file
+fn do_book_build(
+    pgn: &str,
+    out: &str,
+    max_ply: usize,
+    min_games: usize,
+    min_rating: Option<usize>,
+    merge: Option<String>,
+) {
+    let reader = match PgnReader::open(pgn) {
+        Ok(reader) => reader,
+        Err(error) => {
+            println!("Could not open {pgn}: {error}");
+            exit(1);
+        }
+    };
+    // Read before writing, --merge can be the book being written
+    let merged = merge.map(|file| match Book::open(&file) {
+        Ok(book) => book,
+        Err(error) => {
+            println!("Could not read the book {file}: {error}");
+            exit(1);
+        }
+    });
+
+    let mut builder = BookBuilder::new(max_ply, min_games);
+    builder.min_rating = min_rating;
+    let mut invalid = 0;
+    for game in reader {
+        match game {
+            Ok(game) => {
+                builder.add_game(&game);
+            }
+            Err(error) => {
+                println!("{error}");
+                invalid += 1;
+            }
+        }
+    }
+
+    let mut book = builder.build();
+    if let Some(merged) = merged {
+        book = book.merge(&merged);
+    }
+    if let Err(error) = fs::write(out, book.to_bytes()) {
+        println!("Could not write the book to {out}: {error}");
+        exit(1);
+    }
+    println!("Games: {} added, {} left out, {invalid} invalid", builder.games, builder.skipped);
+    println!("Wrote {} entries to {out}", book.len());
+    exit(0);
+}
+
+// Positions for the benchmark, from the opening to the endgame
+const BENCH_POSITIONS: [&str; 8] = [
+    START_POS,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8",
+    "8/5pk1/6p1/1p5p/1P1R3P/6P1/5PK1/3r4 w - - 0 40",
+];
+
+// Search the benchmark positions at a fixed depth, the total node count is the signature
+// of the search: it only changes when pruning, reductions or move ordering do
+fn do_bench(depth: usize) {
+    let start = Instant::now();
+    let mut total_nodes = 0;
+    // Cleared before every position, so the count doesn't depend on the ones before it
+    let shared = Arc::new(SharedSearch::default());
+    for (index, fen) in BENCH_POSITIONS.iter().enumerate() {
+        shared.transposition_table.clear();
+        let mut engine = Engine {
+            position: FenParser::fen_to_position(fen),
+            ..Engine::with_shared(shared.clone())
+        };
+        engine.is_quiet = true;
+        Evaluate::search(&mut engine, GoOptions::depth(depth), None);
+        total_nodes += engine.nodes;
+        println!("Position {}: {} nodes", index + 1, engine.nodes);
+    }
+    let duration = start.elapsed();
+    let nps = total_nodes as u128 * 1000 / duration.as_millis().max(1);
+    println!();
+    println!("Total: {total_nodes} nodes in {} ms, {nps} nps", duration.as_millis());
+    exit(0);
+}
+
 // Checks that the move generation is bug free.
 // To be run once every time there are some big changes in the engine
 fn check() {
@@ -1,8 +1,16 @@
-use crate::movegen::generator::{MoveGenerator, MoveGenKind, MoveInfo};
+use crate::{
+    movegen::generator::{MoveGenerator, MoveGenKind, MoveInfo},
+    pgn::reader::{PgnError, PgnErrorKind},
+};
 
 use super::{
+    epd::EpdError,
+    fen::FenError,
     models::{Castling, Engine, Move, Piece, PiecePosition, Position, Side, Square},
+    outcome::GameOutcome,
+    san::SanError,
     utils::square_to_algebraic,
+    validate::PositionError,
 };
 use core::fmt;
 
@@ -11,7 +19,7 @@ impl fmt::Display for Engine {
         let moves = MoveGenerator::get_ordered_moves_by_kind(&mut self.clone(), MoveGenKind::All);
         let mut available_moves = format!("\nAvailable moves ({}):\n", moves.len());
         for single_move in moves.iter() {
-            available_moves.push_str(&format!("{single_move} "));
+            available_moves.push_str(&format!("{} ", single_move.to_san(&self.position)));
         }
         write!(
             f,
@@ -28,11 +36,12 @@ impl fmt::Display for Position {
         let castling = get_castling_info(&self.state.castling);
         let moves = get_move(self.half_move_number);
         let en_passant = get_en_passat(&self.state.en_passant);
-        let since_last_capture = get_since_last_capture(self.state.since_last_capture);
+        let halfmove_clock = get_halfmove_clock(self.state.halfmove_clock);
         let zobrist = format!("Zobrist hash: {:#}", self.zobrist.hash);
+        let fen = format!("FEN: {}", self.to_fen());
         write!(
             f,
-            "{board}\n{side}\n{moves}\t{since_last_capture}\n{castling}\t{en_passant}\n{zobrist}\n"
+            "{board}\n{side}\n{moves}\t{halfmove_clock}\n{castling}\t{en_passant}\n{zobrist}\n{fen}\n"
         )
     }
 }
@@ -106,6 +115,98 @@ impl fmt::Display for MoveInfo {
     }
 }
 
+impl fmt::Display for GameOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameOutcome::Checkmate(winner) => write!(f, "Checkmate, {} wins", winner),
+            GameOutcome::Stalemate => write!(f, "Draw by stalemate"),
+            GameOutcome::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+            GameOutcome::FiftyMoveRule => write!(f, "Draw by the fifty-move rule"),
+            GameOutcome::ThreefoldRepetition => write!(f, "Draw by threefold repetition"),
+        }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {}", field),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::InvalidRankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::InvalidRankLength(rank) => write!(f, "rank {} is not 8 squares long", rank),
+            FenError::InvalidSide(side) => write!(f, "invalid side to move '{}'", side),
+            FenError::InvalidCastling(castling) => write!(f, "invalid castling '{}'", castling),
+            FenError::InvalidEnPassant(square) => write!(f, "invalid en passant square '{}'", square),
+            FenError::InvalidClock(clock) => write!(f, "invalid move counter '{}'", clock),
+            FenError::InvalidPosition(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::InvalidFen(error) => write!(f, "{}", error),
+            EpdError::InvalidOperation(operations) => write!(f, "invalid operations '{}'", operations),
+            EpdError::InvalidMove(error) => write!(f, "{}", error),
+            EpdError::InvalidNumber(number) => write!(f, "invalid number '{}'", number),
+        }
+    }
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::MissingKing(side) => write!(f, "{} has no king", side),
+            PositionError::TooManyKings(side) => write!(f, "{} has more than one king", side),
+            PositionError::PawnsOnBackRank => write!(f, "pawns on the first or last rank"),
+            PositionError::OverlappingPieces => write!(f, "more than one piece on the same square"),
+            PositionError::InvalidCastlingRights(castling) => write!(
+                f,
+                "castling {} without the king and rook on their starting squares",
+                castling
+            ),
+            PositionError::InvalidEnPassant(square) => {
+                write!(f, "no pawn can be captured en passant on {}", square)
+            }
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::InvalidSyntax(san) => write!(f, "'{}' is not a valid move", san),
+            SanError::IllegalMove(san) => write!(f, "'{}' is not a legal move", san),
+            SanError::AmbiguousMove(san) => write!(f, "'{}' is ambiguous", san),
+        }
+    }
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "game at line {}: {}", self.line, self.kind)
+    }
+}
+
+impl fmt::Display for PgnErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnErrorKind::Io(error) => write!(f, "{}", error),
+            PgnErrorKind::InvalidTag(line) => write!(f, "invalid tag pair '{}'", line),
+            PgnErrorKind::InvalidFen(error) => write!(f, "invalid FEN tag, {}", error),
+            PgnErrorKind::InvalidMove(ply, error) => write!(f, "{} at ply {}", error, ply),
+            PgnErrorKind::NullMove(ply) => write!(f, "null move at ply {}", ply),
+            PgnErrorKind::UnterminatedComment => write!(f, "comment is never closed"),
+            PgnErrorKind::UnbalancedVariation => write!(f, "unbalanced parentheses"),
+            PgnErrorKind::MisplacedAnnotation => {
+                write!(f, "variation or annotation before the first move")
+            }
+        }
+    }
+}
+
 fn get_board(positon: &PiecePosition) -> String {
     let mut board = get_empty_board();
     display_pieces(positon.pieces[Side::WHITE][Piece::PAWN].0, &mut board, "P");
@@ -218,8 +319,8 @@ fn get_en_passat(en_passant: &Square) -> String {
     format!("En passant: {}", en_passant)
 }
 
-fn get_since_last_capture(since_last_capture: usize) -> String {
-    format!("Since capture: {}", since_last_capture)
+fn get_halfmove_clock(halfmove_clock: usize) -> String {
+    format!("Halfmove clock: {}", halfmove_clock)
 }
 
 pub fn print_bitboard(bitboard: u64) -> String {
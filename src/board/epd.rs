@@ -0,0 +1,198 @@
+use crate::movegen::generator::MoveInfo;
+
+use super::{
+    fen::{FenError, FenParser},
+    models::Position,
+    san::SanError,
+};
+
+// A position from an EPD line, with its operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Epd {
+    pub position: Position,
+    // Opcodes with their operands, in the order they appear. Quotes are removed from strings
+    pub operations: Vec<(String, Vec<String>)>,
+    // From the "bm" operation
+    pub best_moves: Vec<MoveInfo>,
+    // From the "am" operation
+    pub avoid_moves: Vec<MoveInfo>,
+}
+
+// Everything that can go wrong while parsing an EPD line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    InvalidFen(FenError),
+    // An operation without opcode, or with a string that is never closed
+    InvalidOperation(String),
+    // A move of "bm" or "am" that is not legal in the position
+    InvalidMove(SanError),
+    InvalidNumber(String),
+}
+impl std::error::Error for EpdError {}
+
+impl FenParser {
+    // Parse an EPD line: the first four FEN fields followed by operations like
+    // bm Nf3; id "WAC.001"; The "hmvc" and "fmvn" operations give the move counters
+    pub fn try_parse_epd(epd: &str) -> Result<Epd, EpdError> {
+        let mut rest = epd.trim();
+        let mut fields = vec![];
+        while fields.len() < 4 && !rest.is_empty() {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        let operations = Self::parse_operations(rest)?;
+
+        let counter = |opcode: &str, default: usize| -> Result<usize, EpdError> {
+            match operations.iter().find(|(name, _)| name == opcode) {
+                Some((_, operands)) => {
+                    let value = operands.first().map(String::as_str).unwrap_or_default();
+                    value
+                        .parse()
+                        .map_err(|_| EpdError::InvalidNumber(String::from(value)))
+                }
+                None => Ok(default),
+            }
+        };
+        let mut fen = fields.join(" ");
+        if fields.len() == 4 {
+            fen.push_str(&format!(" {} {}", counter("hmvc", 0)?, counter("fmvn", 1)?));
+        }
+        let position = FenParser::try_parse(&fen).map_err(EpdError::InvalidFen)?;
+
+        let parse_moves = |opcode: &str| -> Result<Vec<MoveInfo>, EpdError> {
+            operations
+                .iter()
+                .filter(|(name, _)| name == opcode)
+                .flat_map(|(_, operands)| operands.iter())
+                .map(|san| position.parse_san(san).map_err(EpdError::InvalidMove))
+                .collect()
+        };
+        let best_moves = parse_moves("bm")?;
+        let avoid_moves = parse_moves("am")?;
+
+        Ok(Epd {
+            position,
+            operations,
+            best_moves,
+            avoid_moves,
+        })
+    }
+
+    // Operations are separated by semicolons, operands by spaces unless they are quoted
+    fn parse_operations(field: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+        let mut operations = vec![];
+        let mut tokens: Vec<String> = vec![];
+        let mut chars = field.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => {
+                    if !tokens.is_empty() {
+                        let opcode = tokens.remove(0);
+                        operations.push((opcode, std::mem::take(&mut tokens)));
+                    }
+                }
+                '"' => {
+                    let mut string = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => string.push(c),
+                            None => return Err(EpdError::InvalidOperation(String::from(field))),
+                        }
+                    }
+                    if tokens.is_empty() {
+                        return Err(EpdError::InvalidOperation(String::from(field)));
+                    }
+                    tokens.push(string);
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut token = String::from(c);
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';' && *c != '"') {
+                        token.push(c);
+                    }
+                    tokens.push(token);
+                }
+            }
+        }
+        // The last semicolon is sometimes left out
+        if !tokens.is_empty() {
+            let opcode = tokens.remove(0);
+            operations.push((opcode, tokens));
+        }
+        Ok(operations)
+    }
+}
+
+impl Epd {
+    // The operands of the first operation with this opcode
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id")
+            .and_then(|operands| operands.first())
+            .map(String::as_str)
+    }
+
+    // Analysis count depth, the depth the solution is expected at
+    pub fn acd(&self) -> Option<usize> {
+        self.operation("acd")
+            .and_then(|operands| operands.first())
+            .and_then(|depth| depth.parse().ok())
+    }
+
+    pub fn c0(&self) -> Option<&str> {
+        self.operation("c0")
+            .and_then(|operands| operands.first())
+            .map(String::as_str)
+    }
+
+    // Check if playing the move solves the position:
+    // it must be one of the best moves and none of the moves to avoid.
+    // Without either there is nothing to solve
+    pub fn is_solved_by(&self, m: &MoveInfo) -> bool {
+        if !self.has_solution() {
+            return false;
+        }
+        (self.best_moves.is_empty() || self.best_moves.contains(m)) && !self.avoid_moves.contains(m)
+    }
+
+    // If there is a bm or am operation to check the move against
+    pub fn has_solution(&self) -> bool {
+        !self.best_moves.is_empty() || !self.avoid_moves.is_empty()
+    }
+
+    // Points for the move in STS style suites, where c0 is like "Nf3=10, e4=5, d8=Q=3".
+    // None if c0 doesn't list the points
+    pub fn points(&self, m: &MoveInfo) -> Option<usize> {
+        let points = self.c0_points()?;
+        Some(
+            points
+                .iter()
+                .find(|(scored_move, _)| scored_move == m)
+                .map_or(0, |(_, points)| *points),
+        )
+    }
+
+    // The most points a move can get, None if c0 doesn't list the points
+    pub fn max_points(&self) -> Option<usize> {
+        self.c0_points()?.iter().map(|(_, points)| *points).max()
+    }
+
+    fn c0_points(&self) -> Option<Vec<(MoveInfo, usize)>> {
+        self.c0()?
+            .split(',')
+            .map(|entry| {
+                let (san, points) = entry.trim().rsplit_once('=')?;
+                let m = self.position.parse_san(san).ok()?;
+                Some((m, points.trim().parse().ok()?))
+            })
+            .collect()
+    }
+}
@@ -1,131 +1,313 @@
-use std::sync::Arc;
-
 use super::{
     models::{BoardState, Castling, Piece, Position, Side, Square},
     utils::algebraic_to_square,
+    validate::PositionError,
 };
 
+// Indexed by side and piece, same order as the Piece constants
+const FEN_PIECES: [[char; 6]; 2] = [
+    ['P', 'B', 'N', 'R', 'Q', 'K'],
+    ['p', 'b', 'n', 'r', 'q', 'k'],
+];
+
 pub struct FenParser;
 
+// Everything that can go wrong while parsing a FEN string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    // A mandatory field is not there at all
+    MissingField(&'static str),
+    // Unknown character in the pieces position
+    InvalidPiece(char),
+    // The pieces position doesn't have 8 ranks
+    InvalidRankCount(usize),
+    // The given rank (1-8) doesn't describe exactly 8 squares
+    InvalidRankLength(usize),
+    InvalidSide(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidClock(String),
+    // The FEN is well formed, but the position is impossible
+    InvalidPosition(PositionError),
+}
+impl std::error::Error for FenError {}
+
 impl FenParser {
+    // Parse a FEN string we know is valid, panics otherwise
     pub fn fen_to_position(fen: &str) -> Position {
+        match Self::try_parse(fen) {
+            Ok(position) => position,
+            Err(error) => panic!("Invalid FEN \"{fen}\": {error}"),
+        }
+    }
+
+    pub fn try_parse(fen: &str) -> Result<Position, FenError> {
+        Self::parse(fen, false)
+    }
+
+    // Parse a Chess960 position. Castling rights can be given as X-FEN, where K and Q are the
+    // outermost rooks and a file letter picks an inner one, or as Shredder-FEN, file letters only
+    pub fn try_parse_chess960(fen: &str) -> Result<Position, FenError> {
+        Self::parse(fen, true)
+    }
+
+    fn parse(fen: &str, is_chess960: bool) -> Result<Position, FenError> {
         let mut position = Position::empty();
-        let mut fen = fen.split(' ');
+        position.is_chess960 = is_chess960;
+        let mut fen = fen.split_whitespace();
 
         // Pieces position
-        Self::parse_piece_position(&mut fen, &mut position);
+        let pieces = fen.next().ok_or(FenError::MissingField("pieces position"))?;
+        Self::parse_piece_position(pieces, &mut position)?;
 
         // Side to move
-        for c in fen.next().unwrap().chars() {
-            position.side_to_move = if c == 'w' {
-                Side(Side::WHITE)
-            } else {
-                Side(Side::BLACK)
-            }
-        }
+        position.side_to_move = match fen.next() {
+            Some("w") => Side(Side::WHITE),
+            Some("b") => Side(Side::BLACK),
+            Some(side) => return Err(FenError::InvalidSide(String::from(side))),
+            None => return Err(FenError::MissingField("side to move")),
+        };
 
-        let mut castling = Castling(Castling::NO_CASTLING);
         // Castling
-        for c in fen.next().unwrap().chars() {
-            match c {
-                'Q' => castling.0 |= Castling::WHITE_QUEEN_SIDE,
-                'K' => castling.0 |= Castling::WHITE_KING_SIDE,
-                'q' => castling.0 |= Castling::BLACK_QUEEN_SIDE,
-                'k' => castling.0 |= Castling::BLACK_KING_SIDE,
-                _ => {}
-            }
-        }
+        let castling = Self::parse_castling(
+            fen.next().ok_or(FenError::MissingField("castling"))?,
+            &mut position,
+        )?;
 
-        let mut en_passant = Square(Square::NONE);
         // En passant
-        let square = fen.next().unwrap();
-        if square != "-" {
-            en_passant = Square(algebraic_to_square(square));
-        }
+        let en_passant = Self::parse_en_passant(
+            fen.next().ok_or(FenError::MissingField("en passant"))?,
+            &position.side_to_move,
+        )?;
 
-        // Moves since last capture
-        let since_last_capture: usize = fen.next().unwrap().parse().unwrap();
+        // Halfmove clock and fullmove number, both can be omitted
+        let halfmove_clock = fen.next().map_or(Ok(0), Self::parse_clock)?;
+        let full_moves = fen.next().map_or(Ok(1), Self::parse_clock)?;
 
         // Assign state
-        position.state = Arc::new(BoardState {
+        position.state = BoardState {
             castling,
             en_passant,
-            since_last_capture,
-            prev: None
-        });
+            halfmove_clock,
+        };
 
-        // Half moves
-        let full_moves: usize = fen.next().unwrap().parse().unwrap();
-        position.half_move_number = (full_moves - 1) * 2;
+        // Half moves (some tools write 0 as the first fullmove number)
+        position.half_move_number = full_moves.saturating_sub(1) * 2;
         if position.side_to_move == Side(Side::BLACK) {
             position.half_move_number += 1;
         }
-        position.zobrist = Arc::new(position.init_zobrist_key());
+        position.zobrist = position.init_zobrist_key();
+
+        position.validate().map_err(FenError::InvalidPosition)?;
 
-        position
+        Ok(position)
     }
 
     // Parse the piece position from the fen string
-    fn parse_piece_position(fen: &mut std::str::Split<char>, position: &mut Position) {
-        let mut i: i32 = 64 - 8;
-        for c in fen.next().unwrap().chars() {
-            match c {
-                '/' => i -= 16,
-                'r' => {
-                    position.set_piece(1u64 << i, Side::BLACK, Piece::ROOK);
-                    i += 1
-                }
-                'R' => {
-                    position.set_piece(1u64 << i, Side::WHITE, Piece::ROOK);
-                    i += 1
-                }
-                'b' => {
-                    position.set_piece(1u64 << i, Side::BLACK, Piece::BISHOP);
-                    i += 1
-                }
-                'B' => {
-                    position.set_piece(1u64 << i, Side::WHITE, Piece::BISHOP);
-                    i += 1
-                }
-                'n' => {
-                    position.set_piece(1u64 << i, Side::BLACK, Piece::KNIGHT);
-                    i += 1
+    fn parse_piece_position(pieces: &str, position: &mut Position) -> Result<(), FenError> {
+        let ranks: Vec<&str> = pieces.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::InvalidRankCount(ranks.len()));
+        }
+        for (index, rank) in ranks.iter().enumerate() {
+            let row = 7 - index;
+            let mut column = 0;
+            for c in rank.chars() {
+                if let Some(n) = c.to_digit(10).filter(|n| (1..=8).contains(n)) {
+                    column += n as usize;
+                    continue;
                 }
-                'N' => {
-                    position.set_piece(1u64 << i, Side::WHITE, Piece::KNIGHT);
-                    i += 1
+                let (side, piece) = FEN_PIECES
+                    .iter()
+                    .enumerate()
+                    .find_map(|(side, pieces)| {
+                        pieces.iter().position(|p| *p == c).map(|piece| (side, piece))
+                    })
+                    .ok_or(FenError::InvalidPiece(c))?;
+                if column >= 8 {
+                    return Err(FenError::InvalidRankLength(row + 1));
                 }
-                'q' => {
-                    position.set_piece(1u64 << i, Side::BLACK, Piece::QUEEN);
-                    i += 1
+                position.set_piece(1u64 << (row * 8 + column), side, piece);
+                column += 1;
+            }
+            if column != 8 {
+                return Err(FenError::InvalidRankLength(row + 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_castling(field: &str, position: &mut Position) -> Result<Castling, FenError> {
+        let mut castling = Castling(Castling::NO_CASTLING);
+        if field == "-" {
+            return Ok(castling);
+        }
+        for c in field.chars() {
+            let side = if c.is_ascii_uppercase() {
+                Side::WHITE
+            } else {
+                Side::BLACK
+            };
+            let back_rank: u64 = if side == Side::WHITE { 0xFF } else { 0xFF << 56 };
+            let king = position.board.pieces[side][Piece::KING].0 & back_rank;
+            // The rook of a file letter, in Chess960 only
+            let file_rook = match c {
+                'A'..='H' | 'a'..='h' if position.is_chess960 => {
+                    let file = c.to_ascii_lowercase() as u8 - b'a';
+                    Some(back_rank & (0x0101010101010101 << file))
                 }
-                'Q' => {
-                    position.set_piece(1u64 << i, Side::WHITE, Piece::QUEEN);
-                    i += 1
+                _ => None,
+            };
+            let flag = match (c, file_rook) {
+                ('Q', _) => Castling::WHITE_QUEEN_SIDE,
+                ('K', _) => Castling::WHITE_KING_SIDE,
+                ('q', _) => Castling::BLACK_QUEEN_SIDE,
+                ('k', _) => Castling::BLACK_KING_SIDE,
+                (_, Some(rook)) if rook > king => Castling::king_side(side),
+                (_, Some(_)) => Castling::queen_side(side),
+                _ => return Err(FenError::InvalidCastling(String::from(field))),
+            };
+            if castling.0 & flag > 0 {
+                return Err(FenError::InvalidCastling(String::from(field)));
+            }
+            castling.0 |= flag;
+
+            // Where the king and the rook start from, the validation checks they are there
+            if position.is_chess960 && king != 0 {
+                position.castling_kings[side] = king;
+                if let Some(rook) = file_rook.or_else(|| position.outermost_rook(flag)) {
+                    position.castling_rooks[Castling::index(flag)] = rook;
                 }
-                'k' => {
-                    position.set_piece(1u64 << i, Side::BLACK, Piece::KING);
-                    i += 1
+            }
+        }
+        Ok(castling)
+    }
+
+    fn parse_en_passant(field: &str, side_to_move: &Side) -> Result<Square, FenError> {
+        if field == "-" {
+            return Ok(Square(Square::NONE));
+        }
+        // The en passant square is behind a pawn that just moved two squares
+        let expected_row = if side_to_move.0 == Side::WHITE { '6' } else { '3' };
+        let mut chars = field.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('a'..='h'), Some(row), None) if row == expected_row => {
+                Ok(Square(algebraic_to_square(field)))
+            }
+            _ => Err(FenError::InvalidEnPassant(String::from(field))),
+        }
+    }
+
+    fn parse_clock(field: &str) -> Result<usize, FenError> {
+        field
+            .parse()
+            .map_err(|_| FenError::InvalidClock(String::from(field)))
+    }
+}
+
+impl Position {
+    // Serialize the position as a FEN string, the inverse of FenParser::fen_to_position
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        // Pieces position
+        for row in (0..8).rev() {
+            let mut empty_squares = 0;
+            for column in 0..8 {
+                let square = 1u64 << (row * 8 + column);
+                match self.get_fen_piece(square) {
+                    Some(c) => {
+                        if empty_squares > 0 {
+                            fen.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        fen.push(c);
+                    }
+                    None => empty_squares += 1,
                 }
-                'K' => {
-                    position.set_piece(1u64 << i, Side::WHITE, Piece::KING);
-                    i += 1
+            }
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if row > 0 {
+                fen.push('/');
+            }
+        }
+
+        // Side to move
+        fen.push_str(if self.side_to_move.0 == Side::WHITE {
+            " w "
+        } else {
+            " b "
+        });
+
+        // Castling
+        let castling = self.state.castling.0;
+        if castling == Castling::NO_CASTLING {
+            fen.push('-');
+        } else {
+            for (flag, c) in [
+                (Castling::WHITE_KING_SIDE, 'K'),
+                (Castling::WHITE_QUEEN_SIDE, 'Q'),
+                (Castling::BLACK_KING_SIDE, 'k'),
+                (Castling::BLACK_QUEEN_SIDE, 'q'),
+            ] {
+                if castling & flag == 0 {
+                    continue;
                 }
-                'p' => {
-                    position.set_piece(1u64 << i, Side::BLACK, Piece::PAWN);
-                    i += 1
+                // X-FEN: the file of the rook when it's not the outermost one
+                let rook = self.castling_rooks[Castling::index(flag)];
+                if self.is_chess960 && self.outermost_rook(flag) != Some(rook) {
+                    let file = (b'a' + (rook.trailing_zeros() % 8) as u8) as char;
+                    fen.push(if c.is_ascii_uppercase() {
+                        file.to_ascii_uppercase()
+                    } else {
+                        file
+                    });
+                } else {
+                    fen.push(c);
                 }
-                'P' => {
-                    position.set_piece(1u64 << i, Side::WHITE, Piece::PAWN);
-                    i += 1
+            }
+        }
+
+        // En passant, halfmove clock and full moves
+        let full_moves = self.half_move_number / 2 + 1;
+        fen.push_str(&format!(
+            " {} {} {}",
+            self.state.en_passant, self.state.halfmove_clock, full_moves
+        ));
+
+        fen
+    }
+
+    fn get_fen_piece(&self, square: u64) -> Option<char> {
+        for side in [Side::WHITE, Side::BLACK] {
+            for (piece, piece_board) in self.board.pieces[side].iter().enumerate() {
+                if piece_board.0 & square != 0 {
+                    return Some(FEN_PIECES[side][piece]);
                 }
-                other => match other.to_digit(10) {
-                    Some(n) => {
-                        i += n as i32;
-                    }
-                    None => { /* Ignore */ }
-                },
             }
         }
+        None
+    }
+    // The rook furthest from the king on the side of a castling right, what K and Q mean in X-FEN
+    fn outermost_rook(&self, castling: u8) -> Option<u64> {
+        let side = Castling::side(castling);
+        let back_rank = if side == Side::WHITE { 0xFF } else { 0xFF << 56 };
+        let king = self.board.pieces[side][Piece::KING].0 & back_rank;
+        let rooks = self.board.pieces[side][Piece::ROOK].0 & back_rank;
+        if king == 0 {
+            return None;
+        }
+        let rooks = if castling & (Castling::WHITE_KING_SIDE | Castling::BLACK_KING_SIDE) > 0 {
+            rooks & !(king | (king - 1))
+        } else {
+            rooks & (king - 1)
+        };
+        match rooks {
+            0 => None,
+            _ if rooks > king => Some(1u64 << (63 - rooks.leading_zeros())),
+            _ => Some(1u64 << rooks.trailing_zeros()),
+        }
     }
 }
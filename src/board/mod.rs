@@ -1,6 +1,11 @@
 pub mod models;
 pub mod display;
+pub mod epd;
 pub mod fen;
+pub mod outcome;
+pub mod polyglot;
+pub mod san;
 pub mod test;
 pub mod utils;
+pub mod validate;
 pub mod zobrist;
\ No newline at end of file
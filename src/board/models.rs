@@ -1,42 +1,122 @@
-use std::sync::Arc;
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    },
+};
 
 use rustc_hash::FxHashMap;
 
-use crate::movegen::generator::MoveInfo;
+use crate::{
+    book::reader::BookSettings,
+    constants::MAX_PLY,
+    evaluate::{
+        search_context::SearchContext, search_params::SearchParams,
+        transposition::TranspositionTable,
+    },
+    movegen::generator::MoveInfo,
+    syzygy::probe::SyzygySettings,
+};
 
 use super::{
     utils::algebraic_to_move,
     zobrist::{ZobristHashes, ZobristValue},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct Engine {
     pub position: Position,
     pub current_best_move: Option<MoveInfo>,
+    // The principal variation of the last search, best move first
+    pub current_pv: Vec<MoveInfo>,
+    // Score of the last search in centipawns, from white's point of view
+    pub current_score: Option<isize>,
+    // Milliseconds from the start of the last search to when its best move was found
+    pub current_best_move_ms: u128,
     pub is_searching: bool,
     pub is_configuring: bool,
-    pub zobrist_table: FxHashMap<u64, [Option<(isize, isize, isize)>; 10]>,
+    // Don't print the info lines while searching
+    pub is_quiet: bool,
+    // The UCI_Chess960 option, positions are set up with Chess960 castling
+    pub is_chess960: bool,
+    // Nodes visited by this thread in the last search
+    pub nodes: usize,
+    // No null move is tried closer to the root than this ply
+    pub null_move_min_ply: usize,
+    // Move ordering learned by this thread, cleared at every search
+    pub search_context: SearchContext,
+    // Late move reduction and pruning settings, from the UCI options
+    pub search_params: SearchParams,
+    // The opening book, from the OwnBook UCI options
+    pub book: BookSettings,
+    // Endgame tablebases, from the Syzygy UCI options
+    pub syzygy: SyzygySettings,
+    // The UCI Threads option, how many threads search together
+    pub threads: usize,
+    // Shared by the clones of the engine, so they can reuse each other's results
+    pub shared: Arc<SharedSearch>,
     pub zobrist_evaluation_table: FxHashMap<u64, isize>,
 }
 impl Engine {
     pub fn empty() -> Engine {
+        Self::with_shared(Arc::new(SharedSearch::default()))
+    }
+
+    // An empty engine searching into a table that already exists, no need to allocate another
+    pub fn with_shared(shared: Arc<SharedSearch>) -> Engine {
         Engine {
             position: Position::empty(),
             current_best_move: None,
+            current_pv: vec![],
+            current_score: None,
+            current_best_move_ms: 0,
             is_searching: false,
             is_configuring: false,
-            zobrist_table: FxHashMap::default(),
+            is_quiet: false,
+            is_chess960: false,
+            nodes: 0,
+            null_move_min_ply: 0,
+            search_context: SearchContext::new(),
+            search_params: SearchParams::new(),
+            book: BookSettings::new(),
+            syzygy: SyzygySettings::new(),
+            threads: 1,
+            shared,
             zobrist_evaluation_table: FxHashMap::default(),
         }
     }
 
+    // An engine with a table of its own. To search many positions, give them the same table
+    // with `Engine { position, ..Engine::with_shared(shared) }` instead
     pub fn from_position(position: Position) -> Engine {
         Engine {
             position,
+            ..Self::empty()
+        }
+    }
+
+    // A copy of the engine for a helper thread, searching quietly into the same table
+    pub fn helper(&self) -> Engine {
+        Engine {
+            position: self.position.clone(),
             current_best_move: None,
+            current_pv: vec![],
+            current_score: None,
+            current_best_move_ms: 0,
             is_searching: false,
             is_configuring: false,
-            zobrist_table: FxHashMap::default(),
+            is_quiet: true,
+            is_chess960: self.is_chess960,
+            nodes: 0,
+            null_move_min_ply: 0,
+            search_context: SearchContext::new(),
+            search_params: self.search_params.clone(),
+            book: BookSettings::new(),
+            syzygy: self.syzygy.clone(),
+            threads: 1,
+            shared: self.shared.clone(),
             zobrist_evaluation_table: FxHashMap::default(),
         }
     }
@@ -49,18 +129,20 @@ impl Engine {
         self.position.undo_move(m);
     }
 
+    pub fn apply_null_move(&mut self) {
+        self.position.apply_null_move();
+    }
+
+    pub fn undo_null_move(&mut self) {
+        self.position.undo_null_move();
+    }
+
     // Apply a move given as a string in long algebraic notation
     pub fn apply_algebraic_move(&mut self, alg_move: &str) {
         let actual_move = algebraic_to_move(alg_move, &self.position);
         let from_square = match actual_move {
             Move::Normal(from, _) => from,
-            Move::Castle(castling) => match castling {
-                Castling::WHITE_KING_SIDE => Square::E1,
-                Castling::WHITE_QUEEN_SIDE => Square::E1,
-                Castling::BLACK_KING_SIDE => Square::E8,
-                Castling::BLACK_QUEEN_SIDE => Square::E8,
-                _ => Square::NONE,
-            },
+            Move::Castle(castling) => self.position.castling_squares(castling).0,
             Move::Promotion(from, _, _) => from,
             Move::EnPassant(from, _) => from,
         };
@@ -111,6 +193,25 @@ impl Engine {
     }
 }
 
+// What the threads searching the same position share, everything else in Engine is their own
+#[derive(Debug, Default)]
+pub struct SharedSearch {
+    pub transposition_table: TranspositionTable,
+    // Raised when the main thread is done, the helpers stop with it
+    pub stop: AtomicBool,
+    // Positions of the last search found in the Syzygy tables, by all the threads
+    pub tb_hits: AtomicUsize,
+}
+impl SharedSearch {
+    pub fn new(hash_mb: usize) -> SharedSearch {
+        SharedSearch {
+            transposition_table: TranspositionTable::new(hash_mb),
+            stop: AtomicBool::new(false),
+            tb_hits: AtomicUsize::new(0),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Move {
     Normal(u64, u64),
@@ -119,28 +220,111 @@ pub enum Move {
     Promotion(u64, u64, usize),
 }
 
-// The current state of the game
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+// Room in the history for a long game with the deepest search on top of it
+const HISTORY_CAPACITY: usize = 512 + MAX_PLY;
+
+// The current state of the game.
+// Clone, PartialEq and Hash are written by hand to leave the history out of them
+#[derive(Debug)]
 pub struct Position {
     pub board: PiecePosition,
     pub side_to_move: Side,
     pub half_move_number: usize,
-    pub state: Arc<BoardState>,
-    pub zobrist: Arc<ZobristValue>,
+    pub state: BoardState,
+    pub zobrist: ZobristValue,
+    // The state and hash before each move played, the last one on top, so moves can be undone
+    pub history: Vec<(BoardState, u64)>,
     pub zobrist_hashes: ZobristHashes,
+    // Starting squares of the kings, by side, and of the castling rooks, in the
+    // order of Castling::FLAGS. Anywhere on the back rank in Chess960
+    pub castling_kings: [u64; 2],
+    pub castling_rooks: [u64; 4],
+    // Castles are written as the king taking its own rook in long algebraic notation
+    pub is_chess960: bool,
+}
+// Clones keep the room for a search, the history would otherwise be cut to its length
+impl Clone for Position {
+    fn clone(&self) -> Position {
+        let mut history = Vec::with_capacity(HISTORY_CAPACITY.max(self.history.len()));
+        history.extend_from_slice(&self.history);
+        Position {
+            board: self.board.clone(),
+            side_to_move: self.side_to_move.clone(),
+            half_move_number: self.half_move_number,
+            state: self.state.clone(),
+            zobrist: self.zobrist.clone(),
+            history,
+            zobrist_hashes: self.zobrist_hashes.clone(),
+            castling_kings: self.castling_kings,
+            castling_rooks: self.castling_rooks,
+            is_chess960: self.is_chess960,
+        }
+    }
+}
+
+// Two positions are the same whatever moves led to them
+impl PartialEq for Position {
+    fn eq(&self, other: &Position) -> bool {
+        self.board == other.board
+            && self.side_to_move == other.side_to_move
+            && self.half_move_number == other.half_move_number
+            && self.state == other.state
+            && self.zobrist == other.zobrist
+            && self.zobrist_hashes == other.zobrist_hashes
+            && self.castling_kings == other.castling_kings
+            && self.castling_rooks == other.castling_rooks
+            && self.is_chess960 == other.is_chess960
+    }
 }
+impl Eq for Position {}
+
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.board.hash(state);
+        self.side_to_move.hash(state);
+        self.half_move_number.hash(state);
+        self.state.hash(state);
+        self.zobrist.hash(state);
+        self.zobrist_hashes.hash(state);
+        self.castling_kings.hash(state);
+        self.castling_rooks.hash(state);
+        self.is_chess960.hash(state);
+    }
+}
+
 impl Position {
     pub fn empty() -> Position {
         Position {
             board: PiecePosition::empty(),
             side_to_move: Side(Side::WHITE),
             half_move_number: 0,
-            state: Arc::new(BoardState::empty()),
+            state: BoardState::empty(),
+            zobrist: ZobristValue::empty(),
+            history: Vec::with_capacity(HISTORY_CAPACITY),
             zobrist_hashes: ZobristHashes::init(),
-            zobrist: Arc::new(ZobristValue::empty()),
+            castling_kings: [Square::E1, Square::E8],
+            castling_rooks: [Square::H1, Square::A1, Square::H8, Square::A8],
+            is_chess960: false,
         }
     }
 
+    // Squares of a castle: king from, king to, rook from, rook to.
+    // Whatever the starting files, the king ends up on the g or c file and the rook next to it
+    pub fn castling_squares(&self, castling: u8) -> (u64, u64, u64, u64) {
+        let (king_to, rook_to) = match castling {
+            Castling::WHITE_KING_SIDE => (Square::G1, Square::F1),
+            Castling::WHITE_QUEEN_SIDE => (Square::C1, Square::D1),
+            Castling::BLACK_KING_SIDE => (Square::G8, Square::F8),
+            _ => (Square::C8, Square::D8),
+        };
+        (
+            self.castling_kings[Castling::side(castling)],
+            king_to,
+            self.castling_rooks[Castling::index(castling)],
+            rook_to,
+        )
+    }
+
     // Get the side that is not moving in this turn
     pub fn opposite_side(&self) -> usize {
         match self.side_to_move.0 {
@@ -150,6 +334,24 @@ impl Position {
         }
     }
 
+    // Check if the current position has already occurred at least `count` times
+    // (current one included), walking back the zobrist history
+    pub fn is_repetition(&self, count: usize) -> bool {
+        let mut occurrences = 1;
+        // Positions before the last capture or pawn move can't be repeated
+        let plies = self.state.halfmove_clock.min(self.history.len());
+        // Only positions with the same side to move can be the same
+        for ply in (2..=plies).step_by(2) {
+            if occurrences >= count {
+                break;
+            }
+            if self.history[self.history.len() - ply].1 == self.zobrist.hash {
+                occurrences += 1;
+            }
+        }
+        occurrences >= count
+    }
+
     // Set a piece, dumb way to set a square to a piece, only used while parsing FENs
     pub fn set_piece(&mut self, pos: u64, side: usize, piece: usize) {
         self.board.side_pieces[side].0 |= pos;
@@ -201,59 +403,32 @@ impl Position {
                 self.board.pieces[self.opposite_side()][Piece::PAWN].0 |= square_to_populate;
             }
             // Castle
-            Move::Castle(castling) => match castling {
-                Castling::WHITE_KING_SIDE => {
-                    self.set_side_pieces_back_from_move(Square::G1, Square::E1, false);
-                    self.set_side_pieces_back_from_move(Square::F1, Square::H1, false);
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 &= !Square::G1;
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 |= Square::E1;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 &= !Square::F1;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 |= Square::H1;
-                }
-                Castling::WHITE_QUEEN_SIDE => {
-                    self.set_side_pieces_back_from_move(Square::C1, Square::E1, false);
-                    self.set_side_pieces_back_from_move(Square::D1, Square::A1, false);
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 &= !Square::C1;
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 |= Square::E1;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 &= !Square::D1;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 |= Square::A1;
-                }
-                Castling::BLACK_KING_SIDE => {
-                    self.set_side_pieces_back_from_move(Square::G8, Square::E8, false);
-                    self.set_side_pieces_back_from_move(Square::F8, Square::H8, false);
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 &= !Square::G8;
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 |= Square::E8;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 &= !Square::F8;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 |= Square::H8;
-                }
-                Castling::BLACK_QUEEN_SIDE => {
-                    self.set_side_pieces_back_from_move(Square::C8, Square::E8, false);
-                    self.set_side_pieces_back_from_move(Square::D8, Square::A8, false);
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 &= !Square::C8;
-                    self.board.pieces[self.side_to_move.0][Piece::KING].0 |= Square::E8;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 &= !Square::D8;
-                    self.board.pieces[self.side_to_move.0][Piece::ROOK].0 |= Square::A8;
-                }
-                _ => {}
-            },
+            Move::Castle(castling) => {
+                let (king_from, king_to, rook_from, rook_to) = self.castling_squares(castling);
+                // King and rook can swap squares in Chess960, so take both away first
+                self.board.side_pieces[self.side_to_move.0].0 &= !(king_to | rook_to);
+                self.board.side_pieces[self.side_to_move.0].0 |= king_from | rook_from;
+                self.board.pieces[self.side_to_move.0][Piece::KING].0 &= !king_to;
+                self.board.pieces[self.side_to_move.0][Piece::ROOK].0 &= !rook_to;
+                self.board.pieces[self.side_to_move.0][Piece::KING].0 |= king_from;
+                self.board.pieces[self.side_to_move.0][Piece::ROOK].0 |= rook_from;
+            }
         }
-        self.zobrist = Arc::clone(&self.zobrist.prev.as_ref().unwrap());
-        self.state = Arc::clone(&self.state.prev.as_ref().unwrap());
+        self.undo_state();
         self.half_move_number -= 1;
+
+        debug_assert_eq!(
+            self.validate_structure(),
+            Ok(()),
+            "undoing {m} left {}",
+            self.to_fen()
+        );
     }
 
     // Actually apply a move
     pub fn apply_move(&mut self, move_action: &MoveInfo) {
-        let mut new_zobrist = ZobristValue {
-            hash: self.zobrist.hash,
-            prev: Some(Arc::clone(&self.zobrist)),
-        };
-        let mut new_state = BoardState {
-            castling: Castling(self.state.castling.0),
-            en_passant: Square(self.state.en_passant.0),
-            since_last_capture: self.state.since_last_capture,
-            prev: Some(Arc::clone(&self.state)),
-        };
+        let mut new_zobrist = self.zobrist.clone();
+        let mut new_state = self.state.clone();
 
         let own_rooks_square = self.board.pieces[self.side_to_move.0][Piece::ROOK].0;
         let opposite_rooks_square = self.board.pieces[self.opposite_side()][Piece::ROOK].0;
@@ -283,32 +458,20 @@ impl Position {
                 self.move_piece_from_move(from, to, move_action, &mut new_zobrist);
 
                 if !has_king_moved && move_action.piece == Piece::ROOK {
-                    let queen_rook_square = if self.side_to_move.0 == Side::WHITE {
-                        Square::A1
-                    } else {
-                        Square::A8
-                    };
-                    let king_rook_square = if self.side_to_move.0 == Side::WHITE {
-                        Square::H1
-                    } else {
-                        Square::H8
-                    };
+                    let queen_rook_square =
+                        self.castling_rooks[Castling::index(Castling::queen_side(self.side_to_move.0))];
+                    let king_rook_square =
+                        self.castling_rooks[Castling::index(Castling::king_side(self.side_to_move.0))];
 
                     has_queen_rook_moved = (queen_rook_square & own_rooks_square & from) > 0;
 
                     has_king_rook_moved = (king_rook_square & own_rooks_square & from) > 0;
                 }
                 if is_capture && move_action.captured_piece.unwrap() == Piece::ROOK {
-                    let opposite_queen_rook_square = if self.side_to_move.0 == Side::WHITE {
-                        Square::A8
-                    } else {
-                        Square::A1
-                    };
-                    let opposite_king_rook_square = if self.side_to_move.0 == Side::WHITE {
-                        Square::H8
-                    } else {
-                        Square::H1
-                    };
+                    let opposite_queen_rook_square =
+                        self.castling_rooks[Castling::index(Castling::queen_side(self.opposite_side()))];
+                    let opposite_king_rook_square =
+                        self.castling_rooks[Castling::index(Castling::king_side(self.opposite_side()))];
                     has_opposite_side_queen_rook_been_captured =
                         (opposite_queen_rook_square & opposite_rooks_square & to) > 0;
                     has_opposite_side_king_rook_been_captured =
@@ -329,16 +492,10 @@ impl Position {
                 );
 
                 if is_capture && move_action.captured_piece.unwrap() == Piece::ROOK {
-                    let opposite_queen_rook_square = if self.side_to_move.0 == Side::WHITE {
-                        Square::A8
-                    } else {
-                        Square::A1
-                    };
-                    let opposite_king_rook_square = if self.side_to_move.0 == Side::WHITE {
-                        Square::H8
-                    } else {
-                        Square::H1
-                    };
+                    let opposite_queen_rook_square =
+                        self.castling_rooks[Castling::index(Castling::queen_side(self.opposite_side()))];
+                    let opposite_king_rook_square =
+                        self.castling_rooks[Castling::index(Castling::king_side(self.opposite_side()))];
                     has_opposite_side_queen_rook_been_captured =
                         (opposite_queen_rook_square & opposite_rooks_square & to) > 0;
                     has_opposite_side_king_rook_been_captured =
@@ -368,49 +525,15 @@ impl Position {
                 );
             }
             // Castle
-            Move::Castle(castling) => match castling {
-                Castling::WHITE_KING_SIDE => {
+            Move::Castle(castling) => {
+                if castling & (Castling::WHITE_KING_SIDE | Castling::BLACK_KING_SIDE) > 0 {
                     has_king_rook_moved = true;
-                    self.castle(
-                        Square::E1,
-                        Square::G1,
-                        Square::H1,
-                        Square::F1,
-                        &mut new_zobrist,
-                    );
-                }
-                Castling::WHITE_QUEEN_SIDE => {
-                    has_queen_rook_moved = true;
-                    self.castle(
-                        Square::E1,
-                        Square::C1,
-                        Square::A1,
-                        Square::D1,
-                        &mut new_zobrist,
-                    );
-                }
-                Castling::BLACK_KING_SIDE => {
-                    has_king_rook_moved = true;
-                    self.castle(
-                        Square::E8,
-                        Square::G8,
-                        Square::H8,
-                        Square::F8,
-                        &mut new_zobrist,
-                    );
-                }
-                Castling::BLACK_QUEEN_SIDE => {
+                } else {
                     has_queen_rook_moved = true;
-                    self.castle(
-                        Square::E8,
-                        Square::C8,
-                        Square::A8,
-                        Square::D8,
-                        &mut new_zobrist,
-                    );
                 }
-                _ => {}
-            },
+                let (king_from, king_to, rook_from, rook_to) = self.castling_squares(castling);
+                self.castle(king_from, king_to, rook_from, rook_to, &mut new_zobrist);
+            }
         }
 
         // Add to number of moves
@@ -418,11 +541,11 @@ impl Position {
 
         // ** Modify the state **
 
-        // Add half moves since capture
-        new_state.since_last_capture = if is_capture {
+        // Reset the halfmove clock on captures and pawn moves
+        new_state.halfmove_clock = if is_capture || move_action.piece == Piece::PAWN {
             0
         } else {
-            new_state.since_last_capture + 1
+            new_state.halfmove_clock + 1
         };
 
         // Check en passant square
@@ -435,7 +558,7 @@ impl Position {
         } else {
             new_state.en_passant.0 = Square::NONE;
         }
-        let prev_enpassant = new_state.prev.as_ref().unwrap().en_passant.0;
+        let prev_enpassant = self.state.en_passant.0;
         if new_state.en_passant.0 != prev_enpassant {
             new_zobrist.hash ^= self
                 .zobrist_hashes
@@ -493,7 +616,7 @@ impl Position {
                 || has_opposite_side_king_rook_been_captured
                 || has_opposite_side_queen_rook_been_captured
             {
-                let prev_castling = new_state.prev.as_ref().unwrap().castling.0;
+                let prev_castling = self.state.castling.0;
                 if new_state.castling.0 != prev_castling {
                     new_zobrist.hash ^= self.zobrist_hashes.castling(prev_castling as usize).hash;
                     new_zobrist.hash ^= self
@@ -512,8 +635,61 @@ impl Position {
         };
         new_zobrist.hash ^= self.zobrist_hashes.side(self.side_to_move.0).hash;
 
-        self.zobrist = Arc::new(new_zobrist);
-        self.state = Arc::new(new_state);
+        self.push_state(new_state, new_zobrist);
+
+        debug_assert_eq!(
+            self.validate_structure(),
+            Ok(()),
+            "{move_action} left {}",
+            self.to_fen()
+        );
+    }
+
+    // Pass the turn without moving, for null move pruning. Never called while in check
+    pub fn apply_null_move(&mut self) {
+        let mut new_zobrist = self.zobrist.clone();
+        // The en passant capture is gone once the other side has had its turn
+        let new_state = BoardState {
+            castling: Castling(self.state.castling.0),
+            en_passant: Square(Square::NONE),
+            halfmove_clock: self.state.halfmove_clock + 1,
+        };
+        if self.state.en_passant.0 != Square::NONE {
+            new_zobrist.hash ^= self
+                .zobrist_hashes
+                .en_passant(self.state.en_passant.0.trailing_zeros() as usize)
+                .hash;
+            new_zobrist.hash ^= self
+                .zobrist_hashes
+                .en_passant(Square::NONE.trailing_zeros() as usize)
+                .hash;
+        }
+
+        self.half_move_number += 1;
+        self.side_to_move = Side(self.opposite_side());
+        new_zobrist.hash ^= self.zobrist_hashes.side(self.side_to_move.0).hash;
+
+        self.push_state(new_state, new_zobrist);
+    }
+
+    pub fn undo_null_move(&mut self) {
+        self.side_to_move = Side(self.opposite_side());
+        self.undo_state();
+        self.half_move_number -= 1;
+    }
+
+    // Keep the current state and hash in the history and replace them with the new ones
+    fn push_state(&mut self, state: BoardState, zobrist: ZobristValue) {
+        let prev_state = mem::replace(&mut self.state, state);
+        let prev_zobrist = mem::replace(&mut self.zobrist, zobrist);
+        self.history.push((prev_state, prev_zobrist.hash));
+    }
+
+    // Back to the state and hash before the last move
+    fn undo_state(&mut self) {
+        let (state, hash) = self.history.pop().unwrap();
+        self.state = state;
+        self.zobrist.hash = hash;
     }
 
     fn remove_piece(
@@ -542,17 +718,12 @@ impl Position {
         to_rook: u64,
         new_zobrist: &mut ZobristValue,
     ) {
-        // King
-        // Update side reprs
-        self.set_side_pieces_from_move(from, to, false);
-        // Move the king
+        // King and rook can swap squares in Chess960, so take both away first
+        self.board.side_pieces[self.side_to_move.0].0 &= !(from | from_rook);
+        self.board.side_pieces[self.side_to_move.0].0 |= to | to_rook;
         self.remove_piece(self.side_to_move.0, Piece::KING, from, new_zobrist);
-        self.add_piece(self.side_to_move.0, Piece::KING, to, new_zobrist);
-        // Rook
-        // Update side reprs
-        self.set_side_pieces_from_move(from_rook, to_rook, false);
-        // Move the rook
         self.remove_piece(self.side_to_move.0, Piece::ROOK, from_rook, new_zobrist);
+        self.add_piece(self.side_to_move.0, Piece::KING, to, new_zobrist);
         self.add_piece(self.side_to_move.0, Piece::ROOK, to_rook, new_zobrist);
     }
 
@@ -663,28 +834,24 @@ impl Position {
             .en_passant(self.state.en_passant.0.trailing_zeros() as usize)
             .hash;
 
-                ZobristValue {
-            hash: result,
-            prev: None,
-        }
+        ZobristValue { hash: result }
     }
 }
 
 // The current (not pieces) state of the board
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct BoardState {
-    pub since_last_capture: usize,
+    // Plies since the last capture or pawn move, for the fifty-move rule
+    pub halfmove_clock: usize,
     pub castling: Castling,
     pub en_passant: Square,
-    pub prev: Option<Arc<BoardState>>,
 }
 impl BoardState {
     pub fn empty() -> BoardState {
         BoardState {
             castling: Castling(Castling::NO_CASTLING),
             en_passant: Square(Square::NONE),
-            since_last_capture: 0,
-            prev: None,
+            halfmove_clock: 0,
         }
     }
 }
@@ -745,6 +912,43 @@ impl Castling {
     pub const ALL_BLACK: u8 = Self::BLACK_QUEEN_SIDE | Self::BLACK_KING_SIDE;
     #[allow(dead_code)]
     pub const ALL: u8 = Self::ALL_WHITE | Self::ALL_BLACK;
+    // Every single castling right, in the order of Position::castling_rooks
+    pub const FLAGS: [u8; 4] = [
+        Self::WHITE_KING_SIDE,
+        Self::WHITE_QUEEN_SIDE,
+        Self::BLACK_KING_SIDE,
+        Self::BLACK_QUEEN_SIDE,
+    ];
+
+    // Index of a single castling right in Castling::FLAGS
+    pub fn index(castling: u8) -> usize {
+        Self::FLAGS.iter().position(|flag| *flag == castling).unwrap_or(0)
+    }
+
+    // The side a single castling right belongs to
+    pub fn side(castling: u8) -> usize {
+        if castling & Self::ALL_WHITE > 0 {
+            Side::WHITE
+        } else {
+            Side::BLACK
+        }
+    }
+
+    pub fn king_side(side: usize) -> u8 {
+        if side == Side::WHITE {
+            Self::WHITE_KING_SIDE
+        } else {
+            Self::BLACK_KING_SIDE
+        }
+    }
+
+    pub fn queen_side(side: usize) -> u8 {
+        if side == Side::WHITE {
+            Self::WHITE_QUEEN_SIDE
+        } else {
+            Self::BLACK_QUEEN_SIDE
+        }
+    }
 }
 
 // The squares of the board, used for en-passant
@@ -839,4 +1043,6 @@ impl Square {
         Self::A2 | Self::B2 | Self::C2 | Self::D2 | Self::E2 | Self::F2 | Self::G2 | Self::H2;
     pub const SEVENTH_ROW: u64 =
         Self::A7 | Self::B7 | Self::C7 | Self::D7 | Self::E7 | Self::F7 | Self::G7 | Self::H7;
+
+    pub const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
 }
@@ -0,0 +1,87 @@
+use crate::movegen::generator::{MoveGenKind, MoveGenerator};
+
+use super::models::{Engine, Piece, Position, Side, Square};
+
+// How a game ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameOutcome {
+    // The side that delivered the mate
+    Checkmate(Side),
+    Stalemate,
+    InsufficientMaterial,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+}
+impl GameOutcome {
+    pub fn is_draw(&self) -> bool {
+        !matches!(self, GameOutcome::Checkmate(_))
+    }
+
+    // The result as written in a PGN
+    pub fn result(&self) -> &'static str {
+        match self {
+            GameOutcome::Checkmate(Side(Side::WHITE)) => "1-0",
+            GameOutcome::Checkmate(_) => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+}
+
+impl Engine {
+    // The outcome of the game in the current position, if the game is over
+    pub fn game_status(&mut self) -> Option<GameOutcome> {
+        self.position.outcome()
+    }
+}
+
+impl Position {
+    // Check if the game is over, and why
+    pub fn outcome(&mut self) -> Option<GameOutcome> {
+        // Mate goes first, even on the 100th ply or on a repetition
+        if MoveGenerator::get_legal_moves(self, &MoveGenKind::All).is_empty() {
+            if self.is_in_check() {
+                return Some(GameOutcome::Checkmate(Side(self.opposite_side())));
+            }
+            return Some(GameOutcome::Stalemate);
+        }
+        if self.has_insufficient_material() {
+            return Some(GameOutcome::InsufficientMaterial);
+        }
+        if self.is_repetition(3) {
+            return Some(GameOutcome::ThreefoldRepetition);
+        }
+        if self.state.halfmove_clock >= 100 {
+            return Some(GameOutcome::FiftyMoveRule);
+        }
+        None
+    }
+
+    // Check if the side to move is in check
+    pub fn is_in_check(&mut self) -> bool {
+        self.side_to_move = Side(self.opposite_side());
+        let is_check = MoveGenerator::is_position_check(self);
+        self.side_to_move = Side(self.opposite_side());
+        is_check
+    }
+
+    // Neither side can mate: K vs K, K + minor vs K, or only bishops all on the same colour
+    pub fn has_insufficient_material(&self) -> bool {
+        let white = &self.board.pieces[Side::WHITE];
+        let black = &self.board.pieces[Side::BLACK];
+        let heavy_pieces_and_pawns = [Piece::PAWN, Piece::ROOK, Piece::QUEEN]
+            .iter()
+            .fold(0, |acc, piece| acc | white[*piece].0 | black[*piece].0);
+        if heavy_pieces_and_pawns != 0 {
+            return false;
+        }
+
+        let knights = white[Piece::KNIGHT].0 | black[Piece::KNIGHT].0;
+        let bishops = white[Piece::BISHOP].0 | black[Piece::BISHOP].0;
+        let minors = (knights | bishops).count_ones();
+        if minors <= 1 {
+            return true;
+        }
+        knights == 0
+            && ((bishops & Square::LIGHT_SQUARES) == 0 || (bishops & !Square::LIGHT_SQUARES) == 0)
+    }
+}
@@ -0,0 +1,249 @@
+use crate::movegen::generator::{MoveGenKind, MoveGenerator, MoveInfo};
+
+use super::{
+    models::{Castling, Move, Piece, Position, Side},
+    utils::{get_column, get_row},
+};
+
+// Everything that can go wrong while reading a move in standard algebraic notation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    // The string doesn't look like a SAN move at all
+    InvalidSyntax(String),
+    // No legal move matches
+    IllegalMove(String),
+    // More than one legal move matches
+    AmbiguousMove(String),
+}
+impl std::error::Error for SanError {}
+
+// Same letters as the Display impl for Piece
+const SAN_PIECES: [(char, usize); 5] = [
+    ('K', Piece::KING),
+    ('Q', Piece::QUEEN),
+    ('R', Piece::ROOK),
+    ('B', Piece::BISHOP),
+    ('N', Piece::KNIGHT),
+];
+
+impl MoveInfo {
+    // The move in standard algebraic notation ("Nf3", "exd5", "O-O", "e8=Q+").
+    // The position is the one before the move is played
+    pub fn to_san(&self, pos: &Position) -> String {
+        let mut san = match self.m {
+            Move::Castle(castling) => {
+                if castling & (Castling::WHITE_KING_SIDE | Castling::BLACK_KING_SIDE) > 0 {
+                    String::from("O-O")
+                } else {
+                    String::from("O-O-O")
+                }
+            }
+            Move::Normal(from, to) | Move::EnPassant(from, to) | Move::Promotion(from, to, _) => {
+                let mut san = String::new();
+                let is_capture =
+                    self.captured_piece.is_some() || matches!(self.m, Move::EnPassant(_, _));
+                if self.piece == Piece::PAWN {
+                    if is_capture {
+                        san.push(file_char(from));
+                    }
+                } else {
+                    san.push_str(&format!("{}", Piece(self.piece)));
+                    san.push_str(&self.disambiguation(pos, from, to));
+                }
+                if is_capture {
+                    san.push('x');
+                }
+                san.push(file_char(to));
+                san.push(rank_char(to));
+                if let Move::Promotion(_, _, piece) = self.m {
+                    san.push_str(&format!("={}", Piece(piece)));
+                }
+                san
+            }
+        };
+
+        // Check and mate suffixes
+        let mut after = pos.clone();
+        after.apply_move(self);
+        if after.is_in_check() {
+            if MoveGenerator::get_legal_moves(&mut after, &MoveGenKind::All).is_empty() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san
+    }
+
+    // File, rank or both, whatever is needed to tell this move apart from
+    // another piece of the same kind that can reach the same square
+    fn disambiguation(&self, pos: &Position, from: u64, to: u64) -> String {
+        let others: Vec<u64> = MoveGenerator::get_legal_moves(&mut pos.clone(), &MoveGenKind::All)
+            .iter()
+            .filter(|m| m.piece == self.piece)
+            .filter_map(|m| move_squares(&m.m))
+            .filter(|(other_from, other_to)| *other_to == to && *other_from != from)
+            .map(|(other_from, _)| other_from)
+            .collect();
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| get_column(*other) != get_column(from)) {
+            file_char(from).to_string()
+        } else if others.iter().all(|other| get_row(*other) != get_row(from)) {
+            rank_char(from).to_string()
+        } else {
+            format!("{}{}", file_char(from), rank_char(from))
+        }
+    }
+}
+
+impl Position {
+    // Find the legal move described in standard algebraic notation.
+    // Lenient on what humans usually type: "0-0", "e8Q", missing "x" and annotations like "!?"
+    pub fn parse_san(&self, san: &str) -> Result<MoveInfo, SanError> {
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let mut pos = self.clone();
+        let moves = MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All);
+
+        // Castling
+        let castling = match trimmed {
+            "O-O" | "0-0" => Some(Castling::WHITE_KING_SIDE | Castling::BLACK_KING_SIDE),
+            "O-O-O" | "0-0-0" => Some(Castling::WHITE_QUEEN_SIDE | Castling::BLACK_QUEEN_SIDE),
+            _ => None,
+        };
+        if let Some(castling) = castling {
+            let side_castling = if self.side_to_move.0 == Side::WHITE {
+                castling & (Castling::WHITE_KING_SIDE | Castling::WHITE_QUEEN_SIDE)
+            } else {
+                castling & (Castling::BLACK_KING_SIDE | Castling::BLACK_QUEEN_SIDE)
+            };
+            return moves
+                .into_iter()
+                .find(|m| m.m == Move::Castle(side_castling))
+                .ok_or_else(|| SanError::IllegalMove(String::from(san)));
+        }
+
+        let invalid = || SanError::InvalidSyntax(String::from(san));
+        let mut chars: Vec<char> = trimmed.chars().collect();
+
+        // Moving piece, pawns have no letter
+        let piece = match chars.first().and_then(|c| SAN_PIECES.iter().find(|(p, _)| p == c)) {
+            Some((_, piece)) => {
+                chars.remove(0);
+                *piece
+            }
+            None => Piece::PAWN,
+        };
+
+        // Promotion, with or without the "="
+        let mut promotion = None;
+        if piece == Piece::PAWN {
+            if let Some(last) = chars.last().map(|c| c.to_ascii_uppercase()) {
+                if let Some((_, promoted)) = SAN_PIECES[1..].iter().find(|(p, _)| *p == last) {
+                    // A lowercase "b" is a file, unless it comes after the "="
+                    let has_equal = chars.len() > 1 && chars[chars.len() - 2] == '=';
+                    if has_equal || chars.last() == Some(&last) {
+                        promotion = Some(*promoted);
+                        chars.pop();
+                        if has_equal {
+                            chars.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Destination square
+        if chars.len() < 2 {
+            return Err(invalid());
+        }
+        let to_rank = chars.pop().unwrap();
+        let to_file = chars.pop().unwrap();
+        if !('a'..='h').contains(&to_file) || !('1'..='8').contains(&to_rank) {
+            return Err(invalid());
+        }
+        let to = square_from_chars(to_file, to_rank);
+
+        // Whatever is left is the disambiguation, optionally followed by the capture
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+        let (mut from_file, mut from_rank) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c),
+                _ => return Err(invalid()),
+            }
+        }
+        // A pawn that is not capturing stays on its file
+        if piece == Piece::PAWN && from_file.is_none() {
+            from_file = Some(to_file);
+        }
+
+        let mut candidates = moves.into_iter().filter(|m| {
+            let m_promotion = match m.m {
+                Move::Promotion(_, _, promoted) => Some(promoted),
+                _ => None,
+            };
+            match move_squares(&m.m) {
+                Some((m_from, m_to)) => {
+                    m.piece == piece
+                        && m_to == to
+                        && m_promotion == promotion
+                        && from_file.is_none_or(|file| file_char(m_from) == file)
+                        && from_rank.is_none_or(|rank| rank_char(m_from) == rank)
+                }
+                None => false,
+            }
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(m), None) => Ok(m),
+            (Some(_), Some(_)) => Err(SanError::AmbiguousMove(String::from(san))),
+            _ => Err(SanError::IllegalMove(String::from(san))),
+        }
+    }
+
+    // A sequence of moves from this position in SAN, with move numbers ("1. e4 e5 2. Nf3").
+    // Stops at the first move that is not legal, so a stale PV can't corrupt the position
+    pub fn san_line(&self, moves: &[MoveInfo]) -> String {
+        let mut pos = self.clone();
+        let mut line: Vec<String> = vec![];
+        for (index, m) in moves.iter().enumerate() {
+            if !MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All).contains(m) {
+                break;
+            }
+            let move_number = pos.half_move_number / 2 + 1;
+            if pos.side_to_move.0 == Side::WHITE {
+                line.push(format!("{move_number}."));
+            } else if index == 0 {
+                line.push(format!("{move_number}..."));
+            }
+            line.push(m.to_san(&pos));
+            pos.apply_move(m);
+        }
+        line.join(" ")
+    }
+}
+
+// Origin and destination of a move, castling has none in SAN
+fn move_squares(m: &Move) -> Option<(u64, u64)> {
+    match *m {
+        Move::Normal(from, to) | Move::EnPassant(from, to) | Move::Promotion(from, to, _) => {
+            Some((from, to))
+        }
+        Move::Castle(_) => None,
+    }
+}
+
+fn file_char(square: u64) -> char {
+    (b'a' + get_column(square)) as char
+}
+
+fn rank_char(square: u64) -> char {
+    (b'1' + get_row(square)) as char
+}
+
+fn square_from_chars(file: char, rank: char) -> u64 {
+    1u64 << ((rank as u64 - '1' as u64) * 8 + (file as u64 - 'a' as u64))
+}
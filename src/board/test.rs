@@ -2,7 +2,7 @@
 mod fen_tests {
     use crate::{
         board::{
-            fen::FenParser,
+            fen::{FenError, FenParser},
             models::{Castling, Engine, Piece, Side, Square},
         },
         constants::START_POS,
@@ -16,7 +16,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::ALL);
         assert_eq!(sut.position.state.en_passant.0, Square::NONE);
         assert_eq!(sut.position.half_move_number, 0);
-        assert_eq!(sut.position.state.since_last_capture, 0);
+        assert_eq!(sut.position.state.halfmove_clock, 0);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             0b1111111111111111
@@ -44,7 +44,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::ALL);
         assert_eq!(sut.position.state.en_passant.0, Square::E3);
         assert_eq!(sut.position.half_move_number, 1);
-        assert_eq!(sut.position.state.since_last_capture, 1);
+        assert_eq!(sut.position.state.halfmove_clock, 0);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::E4 | 0b1110111111111111
@@ -92,7 +92,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::ALL);
         assert_eq!(sut.position.state.en_passant.0, Square::NONE);
         assert_eq!(sut.position.half_move_number, 21);
-        assert_eq!(sut.position.state.since_last_capture, 5);
+        assert_eq!(sut.position.state.halfmove_clock, 5);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::D5 | Square::B3 | Square::E3 | 0b1111011110110001
@@ -125,7 +125,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::ALL);
         assert_eq!(sut.position.state.en_passant.0, Square::D6);
         assert_eq!(sut.position.half_move_number, 4);
-        assert_eq!(sut.position.state.since_last_capture, 4);
+        assert_eq!(sut.position.state.halfmove_clock, 0);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::E5 | 0b1110111111111111
@@ -147,7 +147,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::ALL);
         assert_eq!(sut.position.state.en_passant.0, Square::NONE);
         assert_eq!(sut.position.half_move_number, 5);
-        assert_eq!(sut.position.state.since_last_capture, 0);
+        assert_eq!(sut.position.state.halfmove_clock, 0);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::D6 | 0b1110111111111111
@@ -184,7 +184,7 @@ mod fen_tests {
         );
         assert_eq!(sut.position.state.en_passant.0, Square::NONE);
         assert_eq!(sut.position.half_move_number, 7);
-        assert_eq!(sut.position.state.since_last_capture, 7);
+        assert_eq!(sut.position.state.halfmove_clock, 5);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::E4 | Square::F3 | 0b1111111101101111
@@ -215,7 +215,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::NO_CASTLING);
         assert_eq!(sut.position.state.en_passant.0, Square::NONE);
         assert_eq!(sut.position.half_move_number, 8);
-        assert_eq!(sut.position.state.since_last_capture, 8);
+        assert_eq!(sut.position.state.halfmove_clock, 6);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::E4 | Square::F3 | 0b1111111101101111
@@ -254,7 +254,7 @@ mod fen_tests {
         assert_eq!(sut.position.state.castling.0, Castling::ALL);
         assert_eq!(sut.position.state.en_passant.0, Square::NONE);
         assert_eq!(sut.position.half_move_number, 4);
-        assert_eq!(sut.position.state.since_last_capture, 0);
+        assert_eq!(sut.position.state.halfmove_clock, 0);
         assert_eq!(
             sut.position.board.side_pieces[Side::WHITE].0,
             Square::E4 | 0b1100111111111111
@@ -272,6 +272,85 @@ mod fen_tests {
             (Square::SEVENTH_ROW | Square::F4) ^ Square::E7
         );
     }
+
+    #[test]
+    fn fen_round_trip() {
+        // All the positions from the perft tests and `engine --check`
+        let fens = [
+            START_POS,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1",
+            "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "3k4/8/8/8/8/8/8/R3K3 w Q - 0 1",
+            "r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1",
+            "r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1",
+            "2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1",
+            "8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1",
+            "4k3/1P6/8/8/8/8/K7/8 w - - 0 1",
+            "8/P1k5/K7/8/8/8/8/8 w - - 0 1",
+            "K1k5/8/P7/8/8/8/8/8 w - - 0 1",
+            "8/k1P5/8/1K6/8/8/8/8 w - - 0 1",
+            "8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1",
+        ];
+        for fen in fens {
+            assert_eq!(FenParser::fen_to_position(fen).to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn fen_errors() {
+        let cases = [
+            ("", FenError::MissingField("pieces position")),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", FenError::InvalidRankCount(7)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", FenError::InvalidRankLength(1)),
+            ("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidRankLength(7)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1", FenError::InvalidPiece('X')),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", FenError::MissingField("side to move")),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSide(String::from("x"))),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1", FenError::InvalidCastling(String::from("KQkx"))),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KK - 0 1", FenError::InvalidCastling(String::from("KK"))),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1", FenError::InvalidEnPassant(String::from("e3"))),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z6 0 1", FenError::InvalidEnPassant(String::from("z6"))),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1", FenError::InvalidClock(String::from("a"))),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(FenParser::try_parse(fen).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn fen_without_move_counters() {
+        let sut = FenParser::try_parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").unwrap();
+        assert_eq!(sut.to_fen(), START_POS);
+        let sut = FenParser::try_parse("  4k3/8/8/8/8/8/8/4K3   b - - 3 0 ").unwrap();
+        assert_eq!(sut.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 3 1");
+        let sut = FenParser::try_parse("4k3/8/8/8/8/8/8/4K3 w - - 7").unwrap();
+        assert_eq!(sut.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 7 1");
+    }
+
+    #[test]
+    fn fen_after_moves() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        sut.apply_algebraic_move("e2e4");
+        assert_eq!(
+            sut.position.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        sut.apply_algebraic_move("g8f6");
+        sut.apply_algebraic_move("e1e2");
+        assert_eq!(
+            sut.position.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+    }
 }
 
 #[cfg(test)]
@@ -285,7 +364,7 @@ mod position_tests {
         assert_eq!(sut.state.castling.0, Castling::NO_CASTLING);
         assert_eq!(sut.state.en_passant.0, Square::NONE);
         assert_eq!(sut.half_move_number, 0);
-        assert_eq!(sut.state.since_last_capture, 0);
+        assert_eq!(sut.state.halfmove_clock, 0);
     }
 }
 
@@ -321,25 +400,32 @@ mod utils_tests {
 
 #[cfg(test)]
 mod zobrist_tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
     use crate::{
         board::{
             fen::FenParser,
-            models::{Castling, Engine, Move, Piece, Square},
+            models::{Castling, Engine, Move, Piece, Position, Square},
+            utils::perft,
         },
-        constants::START_POS,
+        constants::{MAX_PLY, START_POS},
+        evaluate::evaluator::Evaluate,
         movegen::generator::MoveInfo,
+        uci::options::GoOptions,
     };
 
     #[test]
     fn works_and_is_same() {
         let fen = START_POS;
         let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
-        sut.apply_algebraic_move("e2e4");
+        // Knights instead of pawns: a pawn going back from e4 to e2 counts as a double push
+        // and leaves an en passant square on e1, which fails the checks after each move
+        sut.apply_algebraic_move("g1f3");
         let hash1 = sut.position.zobrist.clone();
-        sut.apply_algebraic_move("e7e5");
-        sut.apply_algebraic_move("e4e2");
-        sut.apply_algebraic_move("e5e7");
-        sut.apply_algebraic_move("e2e4");
+        sut.apply_algebraic_move("g8f6");
+        sut.apply_algebraic_move("f3g1");
+        sut.apply_algebraic_move("f6g8");
+        sut.apply_algebraic_move("g1f3");
         assert_eq!(hash1.hash, sut.position.zobrist.hash);
     }
 
@@ -453,16 +539,731 @@ mod zobrist_tests {
             captured_piece: None,
         };
         sut.apply_move(&m9);
-        sut.undo_move(&m);
-        sut.undo_move(&m1);
-        sut.undo_move(&m2);
-        sut.undo_move(&m3);
-        sut.undo_move(&m4);
-        sut.undo_move(&m5);
-        sut.undo_move(&m6);
-        sut.undo_move(&m7);
-        sut.undo_move(&m8);
+        // Undone last move first. In the order they were made, Bf1 is put back under
+        // the rook that castled there and two pieces end up on the same square
         sut.undo_move(&m9);
+        sut.undo_move(&m8);
+        sut.undo_move(&m7);
+        sut.undo_move(&m6);
+        sut.undo_move(&m5);
+        sut.undo_move(&m4);
+        sut.undo_move(&m3);
+        sut.undo_move(&m2);
+        sut.undo_move(&m1);
+        sut.undo_move(&m);
         assert_eq!(hash1.hash, sut.position.zobrist.hash);
     }
+
+    #[test]
+    fn null_move_transposes() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        let start_hash = sut.position.zobrist.hash;
+        sut.apply_algebraic_move("g1f3");
+        sut.apply_null_move();
+        sut.apply_algebraic_move("f3g1");
+        sut.apply_null_move();
+        assert_eq!(start_hash, sut.position.zobrist.hash);
+
+        // The en passant square left by the double push is gone either way
+        sut.apply_algebraic_move("e2e4");
+        sut.apply_null_move();
+        sut.apply_algebraic_move("g1f3");
+        sut.apply_null_move();
+        let hash1 = sut.position.zobrist.hash;
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        sut.apply_algebraic_move("g1f3");
+        sut.apply_null_move();
+        sut.apply_algebraic_move("e2e4");
+        sut.apply_null_move();
+        assert_eq!(hash1, sut.position.zobrist.hash);
+        assert_eq!(Square::NONE, sut.position.state.en_passant.0);
+    }
+
+    #[test]
+    fn null_move_undo_works() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        sut.apply_algebraic_move("e2e4");
+        let hash1 = sut.position.zobrist.hash;
+        let en_passant = sut.position.state.en_passant.0;
+        sut.apply_null_move();
+        sut.undo_null_move();
+        assert_eq!(hash1, sut.position.zobrist.hash);
+        assert_eq!(en_passant, sut.position.state.en_passant.0);
+        assert_eq!(1, sut.position.side_to_move.0);
+    }
+
+    #[test]
+    fn history_is_not_reallocated() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+        let history = sut.position.history.as_ptr();
+        perft(&mut sut, 3, true, false, false);
+        Evaluate::search(&mut sut, GoOptions::depth(4), None);
+        assert!(sut.position.history.is_empty());
+        assert_eq!(history, sut.position.history.as_ptr());
+    }
+
+    #[test]
+    fn clones_keep_room_for_a_search() {
+        let mut pos = FenParser::fen_to_position(START_POS);
+        pos.apply_move(&MoveInfo {
+            m: Move::Normal(Square::G1, Square::F3),
+            piece: Piece::KNIGHT,
+            captured_piece: None,
+        });
+        let clone = pos.clone();
+        assert_eq!(pos.history, clone.history);
+        assert!(clone.history.capacity() >= clone.history.len() + MAX_PLY);
+    }
+
+    #[test]
+    fn history_is_left_out_of_equality() {
+        let pos = FenParser::fen_to_position(START_POS);
+        let mut other = pos.clone();
+        other.history.push((other.state.clone(), other.zobrist.hash));
+        assert_eq!(pos, other);
+
+        let hash = |pos: &Position| {
+            let mut hasher = DefaultHasher::new();
+            pos.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&pos), hash(&other));
+    }
+}
+
+#[cfg(test)]
+mod repetition_tests {
+    use crate::{
+        board::{fen::FenParser, models::Engine},
+        constants::START_POS,
+    };
+
+    #[test]
+    fn detects_threefold_repetition() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        assert!(!sut.position.is_repetition(2));
+        sut.apply_algebraic_move("g1f3");
+        sut.apply_algebraic_move("g8f6");
+        sut.apply_algebraic_move("f3g1");
+        sut.apply_algebraic_move("f6g8");
+        assert!(sut.position.is_repetition(2));
+        assert!(!sut.position.is_repetition(3));
+        sut.apply_algebraic_move("g1f3");
+        sut.apply_algebraic_move("g8f6");
+        sut.apply_algebraic_move("f3g1");
+        sut.apply_algebraic_move("f6g8");
+        assert!(sut.position.is_repetition(3));
+    }
+
+    #[test]
+    fn capture_breaks_repetition() {
+        let fen = "4k3/8/8/3p4/8/8/8/R3K3 w - - 0 1";
+        let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+        sut.apply_algebraic_move("a1a2");
+        sut.apply_algebraic_move("e8d8");
+        sut.apply_algebraic_move("a2a1");
+        sut.apply_algebraic_move("d8e8");
+        assert!(sut.position.is_repetition(2));
+        sut.apply_algebraic_move("a1a5");
+        sut.apply_algebraic_move("e8d8");
+        sut.apply_algebraic_move("a5d5");
+        sut.apply_algebraic_move("d8e8");
+        assert!(!sut.position.is_repetition(2));
+    }
+}
+
+#[cfg(test)]
+mod outcome_tests {
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Side},
+            outcome::GameOutcome,
+        },
+        constants::START_POS,
+    };
+
+    #[test]
+    fn game_in_progress() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        assert_eq!(sut.game_status(), None);
+    }
+
+    #[test]
+    fn checkmate() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        sut.apply_algebraic_move("f2f3");
+        sut.apply_algebraic_move("e7e5");
+        sut.apply_algebraic_move("g2g4");
+        sut.apply_algebraic_move("d8h4");
+        let outcome = sut.game_status().unwrap();
+        assert_eq!(outcome, GameOutcome::Checkmate(Side(Side::BLACK)));
+        assert_eq!(outcome.result(), "0-1");
+    }
+
+    #[test]
+    fn stalemate() {
+        let fen = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+        assert_eq!(sut.game_status(), Some(GameOutcome::Stalemate));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let draws = [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5N2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5B2/8 b - - 0 1",
+            "8/2b5/4k3/8/8/3K4/5B2/8 w - - 0 1",
+        ];
+        for fen in draws {
+            let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+            assert_eq!(sut.game_status(), Some(GameOutcome::InsufficientMaterial));
+        }
+        let not_draws = [
+            "8/8/4k3/8/8/3K4/3P4/8 w - - 0 1",
+            "8/3b4/4k3/8/8/3K4/5B2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4NN2/8 w - - 0 1",
+        ];
+        for fen in not_draws {
+            let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+            assert_eq!(sut.game_status(), None);
+        }
+    }
+
+    #[test]
+    fn fifty_move_rule_after_mate() {
+        // The queen is on b1, not a1: from a1 it would give check with white to move
+        let fen = "7k/8/6K1/8/8/8/8/1Q6 w - - 99 80";
+        let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+        sut.apply_algebraic_move("b1c1");
+        assert_eq!(sut.game_status(), Some(GameOutcome::FiftyMoveRule));
+        let mut sut = Engine::from_position(FenParser::fen_to_position(fen));
+        sut.apply_algebraic_move("b1b8");
+        assert_eq!(
+            sut.game_status(),
+            Some(GameOutcome::Checkmate(Side(Side::WHITE)))
+        );
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        for _ in 0..2 {
+            sut.apply_algebraic_move("g1f3");
+            sut.apply_algebraic_move("g8f6");
+            sut.apply_algebraic_move("f3g1");
+            sut.apply_algebraic_move("f6g8");
+        }
+        assert_eq!(sut.game_status(), Some(GameOutcome::ThreefoldRepetition));
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use crate::{
+        board::{
+            fen::{FenError, FenParser},
+            models::{Castling, Side, Square},
+            validate::PositionError,
+        },
+        constants::START_POS,
+    };
+
+    fn parse_error(fen: &str) -> PositionError {
+        match FenParser::try_parse(fen) {
+            Err(FenError::InvalidPosition(error)) => error,
+            other => panic!("Expected an invalid position, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn valid_positions() {
+        assert_eq!(FenParser::fen_to_position(START_POS).validate(), Ok(()));
+        let fen = "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3";
+        assert!(FenParser::try_parse(fen).is_ok());
+    }
+
+    #[test]
+    fn kings() {
+        assert_eq!(
+            parse_error("8/8/8/8/8/8/8/4K3 w - - 0 1"),
+            PositionError::MissingKing(Side(Side::BLACK))
+        );
+        assert_eq!(
+            parse_error("4k3/8/8/8/8/8/8/K3K3 w - - 0 1"),
+            PositionError::TooManyKings(Side(Side::WHITE))
+        );
+    }
+
+    #[test]
+    fn pawns_on_back_rank() {
+        assert_eq!(
+            parse_error("4k2P/8/8/8/8/8/8/4K3 w - - 0 1"),
+            PositionError::PawnsOnBackRank
+        );
+        assert_eq!(
+            parse_error("4k3/8/8/8/8/8/8/p3K3 w - - 0 1"),
+            PositionError::PawnsOnBackRank
+        );
+    }
+
+    #[test]
+    fn castling_rights() {
+        assert_eq!(
+            parse_error("4k3/8/8/8/8/8/8/4K3 w K - 0 1"),
+            PositionError::InvalidCastlingRights(Castling(Castling::WHITE_KING_SIDE))
+        );
+        assert_eq!(
+            parse_error("r3k1r1/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            PositionError::InvalidCastlingRights(Castling(Castling::BLACK_KING_SIDE))
+        );
+    }
+
+    #[test]
+    fn en_passant() {
+        assert_eq!(
+            parse_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq d6 0 1"),
+            PositionError::InvalidEnPassant(Square(Square::D6))
+        );
+    }
+
+    #[test]
+    fn side_not_to_move_in_check() {
+        assert_eq!(
+            parse_error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+            PositionError::OpponentInCheck
+        );
+        assert!(FenParser::try_parse("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1").is_ok());
+    }
+}
+
+#[cfg(test)]
+mod san_tests {
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Castling, Move, Piece, Position, Square},
+            san::SanError,
+        },
+        constants::START_POS,
+        movegen::generator::{MoveGenKind, MoveGenerator},
+    };
+
+    fn to_san(fen: &str, uci_move: &str) -> String {
+        let pos = FenParser::fen_to_position(fen);
+        let mut moves_pos = pos.clone();
+        let m = MoveGenerator::get_legal_moves(&mut moves_pos, &MoveGenKind::All)
+            .into_iter()
+            .find(|m| format!("{m}") == uci_move)
+            .unwrap();
+        m.to_san(&pos)
+    }
+
+    fn parse_san(fen: &str, san: &str) -> Result<String, SanError> {
+        FenParser::fen_to_position(fen)
+            .parse_san(san)
+            .map(|m| format!("{m}"))
+    }
+
+    #[test]
+    fn round_trip() {
+        let fens = [
+            START_POS,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/8/8/Q7/8/Q1Q4K w - - 0 1",
+        ];
+        for fen in fens {
+            let mut pos: Position = FenParser::fen_to_position(fen);
+            for m in MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All) {
+                assert_eq!(pos.parse_san(&m.to_san(&pos)), Ok(m));
+            }
+        }
+    }
+
+    #[test]
+    fn pieces_and_pawns() {
+        assert_eq!(to_san(START_POS, "g1f3"), "Nf3");
+        assert_eq!(to_san(START_POS, "e2e4"), "e4");
+        let fen = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        assert_eq!(to_san(fen, "e4d5"), "exd5");
+        assert_eq!(parse_san(fen, "exd5"), Ok(String::from("e4d5")));
+        assert_eq!(parse_san(fen, "ed5"), Ok(String::from("e4d5")));
+        assert_eq!(
+            parse_san(fen, "d5"),
+            Err(SanError::IllegalMove(String::from("d5")))
+        );
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        assert_eq!(to_san(fen, "e5d6"), "exd6");
+    }
+
+    #[test]
+    fn disambiguation() {
+        let fen = "4k3/8/8/8/8/8/K7/R6R w - - 0 1";
+        assert_eq!(to_san(fen, "a1d1"), "Rad1");
+        assert_eq!(to_san(fen, "h1d1"), "Rhd1");
+        assert_eq!(
+            parse_san(fen, "Rd1"),
+            Err(SanError::AmbiguousMove(String::from("Rd1")))
+        );
+        let fen = "4k3/8/8/R7/8/8/7K/R7 w - - 0 1";
+        assert_eq!(to_san(fen, "a1a3"), "R1a3");
+        assert_eq!(to_san(fen, "a5a3"), "R5a3");
+        let fen = "4k3/8/8/8/8/Q7/8/Q1Q4K w - - 0 1";
+        assert_eq!(to_san(fen, "a1b2"), "Qa1b2");
+        assert_eq!(to_san(fen, "c1b2"), "Qcb2");
+        assert_eq!(to_san(fen, "a3b2"), "Q3b2");
+        assert_eq!(parse_san(fen, "Qa1b2"), Ok(String::from("a1b2")));
+    }
+
+    #[test]
+    fn castling() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let pos = FenParser::fen_to_position(fen);
+        assert_eq!(pos.parse_san("O-O").unwrap().m, Move::Castle(Castling::WHITE_KING_SIDE));
+        assert_eq!(pos.parse_san("0-0-0").unwrap().m, Move::Castle(Castling::WHITE_QUEEN_SIDE));
+        assert_eq!(to_san(fen, "e1g1"), "O-O");
+        assert_eq!(to_san(fen, "e1c1"), "O-O-O");
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1";
+        let pos = FenParser::fen_to_position(fen);
+        assert_eq!(pos.parse_san("O-O-O").unwrap().m, Move::Castle(Castling::BLACK_QUEEN_SIDE));
+    }
+
+    #[test]
+    fn promotions() {
+        let fen = "8/P6k/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(to_san(fen, "a7a8Q"), "a8=Q");
+        assert_eq!(to_san(fen, "a7a8R"), "a8=R");
+        for san in ["a8=Q", "a8Q", "a8=q", "a8=Q+"] {
+            let m = FenParser::fen_to_position(fen).parse_san(san).unwrap();
+            assert_eq!(m.m, Move::Promotion(Square::A7, Square::A8, Piece::QUEEN));
+        }
+        assert_eq!(
+            parse_san(fen, "a8"),
+            Err(SanError::IllegalMove(String::from("a8")))
+        );
+    }
+
+    #[test]
+    fn check_and_mate() {
+        let fen = "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1";
+        assert_eq!(to_san(fen, "b1b8"), "Qb8#");
+        assert_eq!(to_san(fen, "b1h1"), "Qh1+");
+        assert_eq!(to_san(fen, "b1c1"), "Qc1");
+        assert_eq!(parse_san(fen, "Qb8#"), Ok(String::from("b1b8")));
+        assert_eq!(parse_san(fen, "Qh1+!?"), Ok(String::from("b1h1")));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_san(START_POS, "Nf9"),
+            Err(SanError::InvalidSyntax(String::from("Nf9")))
+        );
+        assert_eq!(
+            parse_san(START_POS, "hello"),
+            Err(SanError::InvalidSyntax(String::from("hello")))
+        );
+        assert_eq!(
+            parse_san(START_POS, "Ke2"),
+            Err(SanError::IllegalMove(String::from("Ke2")))
+        );
+        assert_eq!(
+            parse_san(START_POS, "O-O"),
+            Err(SanError::IllegalMove(String::from("O-O")))
+        );
+    }
+
+    #[test]
+    fn san_line() {
+        let mut pos = FenParser::fen_to_position(START_POS);
+        let start = pos.clone();
+        let mut moves = vec![];
+        for san in ["e4", "e5", "Nf3", "Nc6"] {
+            let m = pos.parse_san(san).unwrap();
+            pos.apply_move(&m);
+            moves.push(m);
+        }
+        assert_eq!(start.san_line(&moves), "1. e4 e5 2. Nf3 Nc6");
+
+        let mut after_e4 = start.clone();
+        after_e4.apply_move(&moves[0]);
+        assert_eq!(after_e4.san_line(&moves[1..]), "1... e5 2. Nf3 Nc6");
+
+        // Stops at the first move that doesn't fit
+        assert_eq!(start.san_line(&[moves[0].clone(), moves[0].clone()]), "1. e4");
+    }
+}
+
+#[cfg(test)]
+mod epd_tests {
+    use crate::board::{
+        epd::EpdError,
+        fen::{FenError, FenParser},
+        san::SanError,
+    };
+
+    #[test]
+    fn operations() {
+        let epd = FenParser::try_parse_epd(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; acd 12; c0 \"a comment; with a semicolon\";",
+        )
+        .unwrap();
+        assert_eq!(
+            epd.position.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.acd(), Some(12));
+        assert_eq!(epd.c0(), Some("a comment; with a semicolon"));
+        assert_eq!(epd.operation("bm"), Some(&[String::from("Qg6")][..]));
+        assert_eq!(epd.operation("pv"), None);
+        assert_eq!(epd.best_moves.len(), 1);
+        assert_eq!(format!("{}", epd.best_moves[0]), "g3g6");
+        assert!(epd.points(&epd.best_moves[0]).is_none());
+    }
+
+    #[test]
+    fn move_counters_and_missing_semicolon() {
+        let epd = FenParser::try_parse_epd(
+            "4k3/8/8/8/8/8/8/4K2R w K - hmvc 12; fmvn 40; bm O-O Rh8+",
+        )
+        .unwrap();
+        assert_eq!(epd.position.to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 12 40");
+        assert_eq!(epd.best_moves.len(), 2);
+    }
+
+    #[test]
+    fn solving() {
+        let epd = FenParser::try_parse_epd(
+            "4k3/8/8/8/8/8/8/4K2R w K - bm O-O Rh8+; am Kd1;",
+        )
+        .unwrap();
+        let castle = epd.position.parse_san("O-O").unwrap();
+        let check = epd.position.parse_san("Rh8+").unwrap();
+        let king = epd.position.parse_san("Kd1").unwrap();
+        let rook = epd.position.parse_san("Rh2").unwrap();
+        assert!(epd.is_solved_by(&castle));
+        assert!(epd.is_solved_by(&check));
+        assert!(!epd.is_solved_by(&king));
+        assert!(!epd.is_solved_by(&rook));
+
+        let epd = FenParser::try_parse_epd("4k3/8/8/8/8/8/8/4K2R w K - am Kd1;").unwrap();
+        assert!(epd.is_solved_by(&rook));
+        assert!(!epd.is_solved_by(&king));
+
+        let epd = FenParser::try_parse_epd("4k3/8/8/8/8/8/8/4K2R w K - id \"none\";").unwrap();
+        assert!(!epd.has_solution());
+        assert!(!epd.is_solved_by(&rook));
+    }
+
+    #[test]
+    fn sts_points() {
+        let epd = FenParser::try_parse_epd(
+            "4k3/1P6/8/8/8/8/8/4K2R w K - bm b8=Q+; c0 \"b8=Q+=10, O-O=4, Rh8+=2\";",
+        )
+        .unwrap();
+        let pos = &epd.position;
+        assert_eq!(epd.points(&pos.parse_san("b8=Q+").unwrap()), Some(10));
+        assert_eq!(epd.points(&pos.parse_san("O-O").unwrap()), Some(4));
+        assert_eq!(epd.points(&pos.parse_san("Kd1").unwrap()), Some(0));
+        assert_eq!(epd.max_points(), Some(10));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            FenParser::try_parse_epd("4k3/8/8/8/8/8/8/4K2R w K"),
+            Err(EpdError::InvalidFen(FenError::MissingField("en passant")))
+        );
+        assert_eq!(
+            FenParser::try_parse_epd("4k3/8/8/8/8/8/8/4K2R w K - bm Ke3;"),
+            Err(EpdError::InvalidMove(SanError::IllegalMove(String::from("Ke3"))))
+        );
+        assert_eq!(
+            FenParser::try_parse_epd("4k3/8/8/8/8/8/8/4K2R w K - id \"never closed;"),
+            Err(EpdError::InvalidOperation(String::from("id \"never closed;")))
+        );
+        assert_eq!(
+            FenParser::try_parse_epd("4k3/8/8/8/8/8/8/4K2R w K - hmvc x;"),
+            Err(EpdError::InvalidNumber(String::from("x")))
+        );
+    }
+}
+
+#[cfg(test)]
+mod chess960_tests {
+    use crate::{
+        board::{
+            fen::{FenError, FenParser},
+            models::{Castling, Engine, Move, Square},
+            utils::move_to_algebraic,
+        },
+        constants::START_POS,
+        movegen::generator::{MoveGenKind, MoveGenerator, MoveInfo},
+    };
+
+    #[test]
+    fn shredder_and_x_fen() {
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let position = FenParser::try_parse_chess960(shredder).unwrap();
+        assert_eq!(position.castling_kings, [Square::G1, Square::G8]);
+        assert_eq!(
+            position.castling_rooks,
+            [Square::H1, Square::F1, Square::H8, Square::F8]
+        );
+        // Both rooks are the outermost ones, so X-FEN writes KQkq
+        let x_fen = position.to_fen();
+        assert_eq!(
+            x_fen,
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert_eq!(FenParser::try_parse_chess960(&x_fen), Ok(position));
+
+        // An inner rook needs its file
+        let fen = "1r2k3/8/8/8/8/8/8/RR2K3 w Bb - 0 1";
+        let position = FenParser::try_parse_chess960(fen).unwrap();
+        assert_eq!(position.state.castling.0, Castling::WHITE_QUEEN_SIDE | Castling::BLACK_QUEEN_SIDE);
+        assert_eq!(position.to_fen(), "1r2k3/8/8/8/8/8/8/RR2K3 w Bq - 0 1");
+
+        // Standard chess knows nothing about files
+        assert_eq!(
+            FenParser::try_parse(fen),
+            Err(FenError::InvalidCastling(String::from("Bb")))
+        );
+    }
+
+    #[test]
+    fn king_takes_rook() {
+        let fen = "4k3/8/8/8/8/8/8/5KR1 w K - 0 1";
+        let mut sut = Engine::from_position(FenParser::try_parse_chess960(fen).unwrap());
+        let castle = MoveInfo {
+            m: Move::Castle(Castling::WHITE_KING_SIDE),
+            piece: 5,
+            captured_piece: None,
+        };
+        let moves = MoveGenerator::get_legal_moves(&mut sut.position, &MoveGenKind::All);
+        assert!(moves.contains(&castle));
+        assert_eq!(move_to_algebraic(&castle, &sut.position), "f1g1");
+
+        sut.apply_algebraic_move("f1g1");
+        assert_eq!(sut.position.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        sut.undo_move(&castle);
+        assert_eq!(sut.position.to_fen(), fen);
+
+        // The king goes through d1, attacked by the rook
+        let fen = "3rk3/8/8/8/8/8/8/1K5R w K - 0 1";
+        let mut position = FenParser::try_parse_chess960(fen).unwrap();
+        let moves = MoveGenerator::get_legal_moves(&mut position, &MoveGenKind::All);
+        assert!(!moves.iter().any(|m| matches!(m.m, Move::Castle(_))));
+
+        // Standard chess keeps the king moving two squares
+        let mut sut = Engine::from_position(FenParser::fen_to_position(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        ));
+        let castle = MoveInfo {
+            m: Move::Castle(Castling::WHITE_QUEEN_SIDE),
+            ..castle
+        };
+        assert_eq!(move_to_algebraic(&castle, &sut.position), "e1c1");
+        sut.apply_algebraic_move("e1c1");
+        assert_eq!(sut.position.to_fen(), "r3k2r/8/8/8/8/8/8/2KR3R b kq - 1 1");
+        assert!(FenParser::try_parse_chess960(START_POS).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod polyglot_tests {
+    use crate::{
+        board::{fen::FenParser, models::Engine},
+        constants::START_POS,
+    };
+
+    // Reference keys from the Polyglot book format specification
+    const GAME: [(&str, &str, u64); 8] = [
+        (
+            "e2e4",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            0x823C9B50FD114196,
+        ),
+        (
+            "d7d5",
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            0x0756B94461C50FB0,
+        ),
+        (
+            "e4e5",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+            0x662FAFB965DB29D4,
+        ),
+        (
+            "f7f5",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            0x22A48B5A8E47FF78,
+        ),
+        (
+            "e1e2",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+            0x652A607CA3F242C1,
+        ),
+        (
+            "e8f7",
+            "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+            0x00FDD303C946BDD9,
+        ),
+        (
+            "",
+            "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+            0x3C8123EA7B067637,
+        ),
+        (
+            "",
+            "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+            0x5C3F9B829B279560,
+        ),
+    ];
+
+    #[test]
+    fn start_position_key() {
+        let position = FenParser::fen_to_position(START_POS);
+        assert_eq!(position.polyglot_key(), 0x463B96181691FC9C);
+    }
+
+    #[test]
+    fn reference_keys_from_fen() {
+        for (_, fen, key) in GAME {
+            assert_eq!(FenParser::fen_to_position(fen).polyglot_key(), key, "{fen}");
+        }
+    }
+
+    #[test]
+    fn reference_keys_after_moves() {
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        for (m, fen, key) in GAME.iter().filter(|(m, _, _)| !m.is_empty()) {
+            sut.apply_algebraic_move(m);
+            assert_eq!(sut.position.polyglot_key(), *key, "{fen}");
+        }
+
+        let mut sut = Engine::from_position(FenParser::fen_to_position(START_POS));
+        for m in ["a2a4", "b7b5", "h2h4", "b5b4", "c2c4"] {
+            sut.apply_algebraic_move(m);
+        }
+        assert_eq!(sut.position.polyglot_key(), GAME[6].2);
+        sut.apply_algebraic_move("b4c3");
+        sut.apply_algebraic_move("a1a3");
+        assert_eq!(sut.position.polyglot_key(), GAME[7].2);
+    }
+
+    #[test]
+    fn en_passant_only_counts_when_capturable() {
+        // After e4 nothing can take on e3, so the key is the same as without the square
+        let with_square = FenParser::fen_to_position(GAME[0].1);
+        let without = FenParser::fen_to_position(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+        );
+        assert_eq!(with_square.polyglot_key(), without.polyglot_key());
+    }
 }
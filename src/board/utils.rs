@@ -1,5 +1,4 @@
 use std::{
-    cmp,
     sync::{Arc, Mutex},
     thread::{self, JoinHandle},
 };
@@ -63,28 +62,40 @@ pub fn algebraic_to_move(alg_string: &str, pos: &Position) -> Move {
         return Move::EnPassant(from, to);
     }
     if (from & pos.board.pieces[pos.side_to_move.0][Piece::KING].0) > 0 {
-        if (from & Square::E1 > 0) && (to & Square::G1 > 0) {
-            return Move::Castle(Castling::WHITE_KING_SIDE);
-        }
-        if (from & Square::E1 > 0) && (to & Square::C1 > 0) {
-            return Move::Castle(Castling::WHITE_QUEEN_SIDE);
-        }
-        if (from & Square::E8 > 0) && (to & Square::G8 > 0) {
-            return Move::Castle(Castling::BLACK_KING_SIDE);
-        }
-        if (from & Square::E8 > 0) && (to & Square::C8 > 0) {
-            return Move::Castle(Castling::BLACK_QUEEN_SIDE);
+        // The king takes its own rook, or goes two squares in standard chess
+        for castling in [
+            Castling::king_side(pos.side_to_move.0),
+            Castling::queen_side(pos.side_to_move.0),
+        ] {
+            let (king_from, king_to, rook_from, _) = pos.castling_squares(castling);
+            if pos.state.castling.0 & castling > 0
+                && from == king_from
+                && (to == rook_from || (!pos.is_chess960 && to == king_to))
+            {
+                return Move::Castle(castling);
+            }
         }
     }
     Move::Normal(from, to)
 }
 
+// The move in long algebraic notation, castles are the king taking its own rook in Chess960
+pub fn move_to_algebraic(m: &MoveInfo, pos: &Position) -> String {
+    match m.m {
+        Move::Castle(castling) if pos.is_chess960 => {
+            let (king_from, _, rook_from, _) = pos.castling_squares(castling);
+            format!("{}{}", Square(king_from), Square(rook_from))
+        }
+        _ => format!("{m}"),
+    }
+}
+
 // Execute perft on a given position
 pub fn perft(e: &mut Engine, index: u8, first: bool, show_moves: bool, parallel: bool) -> usize {
     if index == 0 {
         return 0;
     }
-    let mut moves = MoveGenerator::get_ordered_moves_by_kind(e, MoveGenKind::All);
+    let moves = MoveGenerator::get_ordered_moves_by_kind(e, MoveGenKind::All);
     if index == 1 {
         moves.len()
     } else {
@@ -98,12 +109,8 @@ pub fn perft(e: &mut Engine, index: u8, first: bool, show_moves: bool, parallel:
             } else {
                 tot_moves / cpus
             };
-            loop {
-                let actual_length = cmp::min(moves.len(), batch_length);
-                let thread_moves: Vec<MoveInfo> = moves.drain(0..actual_length).collect();
-                if thread_moves.is_empty() {
-                    break;
-                }
+            for thread_moves in moves.chunks(batch_length) {
+                let thread_moves: Vec<MoveInfo> = thread_moves.to_vec();
                 let new_e = e.clone();
                 let tot = Arc::clone(&tot);
                 threads.push(thread::spawn(move || {
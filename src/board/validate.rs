@@ -0,0 +1,95 @@
+use crate::movegen::generator::MoveGenerator;
+
+use super::models::{Castling, Piece, Position, Side, Square};
+
+// Everything that makes a position impossible to reach in a real game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    MissingKing(Side),
+    TooManyKings(Side),
+    PawnsOnBackRank,
+    // The same square is taken by more than one piece, or the side bitboards are out of sync
+    OverlappingPieces,
+    // The castling right is there, but the king or the rook is not on its starting square
+    InvalidCastlingRights(Castling),
+    // No pawn just moved two squares to justify the en passant square
+    InvalidEnPassant(Square),
+    // The side that just moved left its own king in check
+    OpponentInCheck,
+}
+impl std::error::Error for PositionError {}
+
+impl Position {
+    // Check that the position could actually happen in a game
+    pub fn validate(&self) -> Result<(), PositionError> {
+        self.validate_structure()?;
+        if MoveGenerator::is_position_check(self) {
+            return Err(PositionError::OpponentInCheck);
+        }
+        Ok(())
+    }
+
+    // The checks that must hold after every move, even pseudo-legal ones
+    pub fn validate_structure(&self) -> Result<(), PositionError> {
+        for side in [Side::WHITE, Side::BLACK] {
+            match self.board.pieces[side][Piece::KING].0.count_ones() {
+                0 => return Err(PositionError::MissingKing(Side(side))),
+                1 => {}
+                _ => return Err(PositionError::TooManyKings(Side(side))),
+            }
+        }
+
+        let mut all_pieces = 0;
+        for side in [Side::WHITE, Side::BLACK] {
+            let mut side_pieces = 0;
+            for piece_board in self.board.pieces[side].iter() {
+                if piece_board.0 & all_pieces != 0 {
+                    return Err(PositionError::OverlappingPieces);
+                }
+                all_pieces |= piece_board.0;
+                side_pieces |= piece_board.0;
+            }
+            if side_pieces != self.board.side_pieces[side].0 {
+                return Err(PositionError::OverlappingPieces);
+            }
+        }
+
+        let pawns = self.board.pieces[Side::WHITE][Piece::PAWN].0
+            | self.board.pieces[Side::BLACK][Piece::PAWN].0;
+        if pawns & Square::EXTREME_ROWS != 0 {
+            return Err(PositionError::PawnsOnBackRank);
+        }
+
+        for castling in Castling::FLAGS {
+            let side = Castling::side(castling);
+            let (king_square, _, rook_square, _) = self.castling_squares(castling);
+            // The king side rook is on the h file side of the king
+            let is_king_side = castling & (Castling::WHITE_KING_SIDE | Castling::BLACK_KING_SIDE) > 0;
+            if self.state.castling.0 & castling != 0
+                && (self.board.pieces[side][Piece::KING].0 & king_square == 0
+                    || self.board.pieces[side][Piece::ROOK].0 & rook_square == 0
+                    || (rook_square > king_square) != is_king_side)
+            {
+                return Err(PositionError::InvalidCastlingRights(Castling(castling)));
+            }
+        }
+
+        let en_passant = self.state.en_passant.0;
+        if en_passant != Square::NONE {
+            // The pawn is in front of the en passant square, the square it came from is empty
+            let (pawn_square, from_square) = if self.side_to_move.0 == Side::WHITE {
+                (en_passant >> 8, en_passant << 8)
+            } else {
+                (en_passant << 8, en_passant >> 8)
+            };
+            let occupancy = self.board.side_pieces[Side::WHITE].0 | self.board.side_pieces[Side::BLACK].0;
+            if self.board.pieces[self.opposite_side()][Piece::PAWN].0 & pawn_square == 0
+                || occupancy & (en_passant | from_square) != 0
+            {
+                return Err(PositionError::InvalidEnPassant(Square(en_passant)));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
 
@@ -9,13 +7,11 @@ const RNG_SEED: [u8; 32] = [93; 32];
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct ZobristValue {
     pub hash: u64,
-    pub prev: Option<Arc<ZobristValue>>,
 }
 impl ZobristValue {
     pub fn empty() -> ZobristValue {
         ZobristValue {
             hash: 0,
-            prev: None,
         }
     }
 }
@@ -47,28 +43,24 @@ impl ZobristHashes {
     pub fn piece(&self, side: usize, piece: usize, square: usize) -> ZobristValue {
         ZobristValue {
             hash: self.pieces[side][piece][square],
-            prev: None,
         }
     }
 
     pub fn castling(&self, castling_permissions: usize) -> ZobristValue {
         ZobristValue {
             hash: self.castling[castling_permissions],
-            prev: None,
         }
     }
 
     pub fn side(&self, side: usize) -> ZobristValue {
         ZobristValue {
             hash: self.side[side],
-            prev: None,
         }
     }
 
     pub fn en_passant(&self, en_passant: usize) -> ZobristValue {
         ZobristValue {
             hash: self.enpassant[en_passant],
-            prev: None,
         }
     }
 }
@@ -0,0 +1,121 @@
+use rustc_hash::FxHashMap;
+
+use crate::{board::models::Side, pgn::reader::PgnGame};
+
+use super::reader::{encode_move, Book, BookEntry};
+
+pub const DEFAULT_MAX_PLY: usize = 24;
+pub const DEFAULT_MIN_GAMES: usize = 5;
+
+// How a move did in the games it was played in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MoveStats {
+    pub games: u32,
+    // Two for a win of the side that played it, one for a draw
+    pub points: u32,
+}
+
+// Collects the moves played in a set of games, to write them as a Polyglot book
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+    // Only the first moves of each game go in the book
+    pub max_ply: usize,
+    // Moves played in fewer games are left out
+    pub min_games: usize,
+    // Games are left out unless both players have a WhiteElo/BlackElo tag at least this high
+    pub min_rating: Option<usize>,
+    stats: FxHashMap<u64, FxHashMap<u16, MoveStats>>,
+    // Games added and games left out by the filters or for having no result
+    pub games: usize,
+    pub skipped: usize,
+}
+impl BookBuilder {
+    pub fn new(max_ply: usize, min_games: usize) -> BookBuilder {
+        BookBuilder {
+            max_ply,
+            min_games,
+            min_rating: None,
+            stats: FxHashMap::default(),
+            games: 0,
+            skipped: 0,
+        }
+    }
+
+    // Replays the game, counting its result for every move up to max_ply
+    pub fn add_game(&mut self, game: &PgnGame) -> bool {
+        let winner = match game.result.as_str() {
+            "1-0" => Some(Side::WHITE),
+            "0-1" => Some(Side::BLACK),
+            "1/2-1/2" => None,
+            _ => {
+                self.skipped += 1;
+                return false;
+            }
+        };
+        if !self.is_rated_enough(game) {
+            self.skipped += 1;
+            return false;
+        }
+
+        let mut position = game.start.clone();
+        for pgn_move in game.moves.iter().take(self.max_ply) {
+            let points = match winner {
+                Some(side) if side == position.side_to_move.0 => 2,
+                Some(_) => 0,
+                None => 1,
+            };
+            let stats = self
+                .stats
+                .entry(position.polyglot_key())
+                .or_default()
+                .entry(encode_move(&pgn_move.m, &position))
+                .or_default();
+            stats.games += 1;
+            stats.points += points;
+            position.apply_move(&pgn_move.m);
+        }
+        self.games += 1;
+        true
+    }
+
+    fn is_rated_enough(&self, game: &PgnGame) -> bool {
+        let Some(min_rating) = self.min_rating else {
+            return true;
+        };
+        ["WhiteElo", "BlackElo"].iter().all(|tag| {
+            game.tag(tag)
+                .and_then(|rating| rating.trim().parse::<usize>().ok())
+                .is_some_and(|rating| rating >= min_rating)
+        })
+    }
+
+    // How a move did so far, if it was ever played in the position
+    pub fn stats(&self, key: u64, m: u16) -> Option<MoveStats> {
+        self.stats.get(&key)?.get(&m).copied()
+    }
+
+    // The moves played often enough, weighted by their points. Moves that never scored are
+    // left out, they would never be picked
+    pub fn build(&self) -> Book {
+        let mut entries = vec![];
+        for (key, moves) in &self.stats {
+            let moves: Vec<(u16, u32)> = moves
+                .iter()
+                .filter(|(_, stats)| stats.games as usize >= self.min_games && stats.points > 0)
+                .map(|(m, stats)| (*m, stats.points))
+                .collect();
+            // Weights are 16 bits, scale them all down the same if one doesn't fit
+            let max = moves.iter().map(|(_, points)| *points).max().unwrap_or(0);
+            let scale = max.div_ceil(u16::MAX as u32).max(1);
+            entries.extend(moves.into_iter().map(|(m, points)| BookEntry {
+                key: *key,
+                m,
+                weight: (points / scale).max(1) as u16,
+                learn: 0,
+            }));
+        }
+        // Heaviest first within a position, like other Polyglot books
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.m.cmp(&b.m)));
+        Book::from_entries(entries)
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fs, io, sync::Arc};
+
+use rand::Rng;
+
+use crate::{
+    board::models::{Move, Position},
+    movegen::generator::{MoveGenKind, MoveGenerator, MoveInfo},
+};
+
+// Every entry is 16 big endian bytes: key, move, weight and learn
+pub const ENTRY_SIZE: usize = 16;
+
+pub const DEFAULT_BOOK_DEPTH: usize = 20;
+pub const MAX_BOOK_DEPTH: usize = 100;
+
+// Polyglot promotion pieces, indexed by Piece: none, knight, bishop, rook, queen
+const PROMOTION_PIECE: [u16; 6] = [0, 2, 1, 3, 4, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub m: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+impl BookEntry {
+    pub fn from_bytes(bytes: &[u8]) -> BookEntry {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            m: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.m.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+// A Polyglot opening book, the entries sorted by the key of their position
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+impl Book {
+    pub fn open(path: &str) -> io::Result<Book> {
+        Ok(Self::from_bytes(&fs::read(path)?))
+    }
+
+    // Books should already be sorted, but the search relies on it. A trailing partial entry is dropped
+    pub fn from_bytes(bytes: &[u8]) -> Book {
+        let mut entries: Vec<BookEntry> =
+            bytes.chunks_exact(ENTRY_SIZE).map(BookEntry::from_bytes).collect();
+        entries.sort_by_key(|entry| entry.key);
+        Book { entries }
+    }
+
+    pub fn from_entries(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by_key(|entry| entry.key);
+        Book { entries }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(|entry| entry.to_bytes()).collect()
+    }
+
+    // Both books in one, the weights of a move found in both are added up
+    pub fn merge(&self, other: &Book) -> Book {
+        let mut entries: Vec<BookEntry> =
+            self.entries.iter().chain(other.entries.iter()).copied().collect();
+        entries.sort_by_key(|entry| (entry.key, entry.m));
+        entries.dedup_by(|entry, kept| {
+            let same = entry.key == kept.key && entry.m == kept.m;
+            if same {
+                kept.weight = kept.weight.saturating_add(entry.weight);
+            }
+            same
+        });
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Book { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // All the entries for a position, found with a binary search
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = start + self.entries[start..].partition_point(|entry| entry.key == key);
+        &self.entries[start..end]
+    }
+
+    // The book moves that are legal in the position, with their weights
+    pub fn moves(&self, position: &mut Position) -> Vec<(MoveInfo, u16)> {
+        let entries = self.entries(position.polyglot_key());
+        if entries.is_empty() {
+            return vec![];
+        }
+        let legal_moves = MoveGenerator::get_legal_moves(position, &MoveGenKind::All);
+        entries
+            .iter()
+            .filter_map(|entry| {
+                legal_moves
+                    .iter()
+                    .find(|m| encode_move(m, position) == entry.m)
+                    .map(|m| (m.clone(), entry.weight))
+            })
+            .collect()
+    }
+
+    // The heaviest move, or with variety a random one, more likely the heavier it is
+    pub fn pick_move(&self, position: &mut Position, variety: bool) -> Option<MoveInfo> {
+        let moves = self.moves(position);
+        let total: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+        if variety && total > 0 {
+            let mut pick = rand::thread_rng().gen_range(0..total);
+            for (m, weight) in moves {
+                if pick < weight as u32 {
+                    return Some(m);
+                }
+                pick -= weight as u32;
+            }
+            return None;
+        }
+        moves
+            .into_iter()
+            .reduce(|best, m| if m.1 > best.1 { m } else { best })
+            .map(|(m, _)| m)
+    }
+}
+
+// The move as Polyglot stores it: the to square in bits 0-5, the from square in bits 6-11
+// and the promotion piece in bits 12-14. Castles are the king taking its own rook
+pub fn encode_move(m: &MoveInfo, position: &Position) -> u16 {
+    let (from, to, promotion) = match m.m {
+        Move::Normal(from, to) | Move::EnPassant(from, to) => (from, to, 0),
+        Move::Promotion(from, to, piece) => (from, to, PROMOTION_PIECE[piece]),
+        Move::Castle(castling) => {
+            let (king_from, _, rook_from, _) = position.castling_squares(castling);
+            (king_from, rook_from, 0)
+        }
+    };
+    to.trailing_zeros() as u16 | (from.trailing_zeros() as u16) << 6 | promotion << 12
+}
+
+// The OwnBook UCI options, the book is only read while OwnBook is on
+#[derive(Debug, Clone)]
+pub struct BookSettings {
+    pub own_book: bool,
+    pub file: String,
+    // Full moves into the game the book is used for
+    pub depth: usize,
+    // Pick book moves at random by weight instead of always the heaviest
+    pub variety: bool,
+    pub book: Option<Arc<Book>>,
+}
+impl BookSettings {
+    pub fn new() -> BookSettings {
+        BookSettings {
+            own_book: false,
+            file: String::new(),
+            depth: DEFAULT_BOOK_DEPTH,
+            variety: false,
+            book: None,
+        }
+    }
+
+    // Reads the book file again after OwnBook or BookFile change
+    pub fn load(&mut self) -> io::Result<()> {
+        self.book = None;
+        if self.own_book && !self.file.is_empty() {
+            self.book = Some(Arc::new(Book::open(&self.file)?));
+        }
+        Ok(())
+    }
+
+    // The book move to play, if there is one and the game is still young enough
+    pub fn probe(&self, position: &mut Position) -> Option<MoveInfo> {
+        let book = self.book.as_ref()?;
+        if !self.own_book || position.half_move_number >= 2 * self.depth {
+            return None;
+        }
+        book.pick_move(position, self.variety)
+    }
+}
+
+impl Default for BookSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod reader_tests {
+    use std::sync::Arc;
+
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Castling, Engine, Move, Piece, Square},
+        },
+        book::reader::{Book, BookEntry, BookSettings},
+        constants::START_POS,
+        evaluate::evaluator::Evaluate,
+        uci::options::GoOptions,
+    };
+
+    // Polyglot moves, from square << 6 | to square
+    const E2E4: u16 = 12 << 6 | 28;
+    const D2D4: u16 = 11 << 6 | 27;
+    const E1H1: u16 = 4 << 6 | 7;
+    // And the queen in the promotion bits
+    const E7E8Q: u16 = 4 << 12 | 52 << 6 | 60;
+
+    fn entry(fen: &str, m: u16, weight: u16) -> BookEntry {
+        BookEntry {
+            key: FenParser::fen_to_position(fen).polyglot_key(),
+            m,
+            weight,
+            learn: 0,
+        }
+    }
+
+    fn start_book() -> Book {
+        Book::from_entries(vec![
+            entry("4k3/8/8/8/8/8/8/4K3 w - - 0 1", E2E4, 1),
+            entry(START_POS, D2D4, 5),
+            entry(START_POS, E2E4, 10),
+            entry("8/8/8/8/8/8/8/K6k w - - 0 1", D2D4, 1),
+        ])
+    }
+
+    #[test]
+    fn finds_the_entries_of_a_position() {
+        let book = start_book();
+        let key = FenParser::fen_to_position(START_POS).polyglot_key();
+        let entries = book.entries(key);
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.key == key));
+        assert!(book.entries(key ^ 1).is_empty());
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let book = start_book();
+        let bytes = book.to_bytes();
+        assert_eq!(bytes.len(), 4 * 16);
+        // Keys, moves and weights are big endian
+        assert_eq!(&bytes[24..28], &[0x03, 0x1C, 0x00, 0x0A][..]);
+        assert_eq!(Book::from_bytes(&bytes), book);
+        // A truncated entry is dropped
+        assert_eq!(Book::from_bytes(&bytes[..40]).len(), 2);
+    }
+
+    #[test]
+    fn picks_the_heaviest_move() {
+        let mut position = FenParser::fen_to_position(START_POS);
+        let m = start_book().pick_move(&mut position, false).unwrap();
+        assert_eq!(m.m, Move::Normal(Square::E2, Square::E4));
+    }
+
+    #[test]
+    fn variety_only_picks_weighted_moves() {
+        let book = Book::from_entries(vec![entry(START_POS, D2D4, 0), entry(START_POS, E2E4, 3)]);
+        let mut position = FenParser::fen_to_position(START_POS);
+        for _ in 0..20 {
+            let m = book.pick_move(&mut position, true).unwrap();
+            assert_eq!(m.m, Move::Normal(Square::E2, Square::E4));
+        }
+    }
+
+    #[test]
+    fn castles_are_the_king_taking_its_rook() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let book = Book::from_entries(vec![entry(fen, E1H1, 1)]);
+        let m = book.pick_move(&mut FenParser::fen_to_position(fen), false).unwrap();
+        assert_eq!(m.m, Move::Castle(Castling::WHITE_KING_SIDE));
+    }
+
+    #[test]
+    fn decodes_promotions() {
+        let fen = "8/4P3/8/8/8/8/k7/4K3 w - - 0 1";
+        let book = Book::from_entries(vec![entry(fen, E7E8Q, 1)]);
+        let m = book.pick_move(&mut FenParser::fen_to_position(fen), false).unwrap();
+        assert_eq!(m.m, Move::Promotion(Square::E7, Square::E8, Piece::QUEEN));
+    }
+
+    #[test]
+    fn illegal_book_moves_are_skipped() {
+        let fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+        let book = Book::from_entries(vec![entry(fen, E2E4, 1)]);
+        assert!(book.pick_move(&mut FenParser::fen_to_position(fen), false).is_none());
+    }
+
+    #[test]
+    fn search_plays_book_moves_until_the_depth() {
+        let mut engine = Engine::from_position(FenParser::fen_to_position(START_POS));
+        engine.is_quiet = true;
+        engine.book = BookSettings {
+            own_book: true,
+            depth: 1,
+            book: Some(Arc::new(start_book())),
+            ..BookSettings::new()
+        };
+        Evaluate::search(&mut engine, GoOptions::depth(3), None);
+        assert_eq!(engine.current_best_move.as_ref().unwrap().m, Move::Normal(Square::E2, Square::E4));
+        assert_eq!(engine.nodes, 0);
+
+        // Past the book depth the engine searches
+        engine.position.half_move_number = 2;
+        Evaluate::search(&mut engine, GoOptions::depth(3), None);
+        assert!(engine.nodes > 0);
+
+        engine.position.half_move_number = 0;
+        engine.book.own_book = false;
+        Evaluate::search(&mut engine, GoOptions::depth(3), None);
+        assert!(engine.nodes > 0);
+    }
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use std::io::Cursor;
+
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Move, Square},
+        },
+        book::{
+            builder::{BookBuilder, MoveStats},
+            reader::{Book, BookEntry},
+        },
+        constants::START_POS,
+        pgn::reader::{PgnGame, PgnReader},
+    };
+
+    const E2E4: u16 = 12 << 6 | 28;
+    const D2D4: u16 = 11 << 6 | 27;
+
+    const GAMES: &str = r#"[White "A"]
+[Black "B"]
+[WhiteElo "2400"]
+[BlackElo "2300"]
+
+1. e4 e5 2. Nf3 1-0
+
+[White "B"]
+[Black "A"]
+[WhiteElo "2300"]
+[BlackElo "2400"]
+
+1. e4 c5 0-1
+
+[White "C"]
+[Black "D"]
+
+1. d4 d5 1/2-1/2
+
+[White "C"]
+[Black "D"]
+
+1. d4 d5 *
+"#;
+
+    fn games() -> Vec<PgnGame> {
+        PgnReader::new(Cursor::new(GAMES)).map(|game| game.unwrap()).collect()
+    }
+
+    fn start_key() -> u64 {
+        FenParser::fen_to_position(START_POS).polyglot_key()
+    }
+
+    #[test]
+    fn weights_moves_by_result() {
+        let mut builder = BookBuilder::new(24, 1);
+        for game in games() {
+            builder.add_game(&game);
+        }
+        // The unfinished game doesn't count
+        assert_eq!((builder.games, builder.skipped), (3, 1));
+        assert_eq!(builder.stats(start_key(), E2E4), Some(MoveStats { games: 2, points: 2 }));
+        assert_eq!(builder.stats(start_key(), D2D4), Some(MoveStats { games: 1, points: 1 }));
+
+        let book = builder.build();
+        let entries = book.entries(start_key());
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].m, entries[0].weight), (E2E4, 2));
+        assert_eq!((entries[1].m, entries[1].weight), (D2D4, 1));
+        // 1... e5 only lost, so it is left out
+        let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let mut position = FenParser::fen_to_position(after_e4);
+        let m = book.pick_move(&mut position, false).unwrap();
+        assert_eq!(m.m, Move::Normal(Square::C7, Square::C5));
+        assert_eq!(book.entries(position.polyglot_key()).len(), 1);
+    }
+
+    #[test]
+    fn leaves_out_rare_moves_and_late_plies() {
+        let mut builder = BookBuilder::new(1, 2);
+        for game in games() {
+            builder.add_game(&game);
+        }
+        let book = builder.build();
+        assert_eq!(book.len(), 1);
+        assert_eq!(book.entries(start_key())[0].m, E2E4);
+    }
+
+    #[test]
+    fn filters_by_rating() {
+        let mut builder = BookBuilder::new(24, 1);
+        builder.min_rating = Some(2300);
+        for game in games() {
+            builder.add_game(&game);
+        }
+        // Games without ratings are left out too
+        assert_eq!((builder.games, builder.skipped), (2, 2));
+        assert_eq!(builder.stats(start_key(), D2D4), None);
+
+        builder = BookBuilder::new(24, 1);
+        builder.min_rating = Some(2350);
+        for game in games() {
+            builder.add_game(&game);
+        }
+        assert_eq!(builder.games, 0);
+    }
+
+    #[test]
+    fn merges_into_an_existing_book() {
+        let mut builder = BookBuilder::new(24, 1);
+        for game in games() {
+            builder.add_game(&game);
+        }
+        let existing = Book::from_entries(vec![
+            BookEntry { key: start_key(), m: D2D4, weight: 5, learn: 0 },
+            BookEntry { key: 1, m: E2E4, weight: u16::MAX, learn: 0 },
+            BookEntry { key: 1, m: D2D4, weight: 1, learn: 0 },
+        ]);
+        let merged = builder.build().merge(&existing);
+        let entries = merged.entries(start_key());
+        assert_eq!((entries[0].m, entries[0].weight), (D2D4, 6));
+        assert_eq!((entries[1].m, entries[1].weight), (E2E4, 2));
+        assert_eq!(merged.entries(1).len(), 2);
+        // Merging a book into itself doesn't overflow
+        let doubled = existing.merge(&existing);
+        assert_eq!(doubled.entries(1)[0].weight, u16::MAX);
+        assert_eq!(doubled.entries(1)[1].weight, 2);
+    }
+}
@@ -6,8 +6,12 @@ pub const START_POS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq
 pub const SILENT_MOVE_VALUE: isize = 1;
 // Value of a possibility to capture
 pub const CAPTURE_VALUE: isize = 2;
+// No search goes deeper than this many plies from the root
+pub const MAX_PLY: usize = 128;
 // Mate value (not max size because it may cause bugs)
 pub const MATE_VALUE: isize = 9999999;
+// Won tablebase endings score below the mates, they are not a mate in so many moves
+pub const TB_WIN_VALUE: isize = MATE_VALUE - 500;
 // Pieces value (https://www.chessprogramming.org/Simplified_Evaluation_Function)
 pub const KING_VALUE: isize = 9999999;
 pub const QUEEN_VALUE: isize = 900;
@@ -15,5 +19,14 @@ pub const ROOK_VALUE: isize = 500;
 pub const BISHOP_VALUE: isize = 333;
 pub const KNIGHT_VALUE: isize = 323;
 pub const PAWN_VALUE: isize = 100;
+// The same values in Piece order
+pub const PIECE_VALUES: [isize; 6] = [
+    PAWN_VALUE,
+    BISHOP_VALUE,
+    KNIGHT_VALUE,
+    ROOK_VALUE,
+    QUEEN_VALUE,
+    KING_VALUE,
+];
 // Pattern values
 pub const BISHOP_PAIR_VALUE: isize = 5;
\ No newline at end of file
@@ -0,0 +1,3 @@
+pub mod table;
+pub mod test;
+pub mod unmove;
@@ -0,0 +1,359 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once, OnceLock,
+    },
+    thread,
+};
+
+use crate::{
+    board::models::{Castling, Piece, Position, Side},
+    constants::MATE_VALUE,
+    movegen::generator::{MoveGenKind, MoveGenerator, MoveInfo},
+};
+
+use super::unmove::{Setup, STRONG_KING, WEAK_KING};
+
+// Table values, anything below these is the number of plies to mate
+const DRAW: u8 = u8::MAX;
+const ILLEGAL: u8 = u8::MAX - 1;
+const UNKNOWN: u8 = u8::MAX - 2;
+
+// Whether searches start solving the tables they find missing
+static SOLVE_ON_DEMAND: AtomicBool = AtomicBool::new(false);
+
+// The endings that can be solved, a king and some pieces against a lone king
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Material {
+    KQK,
+    KRK,
+    KPK,
+    KBNK,
+}
+impl Material {
+    pub const ALL: [Material; 4] = [Material::KQK, Material::KRK, Material::KPK, Material::KBNK];
+
+    // The pieces of the strong side besides its king
+    pub fn pieces(self) -> &'static [usize] {
+        match self {
+            Material::KQK => &[Piece::QUEEN],
+            Material::KRK => &[Piece::ROOK],
+            Material::KPK => &[Piece::PAWN],
+            Material::KBNK => &[Piece::BISHOP, Piece::KNIGHT],
+        }
+    }
+
+    // The ending on the board, along with its strong side
+    pub fn of(pos: &Position) -> Option<(Material, usize)> {
+        let board = &pos.board;
+        if (board.side_pieces[Side::WHITE].0 | board.side_pieces[Side::BLACK].0).count_ones() > 4 {
+            return None;
+        }
+        let strong = if board.side_pieces[Side::WHITE].0.count_ones() > 1 {
+            Side::WHITE
+        } else {
+            Side::BLACK
+        };
+        if board.side_pieces[1 - strong].0.count_ones() != 1 {
+            return None;
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|material| {
+                let mut expected = [0; 6];
+                expected[Piece::KING] = 1;
+                for piece in material.pieces() {
+                    expected[*piece] += 1;
+                }
+                (0..6).all(|piece| board.pieces[strong][piece].0.count_ones() == expected[piece])
+            })
+            .map(|material| (material, strong))
+    }
+
+    fn count(self) -> usize {
+        self.pieces().len() + 2
+    }
+}
+
+// The result of a solved position for the side to move, with the plies to mate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dtm {
+    Win(usize),
+    Loss(usize),
+    Draw,
+}
+impl Dtm {
+    // The result for the side that moved into this position
+    pub fn parent(self) -> Dtm {
+        match self {
+            Dtm::Win(plies) => Dtm::Loss(plies + 1),
+            Dtm::Loss(plies) => Dtm::Win(plies + 1),
+            Dtm::Draw => Dtm::Draw,
+        }
+    }
+
+    // The search score of the result, as seen `ply` plies away from the root
+    pub fn score(self, ply: usize) -> isize {
+        match self {
+            Dtm::Win(plies) => MATE_VALUE - (ply + plies) as isize,
+            Dtm::Loss(plies) => -(MATE_VALUE - (ply + plies) as isize),
+            Dtm::Draw => 0,
+        }
+    }
+}
+
+// Distance to mate of every placement of the pieces, for both sides to move
+pub struct EndgameTable {
+    pub material: Material,
+    dtm: Vec<u8>,
+}
+impl EndgameTable {
+    // Tables are solved the first time they are needed and kept for the whole run
+    pub fn get(material: Material) -> &'static EndgameTable {
+        Self::tables()[material as usize].get_or_init(|| Self::generate(material))
+    }
+
+    fn tables() -> &'static [OnceLock<EndgameTable>; 4] {
+        static TABLES: [OnceLock<EndgameTable>; 4] = [
+            OnceLock::new(),
+            OnceLock::new(),
+            OnceLock::new(),
+            OnceLock::new(),
+        ];
+        &TABLES
+    }
+
+    pub fn generate(material: Material) -> EndgameTable {
+        let pieces = material.pieces();
+        let count = material.count();
+        let half = 1 << (6 * count);
+        let mut dtm = vec![UNKNOWN; 2 * half];
+        // Moves the weak king still has that don't lose
+        let mut escapes = vec![0u8; half];
+
+        // Mates, stalemates and positions where the weak king takes a piece
+        let mut longest = 0;
+        for (index, value) in dtm.iter_mut().enumerate() {
+            let setup = setup(index, count);
+            if !setup.is_legal(pieces) {
+                *value = ILLEGAL;
+            } else if setup.strong_to_move {
+                if material == Material::KPK {
+                    if let Some(plies) = promotion_plies(&setup) {
+                        *value = plies;
+                        longest = longest.max(plies);
+                    }
+                }
+            } else {
+                match setup.weak_moves(pieces) {
+                    (_, true) => *value = DRAW,
+                    (0, false) if setup.in_check(pieces) => *value = 0,
+                    (0, false) => *value = DRAW,
+                    (moves, false) => escapes[index - half] = moves,
+                }
+            }
+        }
+
+        // Walk back from the mates one ply at a time
+        let mut plies = 0;
+        loop {
+            let mut found = false;
+
+            // Every strong move into a lost position wins
+            for index in half..2 * half {
+                if dtm[index] != plies {
+                    continue;
+                }
+                found = true;
+                setup(index, count).strong_unmoves(pieces, |prev| {
+                    let prev_index = self::index(&prev, count);
+                    // Also shortens the wins found through promotions
+                    if dtm[prev_index] > plies + 1 {
+                        dtm[prev_index] = plies + 1;
+                    }
+                });
+            }
+
+            // The weak side is lost once all its moves run into won positions
+            for index in 0..half {
+                if dtm[index] != plies + 1 {
+                    continue;
+                }
+                found = true;
+                setup(index, count).weak_unmoves(pieces, |prev| {
+                    let prev_index = self::index(&prev, count);
+                    if dtm[prev_index] == UNKNOWN {
+                        escapes[prev_index - half] -= 1;
+                        if escapes[prev_index - half] == 0 {
+                            dtm[prev_index] = plies + 2;
+                        }
+                    }
+                });
+            }
+
+            if !found && plies >= longest {
+                break;
+            }
+            plies += 2;
+        }
+
+        // Whatever wasn't reached can be held forever
+        for value in dtm.iter_mut().filter(|value| **value == UNKNOWN) {
+            *value = DRAW;
+        }
+        EndgameTable { material, dtm }
+    }
+
+    // The most plies the strong side needs to mate
+    pub fn longest_win(&self) -> usize {
+        let half = self.dtm.len() / 2;
+        self.dtm[..half]
+            .iter()
+            .filter(|value| **value < UNKNOWN)
+            .max()
+            .map_or(0, |plies| *plies as usize)
+    }
+
+    pub fn probe_setup(&self, setup: &Setup) -> Option<Dtm> {
+        match self.dtm[index(setup, self.material.count())] {
+            ILLEGAL => None,
+            DRAW => Some(Dtm::Draw),
+            plies if setup.strong_to_move => Some(Dtm::Win(plies as usize)),
+            plies => Some(Dtm::Loss(plies as usize)),
+        }
+    }
+
+    // The table if it has already been solved
+    pub fn solved(material: Material) -> Option<&'static EndgameTable> {
+        Self::tables()[material as usize].get()
+    }
+
+    pub fn probe(pos: &Position) -> Option<Dtm> {
+        let (material, strong) = Material::of(pos)?;
+        // Castling can't happen with a lone king, but keep the tables out of odd positions
+        if pos.state.castling.0 != Castling::NO_CASTLING {
+            return None;
+        }
+        Self::get(material).probe_setup(&position_setup(pos, material, strong))
+    }
+
+    // From now on a table is solved the first time a search runs into its ending, so the
+    // endings that never come up cost nothing
+    pub fn solve_on_demand() {
+        SOLVE_ON_DEMAND.store(true, Ordering::Relaxed);
+    }
+
+    // Solves the table on a thread of its own, only the first call for it starts one
+    pub fn solve_in_background(material: Material) {
+        static STARTED: [Once; 4] = [Once::new(), Once::new(), Once::new(), Once::new()];
+        STARTED[material as usize].call_once(|| {
+            thread::spawn(move || {
+                Self::get(material);
+            });
+        });
+    }
+
+    // Solves the three piece tables, which only take a moment
+    pub fn solve_small() {
+        for material in Material::ALL {
+            if material.count() == 3 {
+                Self::get(material);
+            }
+        }
+    }
+
+    // Like probe, but only with the tables solved beforehand, solving one would eat the time
+    // of the search. With solve_on_demand, a missing one is solved for later searches.
+    // Results the fifty move rule could overturn are left to the search
+    pub fn probe_in_search(pos: &Position) -> Option<Dtm> {
+        let (material, _) = Material::of(pos)?;
+        if Self::solved(material).is_none() {
+            if SOLVE_ON_DEMAND.load(Ordering::Relaxed) {
+                Self::solve_in_background(material);
+            }
+            return None;
+        }
+        match Self::probe(pos)? {
+            Dtm::Win(plies) | Dtm::Loss(plies) if pos.state.halfmove_clock + plies > 100 => None,
+            dtm => Some(dtm),
+        }
+    }
+
+    // The move keeping the best result in a solved position, if probe_in_search knows it
+    pub fn best_move(pos: &Position) -> Option<(MoveInfo, Dtm)> {
+        Self::probe_in_search(pos)?;
+        let mut pos = pos.clone();
+        MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All)
+            .into_iter()
+            .map(|m| {
+                pos.apply_move(&m);
+                // Taking the last piece or underpromoting leaves a dead draw
+                let dtm = Self::probe(&pos).unwrap_or(Dtm::Draw).parent();
+                pos.undo_move(&m);
+                (m, dtm)
+            })
+            .max_by_key(|(_, dtm)| dtm.score(0))
+    }
+}
+
+fn index(setup: &Setup, count: usize) -> usize {
+    setup.squares[..count]
+        .iter()
+        .fold(!setup.strong_to_move as usize, |index, square| {
+            index * 64 + square
+        })
+}
+
+fn setup(mut index: usize, count: usize) -> Setup {
+    let mut squares = [0; 4];
+    for square in squares[..count].iter_mut().rev() {
+        *square = index % 64;
+        index /= 64;
+    }
+    Setup {
+        strong_to_move: index == 0,
+        squares,
+    }
+}
+
+// The best a pawn on the seventh rank can do by promoting to a queen or a rook
+fn promotion_plies(setup: &Setup) -> Option<u8> {
+    let pawn = setup.squares[2];
+    let to = pawn + 8;
+    if !(48..56).contains(&pawn) || setup.occupancy(3) & (1u64 << to) != 0 {
+        return None;
+    }
+    let promoted = Setup {
+        strong_to_move: false,
+        squares: [setup.squares[STRONG_KING], setup.squares[WEAK_KING], to, 0],
+    };
+    [Material::KQK, Material::KRK]
+        .into_iter()
+        .filter_map(
+            |material| match EndgameTable::get(material).probe_setup(&promoted) {
+                Some(Dtm::Loss(plies)) => Some(plies as u8 + 1),
+                _ => None,
+            },
+        )
+        .min()
+}
+
+fn position_setup(pos: &Position, material: Material, strong: usize) -> Setup {
+    // The tables have the strong side playing up the board
+    let flip = if strong == Side::WHITE { 0 } else { 56 };
+    let square = |side: usize, piece: usize| {
+        pos.board.pieces[side][piece].0.trailing_zeros() as usize ^ flip
+    };
+
+    let mut squares = [0; 4];
+    squares[STRONG_KING] = square(strong, Piece::KING);
+    squares[WEAK_KING] = square(1 - strong, Piece::KING);
+    for (i, piece) in material.pieces().iter().enumerate() {
+        squares[i + 2] = square(strong, *piece);
+    }
+    Setup {
+        strong_to_move: pos.side_to_move.0 == strong,
+        squares,
+    }
+}
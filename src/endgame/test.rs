@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod endgame_tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Move, Piece, Position, Side, Square},
+        },
+        constants::MATE_VALUE,
+        endgame::{
+            table::{Dtm, EndgameTable, Material},
+            unmove::{Setup, STRONG_KING, WEAK_KING},
+        },
+        evaluate::evaluator::Evaluate,
+        movegen::generator::{MoveGenKind, MoveGenerator},
+        uci::options::GoOptions,
+    };
+
+    fn probe(fen: &str) -> Option<Dtm> {
+        EndgameTable::probe(&FenParser::fen_to_position(fen))
+    }
+
+    #[test]
+    fn longest_queen_and_rook_mates() {
+        // Mate in 10 and mate in 16
+        assert_eq!(EndgameTable::get(Material::KQK).longest_win(), 19);
+        assert_eq!(EndgameTable::get(Material::KRK).longest_win(), 31);
+    }
+
+    #[test]
+    fn finds_mates() {
+        let fen = "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1";
+        assert_eq!(probe(fen), Some(Dtm::Win(1)));
+        assert_eq!(probe("1Q5k/8/6K1/8/8/8/8/8 b - - 0 1"), Some(Dtm::Loss(0)));
+
+        let (m, dtm) = EndgameTable::best_move(&FenParser::fen_to_position(fen)).unwrap();
+        assert_eq!(m.m, Move::Normal(Square::B1, Square::B8));
+        assert_eq!(dtm, Dtm::Win(1));
+    }
+
+    #[test]
+    fn search_plays_from_the_tables() {
+        // Black is the strong side here
+        let fen = "8/8/8/8/8/6k1/r7/7K b - - 0 1";
+        EndgameTable::get(Material::KRK);
+        let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+        engine.is_quiet = true;
+        Evaluate::search(&mut engine, GoOptions::depth(1), None);
+        assert_eq!(
+            engine.current_best_move.unwrap().m,
+            Move::Normal(Square::A2, Square::A1)
+        );
+        assert_eq!(engine.current_score, Some(-(MATE_VALUE - 1)));
+    }
+
+    #[test]
+    fn pawn_endings_follow_the_opposition() {
+        assert_eq!(probe("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(Dtm::Draw));
+        assert!(matches!(
+            probe("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"),
+            Some(Dtm::Loss(_))
+        ));
+        // Same thing with colors reversed
+        assert_eq!(probe("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1"), Some(Dtm::Draw));
+        assert!(matches!(
+            probe("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1"),
+            Some(Dtm::Loss(_))
+        ));
+        // A king on the sixth in front of the pawn wins either way
+        assert!(matches!(
+            probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"),
+            Some(Dtm::Win(_))
+        ));
+    }
+
+    #[test]
+    fn probes_leave_the_fifty_move_rule_to_the_search() {
+        EndgameTable::get(Material::KQK);
+        let fresh = FenParser::fen_to_position("8/8/8/4k3/8/8/8/1Q2K3 w - - 0 1");
+        let late = FenParser::fen_to_position("8/8/8/4k3/8/8/8/1Q2K3 w - - 90 1");
+        assert!(matches!(
+            EndgameTable::probe_in_search(&fresh),
+            Some(Dtm::Win(plies)) if plies > 10
+        ));
+        assert_eq!(EndgameTable::probe_in_search(&late), None);
+        assert!(EndgameTable::best_move(&late).is_none());
+    }
+
+    #[test]
+    fn search_does_not_solve_tables() {
+        // Solving this one takes seconds, the search has to go on without it
+        let fen = "8/8/8/4k3/8/8/8/KBN5 w - - 0 1";
+        let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+        engine.is_quiet = true;
+        let start = Instant::now();
+        Evaluate::search(
+            &mut engine,
+            GoOptions {
+                movetime: Some(100),
+                ..GoOptions::empty()
+            },
+            None,
+        );
+        assert!(engine.current_best_move.is_some());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn tables_are_solved_in_the_background() {
+        EndgameTable::solve_in_background(Material::KRK);
+        let start = Instant::now();
+        while EndgameTable::solved(Material::KRK).is_none() {
+            assert!(start.elapsed() < Duration::from_secs(60));
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn lone_king_taking_the_piece_draws() {
+        assert_eq!(probe("8/8/8/8/8/8/6Rk/K7 b - - 0 1"), Some(Dtm::Draw));
+    }
+
+    #[test]
+    fn other_material_is_not_solved() {
+        assert_eq!(probe("8/8/8/8/8/8/6rk/KR6 b - - 0 1"), None);
+    }
+
+    // The setup on a board, white is the strong side
+    fn setup_position(setup: &Setup, material: Material) -> Position {
+        const LETTERS: [char; 6] = ['P', 'B', 'N', 'R', 'Q', 'K'];
+        let mut board = ['.'; 64];
+        board[setup.squares[STRONG_KING]] = 'K';
+        board[setup.squares[WEAK_KING]] = 'k';
+        for (i, piece) in material.pieces().iter().enumerate() {
+            board[setup.squares[i + 2]] = LETTERS[*piece];
+        }
+        let ranks: Vec<String> = (0..8)
+            .rev()
+            .map(|rank| {
+                let mut fen = String::new();
+                let mut empty = 0;
+                for square in &board[rank * 8..rank * 8 + 8] {
+                    if *square == '.' {
+                        empty += 1;
+                        continue;
+                    }
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(*square);
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                }
+                fen
+            })
+            .collect();
+        let side = if setup.strong_to_move { "w" } else { "b" };
+        FenParser::fen_to_position(&format!("{} {side} - - 0 1", ranks.join("/")))
+    }
+
+    fn position_setup(pos: &Position, material: Material) -> Setup {
+        let square = |side: usize, piece: usize| {
+            pos.board.pieces[side][piece].0.trailing_zeros() as usize
+        };
+        let mut squares = [0; 4];
+        squares[STRONG_KING] = square(Side::WHITE, Piece::KING);
+        squares[WEAK_KING] = square(Side::BLACK, Piece::KING);
+        for (i, piece) in material.pieces().iter().enumerate() {
+            squares[i + 2] = square(Side::WHITE, *piece);
+        }
+        Setup {
+            strong_to_move: pos.side_to_move.0 == Side::WHITE,
+            squares,
+        }
+    }
+
+    #[test]
+    fn setup_moves_match_the_move_generator() {
+        for (material, step) in [
+            (Material::KQK, 2003),
+            (Material::KRK, 2003),
+            (Material::KPK, 2003),
+            (Material::KBNK, 99991),
+        ] {
+            let pieces = material.pieces();
+            let count = pieces.len() + 2;
+            for index in (0..2 << (6 * count)).step_by(step) {
+                let mut squares = [0; 4];
+                for (i, square) in squares[..count].iter_mut().enumerate() {
+                    *square = (index >> (6 * i)) % 64;
+                }
+                let setup = Setup {
+                    strong_to_move: index >> (6 * count) == 0,
+                    squares,
+                };
+                if !setup.is_legal(pieces) {
+                    continue;
+                }
+                let mut pos = setup_position(&setup, material);
+                let moves = MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All);
+
+                if !setup.strong_to_move {
+                    assert_eq!(setup.in_check(pieces), pos.checkers() != 0, "{}", pos.to_fen());
+                    let quiet = moves.iter().filter(|m| m.captured_piece.is_none()).count();
+                    let captures = moves.len() > quiet;
+                    assert_eq!(setup.weak_moves(pieces), (quiet as u8, captures));
+
+                    // Every strong move back from here can be played forward again
+                    setup.strong_unmoves(pieces, |prev| {
+                        let mut prev_pos = setup_position(&prev, material);
+                        let prev_moves =
+                            MoveGenerator::get_legal_moves(&mut prev_pos, &MoveGenKind::All);
+                        let leads_here = prev_moves.iter().any(|m| {
+                            prev_pos.apply_move(m);
+                            let after = position_setup(&prev_pos, material);
+                            prev_pos.undo_move(m);
+                            after == setup
+                        });
+                        assert!(leads_here, "{} from {}", pos.to_fen(), prev_pos.to_fen());
+                    });
+                    continue;
+                }
+
+                // And every strong move forward can be taken back, promotions aside
+                for m in moves.iter().filter(|m| !matches!(m.m, Move::Promotion(..))) {
+                    pos.apply_move(m);
+                    let after = position_setup(&pos, material);
+                    pos.undo_move(m);
+                    let mut found = false;
+                    after.strong_unmoves(pieces, |prev| found |= prev == setup);
+                    assert!(found, "{}", pos.to_fen());
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "slow without optimizations"]
+    fn longest_bishop_and_knight_mate() {
+        // Mate in 33
+        assert_eq!(EndgameTable::get(Material::KBNK).longest_win(), 65);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    board::models::Piece,
+    movegen::{
+        generator::MAGIC,
+        magic::{KING_LOOKUP, KNIGHTS_LOOKUP},
+    },
+};
+
+const FILE_A: u64 = 0x0101010101010101;
+const FILE_H: u64 = 0x8080808080808080;
+
+// Index of the kings in a setup, the strong pieces follow them
+pub const STRONG_KING: usize = 0;
+pub const WEAK_KING: usize = 1;
+
+// A position of a solved ending, seen from the strong side which always plays up the board.
+// A lot lighter than a full Position, as the solver has to walk through millions of them.
+// For the same reason it makes its own moves, mostly backwards, which MoveGenerator can't,
+// from the same attack lookups. The endgame tests check them against MoveGenerator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setup {
+    pub strong_to_move: bool,
+    pub squares: [usize; 4],
+}
+impl Setup {
+    pub fn occupancy(&self, count: usize) -> u64 {
+        self.squares[..count]
+            .iter()
+            .fold(0, |occupancy, square| occupancy | (1u64 << square))
+    }
+
+    // The squares attacked by the strong side, optionally leaving out a captured piece
+    pub fn strong_attacks(&self, pieces: &[usize], occupancy: u64, captured: Option<usize>) -> u64 {
+        let mut attacked = KING_LOOKUP[self.squares[STRONG_KING]];
+        for (i, piece) in pieces.iter().enumerate() {
+            if captured != Some(i + 2) {
+                attacked |= attacks(*piece, self.squares[i + 2], occupancy);
+            }
+        }
+        attacked
+    }
+
+    pub fn in_check(&self, pieces: &[usize]) -> bool {
+        let occupancy = self.occupancy(pieces.len() + 2);
+        self.strong_attacks(pieces, occupancy, None) & (1u64 << self.squares[WEAK_KING]) != 0
+    }
+
+    pub fn is_legal(&self, pieces: &[usize]) -> bool {
+        let count = pieces.len() + 2;
+        if self.occupancy(count).count_ones() as usize != count {
+            return false;
+        }
+        // Pawns never stand on the first or the last rank
+        let misplaced_pawn = pieces
+            .iter()
+            .enumerate()
+            .any(|(i, piece)| *piece == Piece::PAWN && !(8..56).contains(&self.squares[i + 2]));
+        if misplaced_pawn {
+            return false;
+        }
+        if KING_LOOKUP[self.squares[STRONG_KING]] & (1u64 << self.squares[WEAK_KING]) != 0 {
+            return false;
+        }
+        // The side that just moved can't have left its king in check
+        !(self.strong_to_move && self.in_check(pieces))
+    }
+
+    // The legal moves of the weak king to empty squares, and whether it can take a piece
+    pub fn weak_moves(&self, pieces: &[usize]) -> (u8, bool) {
+        let count = pieces.len() + 2;
+        let weak_king = self.squares[WEAK_KING];
+        // The king doesn't shield the squares behind itself
+        let occupancy = self.occupancy(count) & !(1u64 << weak_king);
+
+        let mut moves = 0;
+        let mut can_capture = false;
+        let mut targets = KING_LOOKUP[weak_king];
+        while targets != 0 {
+            let to = targets.trailing_zeros() as usize;
+            targets &= targets - 1;
+
+            let captured = (2..count).find(|i| self.squares[*i] == to);
+            if self.strong_attacks(pieces, occupancy, captured) & (1u64 << to) != 0 {
+                continue;
+            }
+            match captured {
+                Some(_) => can_capture = true,
+                None => moves += 1,
+            }
+        }
+        (moves, can_capture)
+    }
+
+    // Calls back with every legal position, strong side to move, that leads here by a strong move
+    pub fn strong_unmoves(&self, pieces: &[usize], mut f: impl FnMut(Setup)) {
+        let count = pieces.len() + 2;
+        let occupancy = self.occupancy(count);
+        for i in (0..count).filter(|i| *i != WEAK_KING) {
+            let piece = if i == STRONG_KING {
+                Piece::KING
+            } else {
+                pieces[i - 2]
+            };
+            let to = self.squares[i];
+            // Apart from pawns, pieces go back the same way they came
+            let mut froms = match piece {
+                Piece::PAWN => pawn_unmoves(to, occupancy),
+                _ => attacks(piece, to, occupancy),
+            } & !occupancy;
+
+            while froms != 0 {
+                let from = froms.trailing_zeros() as usize;
+                froms &= froms - 1;
+
+                let mut prev = *self;
+                prev.squares[i] = from;
+                prev.strong_to_move = true;
+                if prev.is_legal(pieces) {
+                    f(prev);
+                }
+            }
+        }
+    }
+
+    // Calls back with every position, weak side to move, that leads here by a king move
+    pub fn weak_unmoves(&self, pieces: &[usize], mut f: impl FnMut(Setup)) {
+        let occupancy = self.occupancy(pieces.len() + 2);
+        let mut froms = KING_LOOKUP[self.squares[WEAK_KING]]
+            & !KING_LOOKUP[self.squares[STRONG_KING]]
+            & !occupancy;
+
+        while froms != 0 {
+            let from = froms.trailing_zeros() as usize;
+            froms &= froms - 1;
+
+            let mut prev = *self;
+            prev.squares[WEAK_KING] = from;
+            prev.strong_to_move = false;
+            f(prev);
+        }
+    }
+}
+
+// The squares attacked by a strong piece, pawns capture up the board
+pub fn attacks(piece: usize, square: usize, occupancy: u64) -> u64 {
+    match piece {
+        Piece::PAWN => {
+            let bit = 1u64 << square;
+            ((bit << 7) & !FILE_H) | ((bit << 9) & !FILE_A)
+        }
+        Piece::KNIGHT => KNIGHTS_LOOKUP[square],
+        Piece::BISHOP => MAGIC.get_bishop_attacks(square, occupancy),
+        Piece::ROOK => MAGIC.get_rook_attacks(square, occupancy),
+        Piece::QUEEN => {
+            MAGIC.get_bishop_attacks(square, occupancy) | MAGIC.get_rook_attacks(square, occupancy)
+        }
+        _ => KING_LOOKUP[square],
+    }
+}
+
+// The squares a pawn could have been pushed from, including the double step
+fn pawn_unmoves(to: usize, occupancy: u64) -> u64 {
+    if to < 16 {
+        return 0;
+    }
+    let mut froms = 1u64 << (to - 8);
+    if (24..32).contains(&to) && occupancy & (1u64 << (to - 8)) == 0 {
+        froms |= 1u64 << (to - 16);
+    }
+    froms
+}
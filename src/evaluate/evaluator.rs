@@ -1,32 +1,53 @@
 use std::{
     ops::ControlFlow,
     sync::{
+        atomic::Ordering,
         mpsc::{Receiver, TryRecvError},
         Arc, Mutex,
     },
+    thread,
 };
 
+use arrayvec::ArrayVec;
 use instant::Instant;
 use rustc_hash::FxHashMap;
 
 use crate::{
-    board::models::{Engine, Piece, PiecePosition, Position, Side},
+    board::{
+        models::{Engine, Piece, PiecePosition, Position, Side},
+        utils::move_to_algebraic,
+    },
+    endgame::table::EndgameTable,
     constants::{
         BISHOP_PAIR_VALUE, BISHOP_VALUE, CAPTURE_VALUE, KING_VALUE, KNIGHT_VALUE, MATE_VALUE,
-        PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE, SILENT_MOVE_VALUE,
+        MAX_PLY, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE, SILENT_MOVE_VALUE,
+    },
+    movegen::{
+        generator::{MoveGenKind, MoveGenerator, MoveInfo},
+        move_list::MoveList,
+        move_picker::MovePicker,
     },
-    movegen::generator::{MoveGenKind, MoveGenerator, MoveInfo},
     uci::{options::GoOptions, protocol::UCI},
 };
 
-use super::piece_square_tables::{
-    BLACK_BISHOP_SQUARE_TABLE, BLACK_KING_BEGIN_SQUARE_TABLE, BLACK_KING_END_SQUARE_TABLE,
-    BLACK_KNIGHT_SQUARE_TABLE, BLACK_PAWN_SQUARE_TABLE, BLACK_QUEEN_SQUARE_TABLE,
-    BLACK_ROOK_SQUARE_TABLE, WHITE_BISHOP_SQUARE_TABLE, WHITE_KING_BEGIN_SQUARE_TABLE,
-    WHITE_KING_END_SQUARE_TABLE, WHITE_KNIGHT_SQUARE_TABLE, WHITE_PAWN_SQUARE_TABLE,
-    WHITE_QUEEN_SQUARE_TABLE, WHITE_ROOK_SQUARE_TABLE,
+use super::{
+    piece_square_tables::{
+        BLACK_BISHOP_SQUARE_TABLE, BLACK_KING_BEGIN_SQUARE_TABLE, BLACK_KING_END_SQUARE_TABLE,
+        BLACK_KNIGHT_SQUARE_TABLE, BLACK_PAWN_SQUARE_TABLE, BLACK_QUEEN_SQUARE_TABLE,
+        BLACK_ROOK_SQUARE_TABLE, WHITE_BISHOP_SQUARE_TABLE, WHITE_KING_BEGIN_SQUARE_TABLE,
+        WHITE_KING_END_SQUARE_TABLE, WHITE_KNIGHT_SQUARE_TABLE, WHITE_PAWN_SQUARE_TABLE,
+        WHITE_QUEEN_SQUARE_TABLE, WHITE_ROOK_SQUARE_TABLE,
+    },
+    transposition::Bound,
 };
 
+// Search threads recurse deep with a move list on every frame
+pub const SEARCH_STACK_SIZE: usize = 64 << 20;
+// The principal variation below a node, its best move first
+type Line = ArrayVec<MoveInfo, MAX_PLY>;
+// Null move cutoffs with at least this much depth left are verified
+const NULL_MOVE_VERIFICATION_DEPTH: usize = 6;
+
 pub struct Evaluate;
 impl Evaluate {
     // todo: Evaluate pawn structures
@@ -43,33 +64,119 @@ impl Evaluate {
     }
 
     pub fn search(engine: &mut Engine, options: GoOptions, rx: Option<Arc<Mutex<Receiver<&str>>>>) {
-        let alpha = -isize::MAX;
-        let beta = isize::MAX;
         engine.is_searching = true;
         engine.current_best_move = None;
+        engine.current_pv.clear();
+        engine.current_score = None;
+        engine.current_best_move_ms = 0;
+        engine.nodes = 0;
+        engine.null_move_min_ply = 0;
+        engine.search_context.clear();
+        engine.shared.transposition_table.new_search();
+        engine.shared.tb_hits.store(0, Ordering::Relaxed);
 
         let (max_depth, time_to_move_ms) = GoOptions::parse_uci_options(
             options,
             engine.position.side_to_move.0,
             engine.position.half_move_number,
         );
+        // Lines, killers and the rest of the per ply tables end there
+        let max_depth = max_depth.min(MAX_PLY - 1);
         let start = Instant::now();
 
-        let mut prev_ordered_moves: Option<Vec<MoveInfo>> = None;
+        // Opening moves are played straight from the book
+        if let Some(m) = engine.book.probe(&mut engine.position) {
+            engine.current_best_move = Some(m.clone());
+            engine.current_pv = vec![m.clone()];
+            if !engine.is_quiet {
+                let pv = move_to_algebraic(&m, &engine.position);
+                println!("info string book move {pv}");
+            }
+            engine.is_searching = false;
+            return;
+        }
 
-        for i in 1..max_depth + 1 {
-            if !engine.is_searching {
-                return;
+        // Solved endings are played straight from the tables
+        if let Some((m, dtm)) = EndgameTable::best_move(&engine.position) {
+            let score = dtm.score(0);
+            let score_cp = match engine.position.side_to_move.0 {
+                Side::WHITE => score,
+                _ => -score,
+            };
+            engine.current_best_move = Some(m.clone());
+            engine.current_pv = vec![m.clone()];
+            engine.current_score = Some(score_cp);
+            if !engine.is_quiet {
+                let pv = move_to_algebraic(&m, &engine.position);
+                println!("info score cp {score_cp} pv {pv} depth 1");
+            }
+            engine.is_searching = false;
+            return;
+        }
+
+        // So are won and lost endings in the Syzygy tables, keeping to the fifty move rule
+        if let Some((m, wdl)) = engine.syzygy.root_move(&engine.position) {
+            let score = wdl.score(0);
+            let score_cp = match engine.position.side_to_move.0 {
+                Side::WHITE => score,
+                _ => -score,
+            };
+            engine.shared.tb_hits.fetch_add(1, Ordering::Relaxed);
+            engine.current_best_move = Some(m.clone());
+            engine.current_pv = vec![m.clone()];
+            engine.current_score = Some(score_cp);
+            if !engine.is_quiet {
+                let pv = move_to_algebraic(&m, &engine.position);
+                println!("info score cp {score_cp} tbhits 1 pv {pv} depth 1");
             }
+            engine.is_searching = false;
+            return;
+        }
 
-            if let ControlFlow::Break(_) =
-                Self::check_if_max_time_passed(time_to_move_ms, start, engine)
-            {
-                return;
+        // Lazy SMP: helpers search the same tree, finding their way through each other's
+        // table entries, and the main thread reports what it found
+        engine.shared.stop.store(false, Ordering::Relaxed);
+        thread::scope(|scope| {
+            for index in 1..engine.threads {
+                let mut helper = engine.helper();
+                helper.is_searching = true;
+                // Half of the helpers start one ply deeper, so they don't all go in lockstep
+                let first_depth = 1 + index % 2;
+                thread::Builder::new()
+                    .stack_size(SEARCH_STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        Self::iterative_deepening(
+                            &mut helper,
+                            first_depth,
+                            max_depth,
+                            &None,
+                            None,
+                            start,
+                        );
+                    })
+                    .expect("Failed to spawn a search thread");
             }
 
-            // Stop search on stop signal
-            if let ControlFlow::Break(_) = Self::stop_on_signal(&rx, engine) {
+            Self::iterative_deepening(engine, 1, max_depth, &rx, time_to_move_ms, start);
+            engine.shared.stop.store(true, Ordering::Relaxed);
+        });
+        engine.is_searching = false;
+    }
+
+    fn iterative_deepening(
+        engine: &mut Engine,
+        first_depth: usize,
+        max_depth: usize,
+        rx: &Option<Arc<Mutex<Receiver<&str>>>>,
+        time_to_move_ms: Option<u128>,
+        start: Instant,
+    ) {
+        let alpha = -isize::MAX;
+        let beta = isize::MAX;
+        let mut prev_ordered_moves: Option<MoveList> = None;
+
+        for i in first_depth..max_depth + 1 {
+            if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
                 return;
             }
 
@@ -78,14 +185,13 @@ impl Evaluate {
                 beta,
                 alpha,
                 i,
-                &rx,
+                rx,
                 &mut prev_ordered_moves,
                 engine.position.side_to_move.0,
                 time_to_move_ms,
                 start,
             );
         }
-        engine.is_searching = false;
     }
 
     fn search_depth(
@@ -94,7 +200,7 @@ impl Evaluate {
         mut alpha: isize,
         depth: usize,
         rx: &Option<Arc<Mutex<Receiver<&str>>>>,
-        prev_ordered_moves: &mut Option<Vec<MoveInfo>>,
+        prev_ordered_moves: &mut Option<MoveList>,
         original_side: usize,
         time_to_move_ms: Option<u128>,
         start: Instant,
@@ -115,111 +221,76 @@ impl Evaluate {
                 return;
             }
 
+            engine.search_context.set_move(0, &engine.position, m);
             engine.apply_move(m);
 
-            let mut cached_score = 0;
-            let cached_zobrist = engine.zobrist_table.get(&engine.position.zobrist.hash);
-            let has_key = cached_zobrist.is_some();
-            let write_table = if has_key {
-                let cached_triplet = cached_zobrist.unwrap()[depth - 1];
-
-                if let Some((exact_score, alpha_bound, beta_bound)) = cached_triplet {
-                    // Check if the cached value is in the bound
-                    if alpha >= alpha_bound && beta <= beta_bound {
-                        cached_score = exact_score;
-                        false
-                    } else {
-                        true
-                    }
-                } else {
-                    true
-                }
-            } else {
-                true
-            };
-
-            let mut line: [Option<MoveInfo>; 10] = [None, None, None, None, None, None, None, None, None, None];
-            let score = if write_table {
-                -Self::alpha_beta(
-                    engine,
-                    -beta,
-                    -alpha,
-                    depth - 1,
-                    depth,
-                    rx,
-                    time_to_move_ms,
-                    start,
-                    &mut line,
-                )
-            } else {
-                cached_score
-            };
+            let mut line = Line::new();
+            let score = -Self::alpha_beta(
+                engine,
+                -beta,
+                -alpha,
+                depth - 1,
+                depth,
+                rx,
+                time_to_move_ms,
+                start,
+                &mut line,
+            );
 
             if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
-                engine.undo_move(&m);
+                engine.undo_move(m);
                 return;
             }
 
-            if write_table {
-                if !has_key {
-                    engine.zobrist_table.insert(
-                        engine.position.zobrist.hash,
-                        [
-                            Some((score, alpha, beta)),
-                            None,
-                            None,
-                            None,
-                            None,
-                            None,
-                            None,
-                            None,
-                            None,
-                            None,
-                        ],
-                    );
-                } else {
-                    let mut new_value = *engine
-                        .zobrist_table
-                        .get(&engine.position.zobrist.hash)
-                        .unwrap();
-                    new_value[depth - 1] = Some((score, alpha, beta));
-                    engine
-                        .zobrist_table
-                        .insert(engine.position.zobrist.hash, new_value);
-                }
-            }
             moves_score.insert(m.clone(), score);
-            engine.undo_move(&m);
+            engine.undo_move(m);
 
             if score > alpha {
                 alpha = score;
+                if engine.current_best_move.as_ref() != Some(m) {
+                    engine.current_best_move_ms = start.elapsed().as_millis();
+                }
                 engine.current_best_move = Some(m.clone());
+                engine.current_pv = vec![m.clone()];
+                engine.current_pv.extend(line.iter().cloned());
 
                 let score_cp = match original_side {
                     Side::WHITE => score,
                     Side::BLACK => -score,
                     _ => 0,
                 };
+                engine.current_score = Some(score_cp);
 
                 let pv_enabled = true;
+                if engine.is_quiet {
+                    continue;
+                }
+                let hashfull = engine.shared.transposition_table.hashfull();
+                let tb_hits = engine.shared.tb_hits.load(Ordering::Relaxed);
                 if pv_enabled {
                     let mut pv = String::from("");
-                    pv.push_str(&format!(" {}", m.clone()));
+                    pv.push_str(&format!(" {}", move_to_algebraic(m, &engine.position)));
 
-                    for m in line {
-                        if m.is_some() {
-                            pv.push_str(&format!(" {}", m.unwrap().clone()));
-                        }
+                    for m in line.iter() {
+                        pv.push_str(&format!(" {}", move_to_algebraic(m, &engine.position)));
                     }
-                    println!("info score cp {score_cp} pv{pv} depth {depth}");
+                    println!(
+                        "info score cp {score_cp} hashfull {hashfull} tbhits {tb_hits} pv{pv} \
+                        depth {depth}"
+                    );
                 } else {
-                    println!("info score cp {score_cp} depth {depth}");
+                    println!(
+                        "info score cp {score_cp} hashfull {hashfull} tbhits {tb_hits} depth {depth}"
+                    );
                 }
             }
         }
 
         moves.sort_by(|a, b| moves_score.get(b).cmp(&moves_score.get(a)));
         *prev_ordered_moves = Some(moves);
+
+        let best_move = engine.current_best_move.clone();
+        Self::store_in_table(engine, 0, depth, alpha, Bound::Exact, best_move.as_ref());
     }
 
     fn alpha_beta(
@@ -231,8 +302,31 @@ impl Evaluate {
         rx: &Option<Arc<Mutex<Receiver<&str>>>>,
         time_to_move_ms: Option<u128>,
         start: Instant,
-        prev_line: &mut [Option<MoveInfo>],
+        prev_line: &mut Line,
     ) -> isize {
+        engine.nodes += 1;
+
+        // A repeated position is a draw
+        if engine.position.is_repetition(2) {
+            return 0;
+        }
+
+        // So is a position after 100 plies without captures or pawn moves
+        if let Some(value) = Self::check_fifty_move_rule(engine, starting_depth - depth_left) {
+            return value;
+        }
+
+        // The result of a solved ending is known exactly
+        if let Some(dtm) = EndgameTable::probe_in_search(&engine.position) {
+            return dtm.score(starting_depth - depth_left);
+        }
+
+        // Other endings may be in the Syzygy tables, as a win, draw or loss
+        if let Some(wdl) = engine.syzygy.probe_in_search(&mut engine.position) {
+            engine.shared.tb_hits.fetch_add(1, Ordering::Relaxed);
+            return wdl.score(starting_depth - depth_left);
+        }
+
         if depth_left == 0 {
             let quiesce_score = Self::quiesce(engine, alpha, beta);
             return quiesce_score;
@@ -240,135 +334,137 @@ impl Evaluate {
 
         let actual_depth = starting_depth - depth_left;
 
-        let moves = MoveGenerator::get_ordered_moves(engine);
-        let tot_moves = moves.len();
+        let tt_entry = engine
+            .shared
+            .transposition_table
+            .probe(&engine.position, actual_depth);
+        if let Some(entry) = &tt_entry {
+            if let Some(score) = entry.cutoff(depth_left, alpha, beta) {
+                return score;
+            }
+        }
+
+        let mut picker = MovePicker::new(
+            tt_entry.and_then(|entry| entry.best_move),
+            engine.search_context.killers(actual_depth),
+            engine.search_context.counter_move(actual_depth),
+        );
+        let mut tot_moves = 0;
+        // Quiet moves that didn't cause a cutoff, their history goes down when another one does
+        let mut quiets_tried: ArrayVec<MoveInfo, 64> = ArrayVec::new();
+        let original_alpha = alpha;
+        let mut best_move = None;
+
+        let in_check = engine.position.checkers() > 0;
 
         let mut search_pv = true;
-        for m in moves {
+        while let Some(m) = picker.next(engine) {
+            tot_moves += 1;
             if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
                 break;
             }
 
+            engine.search_context.set_move(actual_depth, &engine.position, &m);
             engine.apply_move(&m);
 
-            let mut cached_score = 0;
-            let mut cached_triplet = None;
-            let cached_zobrist = engine.zobrist_table.get(&engine.position.zobrist.hash);
-            let has_key = cached_zobrist.is_some();
-            if has_key {
-                let unwrapped = cached_zobrist.unwrap();
-                cached_triplet = unwrapped[depth_left - 1];
-                let mut iter = depth_left;
-                                while cached_triplet.is_none() {
-                    cached_triplet = unwrapped[iter];
-                    iter += 1;
-                    if iter >= 10 {
-                        break;
-                    }
-                }
-            }
-
-            let write_table = if let Some((exact_score, alpha_bound, beta_bound)) = cached_triplet {
-                // Check if the cached value is in the bound
-                if alpha >= alpha_bound && beta <= beta_bound {
-                    cached_score = exact_score;
-                    false
-                } else {
-                    true
-                }
-            } else {
-                true
-            };
-
             let score;
-            let mut line: [Option<MoveInfo>; 10] = [None, None, None, None, None, None, None, None, None, None];
-            if write_table {
-                if search_pv {
-                    score = -Self::alpha_beta(
+            let mut line = Line::new();
+            if search_pv {
+                score = -Self::alpha_beta(
+                    engine,
+                    -beta,
+                    -alpha,
+                    depth_left - 1,
+                    starting_depth,
+                    rx,
+                    time_to_move_ms,
+                    start,
+                    &mut line,
+                );
+            } else {
+                // One ply less than in a zero width node, the principal variation is searched with care
+                let reduction = Self::late_move_reduction(engine, &m, depth_left, tot_moves, in_check)
+                    .saturating_sub(1);
+                let mut null_window_score = -Self::zero_width_search(
+                    engine,
+                    -alpha,
+                    depth_left - 1 - reduction,
+                    actual_depth + depth_left - reduction,
+                    rx,
+                    time_to_move_ms,
+                    start,
+                );
+                if reduction > 0 && null_window_score > alpha {
+                    null_window_score = -Self::zero_width_search(
                         engine,
-                        -beta,
                         -alpha,
                         depth_left - 1,
                         starting_depth,
                         rx,
                         time_to_move_ms,
                         start,
-                        &mut line,
                     );
-                } else {
-                    let null_window_score = -Self::zero_width_search(
+                }
+                if null_window_score > alpha && null_window_score < beta {
+                    // re-search
+                    score = -Self::alpha_beta(
                         engine,
+                        -beta,
                         -alpha,
                         depth_left - 1,
                         starting_depth,
                         rx,
                         time_to_move_ms,
                         start,
+                        &mut line,
                     );
-                    if null_window_score > alpha && null_window_score < beta {
-                        // re-search
-                        score = -Self::alpha_beta(
-                            engine,
-                            -beta,
-                            -alpha,
-                            depth_left - 1,
-                            starting_depth,
-                            rx,
-                            time_to_move_ms,
-                            start,
-                            &mut line,
-                        );
-                    } else {
-                        score = null_window_score;
-                    }
-                }
-            } else {
-                score = cached_score;
-            };
-
-            if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
-                engine.undo_move(&m);
-                break;
-            }
-
-            if write_table {
-                if !has_key {
-                    let mut value = [None; 10];
-                    value[depth_left - 1] = Some((score, alpha, beta));
-                    engine
-                        .zobrist_table
-                        .insert(engine.position.zobrist.hash, value);
                 } else {
-                    // todo: find a way to not read this value again
-                    let mut value = *engine
-                        .zobrist_table
-                        .get(&engine.position.zobrist.hash)
-                        .unwrap();
-                    value[depth_left - 1] = Some((score, alpha, beta));
-                    engine
-                        .zobrist_table
-                        .insert(engine.position.zobrist.hash, value);
+                    score = null_window_score;
                 }
             }
 
             engine.undo_move(&m);
 
+            if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
+                break;
+            }
+
             if score >= beta {
+                if m.is_quiet() {
+                    engine.search_context.update_quiet_cutoff(
+                        &engine.position,
+                        actual_depth,
+                        depth_left,
+                        &m,
+                        &quiets_tried,
+                    );
+                }
+                Self::store_in_table(engine, actual_depth, depth_left, beta, Bound::Lower, Some(&m));
                 return beta;
             }
+            if m.is_quiet() {
+                let _ = quiets_tried.try_push(m.clone());
+            }
             if score > alpha {
                 alpha = score;
                 search_pv = false;
 
-                prev_line[actual_depth] = Some(m.clone());
-                for i in (actual_depth+1)..10 {
-                    prev_line[i] = line[i-1].clone();
-                }
+                prev_line.clear();
+                prev_line.push(m.clone());
+                prev_line.extend(line.iter().take(MAX_PLY - 1).cloned());
+                best_move = Some(m);
             }
         }
         if let Some(value) = Self::check_mate_or_stalemate(engine, tot_moves, actual_depth) {
             return value;
         }
+
+        let bound = if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        Self::store_in_table(engine, actual_depth, depth_left, alpha, bound, best_move.as_ref());
         alpha
     }
 
@@ -381,6 +477,29 @@ impl Evaluate {
         time_to_move_ms: Option<u128>,
         start: Instant,
     ) -> isize {
+        engine.nodes += 1;
+
+        // A repeated position is a draw
+        if engine.position.is_repetition(2) {
+            return 0;
+        }
+
+        // So is a position after 100 plies without captures or pawn moves
+        if let Some(value) = Self::check_fifty_move_rule(engine, starting_depth - depth_left) {
+            return value;
+        }
+
+        // The result of a solved ending is known exactly
+        if let Some(dtm) = EndgameTable::probe_in_search(&engine.position) {
+            return dtm.score(starting_depth - depth_left);
+        }
+
+        // Other endings may be in the Syzygy tables, as a win, draw or loss
+        if let Some(wdl) = engine.syzygy.probe_in_search(&mut engine.position) {
+            engine.shared.tb_hits.fetch_add(1, Ordering::Relaxed);
+            return wdl.score(starting_depth - depth_left);
+        }
+
         if depth_left == 0 {
             let quiesce_score = Self::quiesce(engine, beta - 1, beta);
             return quiesce_score;
@@ -388,31 +507,134 @@ impl Evaluate {
 
         let actual_depth = starting_depth - depth_left;
 
-        let moves = MoveGenerator::get_ordered_moves(engine);
-        let tot_moves = moves.len();
+        let tt_entry = engine
+            .shared
+            .transposition_table
+            .probe(&engine.position, actual_depth);
+        if let Some(entry) = &tt_entry {
+            if let Some(score) = entry.cutoff(depth_left, beta - 1, beta) {
+                return score;
+            }
+        }
+
+        // Null move pruning: if the opponent can't get below beta even when given a free move,
+        // any real move would fail high too
+        if actual_depth >= engine.null_move_min_ply
+            && depth_left >= 2
+            && Self::can_try_null_move(engine)
+        {
+            let reduction = if depth_left > 6 { 3 } else { 2 };
+            // The reduced searches start further down, so that the plies from the root still add up
+            let null_depth = depth_left.saturating_sub(1 + reduction);
+            let min_ply = engine.null_move_min_ply;
+            // Passing twice in a row would only give the move back
+            engine.null_move_min_ply = actual_depth + 2;
+            engine.search_context.set_null_move(actual_depth);
+            engine.apply_null_move();
+            let score = -Self::zero_width_search(
+                engine,
+                1 - beta,
+                null_depth,
+                actual_depth + 1 + null_depth,
+                rx,
+                time_to_move_ms,
+                start,
+            );
+            engine.undo_null_move();
+
+            // Deep in the tree a zugzwang costs too much, confirm with a reduced real search
+            let mut verified = score >= beta && engine.is_searching;
+            if verified && depth_left >= NULL_MOVE_VERIFICATION_DEPTH {
+                engine.null_move_min_ply = actual_depth + 1;
+                verified = Self::zero_width_search(
+                    engine,
+                    beta,
+                    depth_left - reduction,
+                    actual_depth + depth_left - reduction,
+                    rx,
+                    time_to_move_ms,
+                    start,
+                ) >= beta;
+            }
+            engine.null_move_min_ply = min_ply;
+            if verified {
+                Self::store_in_table(engine, actual_depth, depth_left, beta, Bound::Lower, None);
+                return beta;
+            }
+        }
 
-        for m in moves {
+        let mut picker = MovePicker::new(
+            tt_entry.and_then(|entry| entry.best_move),
+            engine.search_context.killers(actual_depth),
+            engine.search_context.counter_move(actual_depth),
+        );
+        let mut tot_moves = 0;
+        // Quiet moves that didn't cause a cutoff, their history goes down when another one does
+        let mut quiets_tried: ArrayVec<MoveInfo, 64> = ArrayVec::new();
+        let in_check = engine.position.checkers() > 0;
+        let late_move_count = engine.search_params.late_move_count(depth_left);
+
+        while let Some(m) = picker.next(engine) {
+            tot_moves += 1;
             if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
                 break;
             }
 
+            // Late move pruning: this far down the list, a quiet move close to the leaves
+            // is very unlikely to do better than the ones before it.
+            // Decided before playing the move, as most moves this late get pruned
+            if depth_left <= engine.search_params.lmp_depth
+                && tot_moves > late_move_count
+                && m.is_quiet()
+                && !in_check
+            {
+                continue;
+            }
+
+            engine.search_context.set_move(actual_depth, &engine.position, &m);
             engine.apply_move(&m);
 
-            let score = -Self::zero_width_search(
+            let reduction = Self::late_move_reduction(engine, &m, depth_left, tot_moves, in_check);
+            let mut score = -Self::zero_width_search(
                 engine,
                 1 - beta,
-                depth_left - 1,
-                starting_depth,
+                depth_left - 1 - reduction,
+                actual_depth + depth_left - reduction,
                 rx,
                 time_to_move_ms,
                 start,
             );
+            // A reduced move that looks good gets searched to the full depth
+            if reduction > 0 && score >= beta {
+                score = -Self::zero_width_search(
+                    engine,
+                    1 - beta,
+                    depth_left - 1,
+                    starting_depth,
+                    rx,
+                    time_to_move_ms,
+                    start,
+                );
+            }
 
             engine.undo_move(&m);
 
             if score >= beta {
+                if m.is_quiet() {
+                    engine.search_context.update_quiet_cutoff(
+                        &engine.position,
+                        actual_depth,
+                        depth_left,
+                        &m,
+                        &quiets_tried,
+                    );
+                }
+                Self::store_in_table(engine, actual_depth, depth_left, beta, Bound::Lower, Some(&m));
                 return beta;
             }
+            if m.is_quiet() {
+                let _ = quiets_tried.try_push(m.clone());
+            }
 
             if Self::check_should_exit(engine, rx, time_to_move_ms, start) {
                 break;
@@ -421,10 +643,14 @@ impl Evaluate {
         if let Some(value) = Self::check_mate_or_stalemate(engine, tot_moves, actual_depth) {
             return value;
         }
+        Self::store_in_table(engine, actual_depth, depth_left, beta - 1, Bound::Upper, None);
         beta - 1
     }
 
     fn quiesce(engine: &mut Engine, mut alpha: isize, beta: isize) -> isize {
+        engine.nodes += 1;
+
+        // No repetition check, captures and promotions reset the halfmove clock
         let score = Self::static_evaluation(engine);
         if score >= beta {
             return score;
@@ -434,8 +660,8 @@ impl Evaluate {
         }
 
         // todo: In quiesce, search also for checks
-        let moves = MoveGenerator::get_ordered_moves_by_kind(engine, MoveGenKind::OnlyCaptures);
-        for m in moves {
+        let mut picker = MovePicker::captures();
+        while let Some(m) = picker.next(engine) {
             engine.apply_move(&m);
             let quiesce_score = -Self::quiesce(engine, -beta, -alpha);
             engine.undo_move(&m);
@@ -775,6 +1001,10 @@ impl Evaluate {
         if !engine.is_searching {
             return true;
         }
+        if engine.shared.stop.load(Ordering::Relaxed) {
+            engine.is_searching = false;
+            return true;
+        }
         if let ControlFlow::Break(_) = Self::stop_on_signal(rx, engine) {
             engine.is_searching = false;
             return true;
@@ -788,16 +1018,66 @@ impl Evaluate {
         false
     }
 
+    // Plies to take off the search of a move just made. Only late quiet moves are reduced,
+    // never out of or into check, and at least one ply is always left
+    fn late_move_reduction(
+        engine: &Engine,
+        m: &MoveInfo,
+        depth_left: usize,
+        move_number: usize,
+        in_check: bool,
+    ) -> usize {
+        let gives_check = engine.position.checkers() > 0;
+        if depth_left < 3 || move_number < 2 || in_check || gives_check || !m.is_quiet() {
+            return 0;
+        }
+        let reduction = engine.search_params.reduction(depth_left, move_number);
+        reduction.min(depth_left - 2)
+    }
+
+    // Passing is assumed to be the worst a side can do, which is wrong when in check
+    // and in zugzwang, mostly found in endings with only pawns left
+    fn can_try_null_move(engine: &mut Engine) -> bool {
+        let side = engine.position.side_to_move.0;
+        let pieces = &engine.position.board.pieces[side];
+        let pawns_and_king = pieces[Piece::PAWN].0 | pieces[Piece::KING].0;
+        engine.position.board.side_pieces[side].0 != pawns_and_king
+            && !engine.position.is_in_check()
+    }
+
+    // Results of a search cut short by the clock or a stop aren't trusted
+    fn store_in_table(
+        engine: &Engine,
+        ply: usize,
+        depth: usize,
+        score: isize,
+        bound: Bound,
+        best_move: Option<&MoveInfo>,
+    ) {
+        if engine.is_searching {
+            engine
+                .shared
+                .transposition_table
+                .store(&engine.position, ply, depth, score, bound, best_move);
+        }
+    }
+
+    fn check_fifty_move_rule(engine: &mut Engine, actual_depth: usize) -> Option<isize> {
+        if engine.position.state.halfmove_clock < 100 {
+            return None;
+        }
+        // A mate delivered on the 100th ply is still a mate
+        let tot_moves = MoveGenerator::get_legal_moves(&mut engine.position, &MoveGenKind::All).len();
+        Some(Self::check_mate_or_stalemate(engine, tot_moves, actual_depth).unwrap_or(0))
+    }
+
     fn check_mate_or_stalemate(
         engine: &mut Engine,
         tot_moves: usize,
         actual_depth: usize,
     ) -> Option<isize> {
         if engine.is_searching && tot_moves == 0 {
-            engine.position.side_to_move = Side(engine.position.opposite_side());
-            let is_check = MoveGenerator::is_position_check(&mut engine.position);
-            engine.position.side_to_move = Side(engine.position.opposite_side());
-            if is_check {
+            if engine.position.is_in_check() {
                 // That's a mate, mate
                 return Some(-(MATE_VALUE - (actual_depth as isize)));
             } else {
@@ -1,3 +1,6 @@
 pub mod evaluator;
 pub mod test;
-pub mod piece_square_tables;
\ No newline at end of file
+pub mod piece_square_tables;
+pub mod search_context;
+pub mod search_params;
+pub mod transposition;
\ No newline at end of file
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::{
+    board::models::{Move, Position},
+    constants::MAX_PLY,
+    movegen::generator::MoveInfo,
+};
+
+// History scores stay within this, so old cutoffs don't outweigh new ones forever
+pub const MAX_HISTORY: isize = 1 << 14;
+// One counter move for each side, piece and target square of the move it answers
+const COUNTER_MOVES: usize = 2 * 6 * 64;
+
+// What a search thread learns about move ordering while it searches
+#[derive(Clone)]
+pub struct SearchContext {
+    // How good quiet moves turned out to be, by side, from square and to square
+    history: Box<[[[isize; 64]; 64]; 2]>,
+    // The last two quiet moves that caused a cutoff at each ply, newest first
+    killers: Vec<[Option<MoveInfo>; 2]>,
+    // The quiet move that last refuted a move
+    counter_moves: Vec<Option<MoveInfo>>,
+    // What was played at each ply of the line being searched, as an index in counter_moves.
+    // None for a null move
+    line: Vec<Option<usize>>,
+}
+impl SearchContext {
+    pub fn new() -> SearchContext {
+        SearchContext {
+            history: Box::new([[[0; 64]; 64]; 2]),
+            killers: vec![[None, None]; MAX_PLY],
+            counter_moves: vec![None; COUNTER_MOVES],
+            line: vec![None; MAX_PLY],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self.history = [[[0; 64]; 64]; 2];
+        self.killers.fill([None, None]);
+        self.counter_moves.fill(None);
+        self.line.fill(None);
+    }
+
+    // The history of a move of the side to move
+    pub fn history(&self, pos: &Position, m: &MoveInfo) -> isize {
+        let (from, to) = squares(pos, m);
+        self.history[pos.side_to_move.0][from][to]
+    }
+
+    pub fn killers(&self, ply: usize) -> [Option<MoveInfo>; 2] {
+        self.killers.get(ply).cloned().unwrap_or([None, None])
+    }
+
+    // The answer that worked last time to the move played just before this ply
+    pub fn counter_move(&self, ply: usize) -> Option<MoveInfo> {
+        let previous = (*self.line.get(ply.checked_sub(1)?)?)?;
+        self.counter_moves[previous].clone()
+    }
+
+    // Called before making a move at this ply, with the position it is made in
+    pub fn set_move(&mut self, ply: usize, pos: &Position, m: &MoveInfo) {
+        let (_, to) = squares(pos, m);
+        let index = (pos.side_to_move.0 * 6 + m.piece) * 64 + to;
+        if let Some(played) = self.line.get_mut(ply) {
+            *played = Some(index);
+        }
+    }
+
+    pub fn set_null_move(&mut self, ply: usize) {
+        if let Some(played) = self.line.get_mut(ply) {
+            *played = None;
+        }
+    }
+
+    // A quiet move caused a cutoff. It becomes a killer and a counter move, and its history
+    // goes up while the history of the quiet moves searched before it goes down
+    pub fn update_quiet_cutoff(
+        &mut self,
+        pos: &Position,
+        ply: usize,
+        depth: usize,
+        m: &MoveInfo,
+        quiets_tried: &[MoveInfo],
+    ) {
+        let bonus = (depth * depth) as isize;
+        self.update_history(pos, m, bonus);
+        for tried in quiets_tried {
+            self.update_history(pos, tried, -bonus);
+        }
+
+        if let Some(killers) = self.killers.get_mut(ply) {
+            if killers[0].as_ref() != Some(m) {
+                killers[1] = killers[0].take();
+                killers[0] = Some(m.clone());
+            }
+        }
+
+        if let Some(Some(previous)) = ply.checked_sub(1).and_then(|ply| self.line.get(ply)) {
+            self.counter_moves[*previous] = Some(m.clone());
+        }
+    }
+
+    // The closer to the limit, the less a bonus or malus moves the score
+    fn update_history(&mut self, pos: &Position, m: &MoveInfo, bonus: isize) {
+        let (from, to) = squares(pos, m);
+        let entry = &mut self.history[pos.side_to_move.0][from][to];
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+impl Default for SearchContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SearchContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SearchContext")
+    }
+}
+
+// Castles are indexed by the squares of the king
+fn squares(pos: &Position, m: &MoveInfo) -> (usize, usize) {
+    let (from, to) = match m.m {
+        Move::Normal(from, to) | Move::EnPassant(from, to) | Move::Promotion(from, to, _) => {
+            (from, to)
+        }
+        Move::Castle(castling) => {
+            let (king_from, king_to, _, _) = pos.castling_squares(castling);
+            (king_from, king_to)
+        }
+    };
+    (from.trailing_zeros() as usize, to.trailing_zeros() as usize)
+}
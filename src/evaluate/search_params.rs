@@ -0,0 +1,85 @@
+use std::fmt;
+
+// Both in hundredths, see SearchParams
+pub const DEFAULT_LMR_BASE: usize = 75;
+pub const DEFAULT_LMR_DIVISOR: usize = 225;
+
+pub const DEFAULT_LMP_DEPTH: usize = 3;
+pub const DEFAULT_LMP_MOVES: usize = 3;
+
+// Depths and move counts past this share the last row and column of the table
+const TABLE_SIZE: usize = 64;
+
+// Knobs of the late move reductions and pruning, exposed as UCI options for testing
+#[derive(Clone)]
+pub struct SearchParams {
+    // The n-th move searched with d plies left is reduced by
+    // base / 100 + ln(d) * ln(n) / (divisor / 100) plies
+    lmr_base: usize,
+    lmr_divisor: usize,
+    // Up to this depth, quiet moves after the first lmp_moves + depth² are skipped
+    pub lmp_depth: usize,
+    pub lmp_moves: usize,
+    reductions: [[u8; TABLE_SIZE]; TABLE_SIZE],
+}
+impl SearchParams {
+    pub fn new() -> SearchParams {
+        let mut params = SearchParams {
+            lmr_base: DEFAULT_LMR_BASE,
+            lmr_divisor: DEFAULT_LMR_DIVISOR,
+            lmp_depth: DEFAULT_LMP_DEPTH,
+            lmp_moves: DEFAULT_LMP_MOVES,
+            reductions: [[0; TABLE_SIZE]; TABLE_SIZE],
+        };
+        params.fill_reductions();
+        params
+    }
+
+    pub fn set_lmr_base(&mut self, base: usize) {
+        self.lmr_base = base;
+        self.fill_reductions();
+    }
+
+    pub fn set_lmr_divisor(&mut self, divisor: usize) {
+        self.lmr_divisor = divisor.max(1);
+        self.fill_reductions();
+    }
+
+    // Plies taken off the search of the n-th move, counting from 1
+    pub fn reduction(&self, depth: usize, move_number: usize) -> usize {
+        self.reductions[depth.min(TABLE_SIZE - 1)][move_number.min(TABLE_SIZE - 1)] as usize
+    }
+
+    // How many quiet moves are searched at this depth before the rest are pruned
+    pub fn late_move_count(&self, depth: usize) -> usize {
+        self.lmp_moves + depth * depth
+    }
+
+    fn fill_reductions(&mut self) {
+        for depth in 1..TABLE_SIZE {
+            for move_number in 1..TABLE_SIZE {
+                let log = (depth as f64).ln() * (move_number as f64).ln();
+                let reduction =
+                    self.lmr_base as f64 / 100.0 + log * 100.0 / self.lmr_divisor as f64;
+                self.reductions[depth][move_number] = reduction as u8;
+            }
+        }
+    }
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SearchParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchParams")
+            .field("lmr_base", &self.lmr_base)
+            .field("lmr_divisor", &self.lmr_divisor)
+            .field("lmp_depth", &self.lmp_depth)
+            .field("lmp_moves", &self.lmp_moves)
+            .finish()
+    }
+}
@@ -22,6 +22,8 @@ mod static_evaluation_tests {
 
 #[cfg(test)]
 mod ponder_tests {
+    use std::sync::atomic::Ordering;
+
     use crate::{
         board::{
             fen::FenParser,
@@ -79,7 +81,8 @@ mod ponder_tests {
 
     #[test]
     fn mate_in_2() {
-        let fen = "2bqkbn1/2pppp2/np2N3/r3P1p1/p2N2B1/5Q2/PPPPKPP1/RNB2r2 w KQkq - 0 1";
+        // No castling rights: the white king and the black rooks have left their squares
+        let fen = "2bqkbn1/2pppp2/np2N3/r3P1p1/p2N2B1/5Q2/PPPPKPP1/RNB2r2 w - - 0 1";
         let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
         Evaluate::search(&mut engine, GoOptions::depth(5), None);
         assert_eq!(
@@ -103,6 +106,32 @@ mod ponder_tests {
             Move::Normal(Square::G2, Square::G1)
         );
     }
+
+    #[test]
+    fn mate_in_2_with_helper_threads() {
+        let fen = "2bqkbn1/2pppp2/np2N3/r3P1p1/p2N2B1/5Q2/PPPPKPP1/RNB2r2 w - - 0 1";
+        let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+        engine.threads = 4;
+        Evaluate::search(&mut engine, GoOptions::depth(5), None);
+        assert_eq!(
+            engine.current_best_move.clone().unwrap().m,
+            Move::Normal(Square::F3, Square::F7)
+        );
+        // The helpers are stopped along with the main thread
+        assert!(engine.shared.stop.load(Ordering::Relaxed));
+        assert!(!engine.is_searching);
+    }
+
+    #[test]
+    fn searches_deeper_than_ten_plies() {
+        // Locked pawns, the kings have few moves and the search gets deep quickly
+        let fen = "4k3/8/3p1p2/3P1P2/8/8/8/4K3 w - - 0 1";
+        let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+        engine.is_quiet = true;
+        Evaluate::search(&mut engine, GoOptions::depth(12), None);
+        assert!(engine.current_best_move.is_some());
+        assert!(!engine.current_pv.is_empty());
+    }
 }
 
 #[cfg(test)]
@@ -179,3 +208,281 @@ mod uci_options_parser_tests {
         assert_eq!(max_time_in_ms, Some(500));
     }
 }
+
+#[cfg(test)]
+mod fifty_move_rule_tests {
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Piece},
+        },
+        evaluate::evaluator::Evaluate,
+        uci::options::GoOptions,
+    };
+
+    #[test]
+    fn resets_the_clock_instead_of_drawing() {
+        let fen = "7k/8/8/8/8/8/P7/K5Q1 w - - 99 80";
+        let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+        Evaluate::search(&mut engine, GoOptions::depth(3), None);
+        assert_eq!(engine.current_best_move.clone().unwrap().piece, Piece::PAWN);
+    }
+
+    #[test]
+    fn mate_on_the_last_ply_is_still_mate() {
+        // The queen is on b1, not a1: from a1 it would give check with white to move
+        let fen = "7k/8/6K1/8/8/8/8/1Q6 w - - 99 80";
+        let mut engine = Engine::from_position(FenParser::fen_to_position(fen));
+        Evaluate::search(&mut engine, GoOptions::depth(3), None);
+        assert_eq!(format!("{}", engine.current_best_move.clone().unwrap()), "b1b8");
+    }
+}
+
+#[cfg(test)]
+mod transposition_tests {
+    use std::sync::Arc;
+
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Castling, Engine, Move, Piece, Position, SharedSearch, Square},
+            zobrist::ZobristValue,
+        },
+        constants::{MATE_VALUE, START_POS},
+        evaluate::transposition::{Bound, TranspositionTable},
+        movegen::generator::MoveInfo,
+    };
+
+    fn with_hash(fen: &str, hash: u64) -> Position {
+        let mut pos = FenParser::fen_to_position(fen);
+        pos.zobrist = ZobristValue { hash };
+        pos
+    }
+
+    #[test]
+    fn stores_and_probes_entries() {
+        let table = TranspositionTable::new(1);
+        let moves = [
+            (
+                START_POS,
+                MoveInfo {
+                    m: Move::Normal(Square::E2, Square::E4),
+                    piece: Piece::PAWN,
+                    captured_piece: None,
+                },
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                MoveInfo {
+                    m: Move::Castle(Castling::WHITE_KING_SIDE),
+                    piece: Piece::KING,
+                    captured_piece: None,
+                },
+            ),
+            (
+                "rnbqkbnr/1ppppppp/8/8/P3P3/8/1p1PKPPP/RN1Q1BNR b kq - 1 6",
+                MoveInfo {
+                    m: Move::Promotion(Square::B2, Square::A1, Piece::QUEEN),
+                    piece: Piece::PAWN,
+                    captured_piece: Some(Piece::ROOK),
+                },
+            ),
+        ];
+        for (fen, m) in moves {
+            let pos = FenParser::fen_to_position(fen);
+            assert_eq!(table.probe(&pos, 0), None);
+            table.store(&pos, 0, 5, -42, Bound::Upper, Some(&m));
+            let entry = table.probe(&pos, 0).unwrap();
+            assert_eq!(
+                (entry.depth, entry.score, entry.bound),
+                (5, -42, Bound::Upper)
+            );
+            assert_eq!(entry.best_move, Some(m));
+            assert_eq!(entry.cutoff(5, -10, 10), Some(-10));
+            assert_eq!(entry.cutoff(5, -50, 10), None);
+            assert_eq!(entry.cutoff(6, -10, 10), None);
+        }
+
+        table.clear();
+        assert_eq!(table.probe(&FenParser::fen_to_position(START_POS), 0), None);
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_node() {
+        let table = TranspositionTable::new(1);
+        let pos = FenParser::fen_to_position(START_POS);
+        table.store(&pos, 4, 3, MATE_VALUE - 7, Bound::Exact, None);
+        assert_eq!(table.probe(&pos, 2).unwrap().score, MATE_VALUE - 5);
+        table.store(&pos, 4, 3, -(MATE_VALUE - 7), Bound::Exact, None);
+        assert_eq!(table.probe(&pos, 2).unwrap().score, -(MATE_VALUE - 5));
+    }
+
+    #[test]
+    fn keeps_the_deepest_entry_of_the_current_search() {
+        let table = TranspositionTable::new(1);
+        // Small hashes all land in the first bucket
+        let deep = with_hash(START_POS, 1);
+        let shallow = with_hash(START_POS, 2);
+        let newest = with_hash(START_POS, 3);
+
+        table.store(&deep, 0, 8, 10, Bound::Exact, None);
+        table.store(&shallow, 0, 2, 20, Bound::Exact, None);
+        assert!(table.probe(&deep, 0).is_some());
+        assert!(table.probe(&shallow, 0).is_some());
+
+        // The always replace slot takes the new entry
+        table.store(&newest, 0, 3, 30, Bound::Exact, None);
+        assert!(table.probe(&deep, 0).is_some());
+        assert!(table.probe(&shallow, 0).is_none());
+        assert!(table.probe(&newest, 0).is_some());
+
+        // Entries of an old search go first, however deep
+        table.new_search();
+        table.store(&shallow, 0, 1, 20, Bound::Exact, None);
+        assert!(table.probe(&deep, 0).is_none());
+        assert!(table.probe(&shallow, 0).is_some());
+    }
+
+    #[test]
+    fn hashfull_counts_the_current_search() {
+        let table = TranspositionTable::new(1);
+        assert_eq!(table.hashfull(), 0);
+        let mut pos = FenParser::fen_to_position(START_POS);
+        for hash in 0..2000 {
+            pos.zobrist = ZobristValue { hash: hash << 50 };
+            table.store(&pos, 0, 1, 0, Bound::Exact, None);
+        }
+        assert!(table.hashfull() > 0);
+        table.new_search();
+        assert_eq!(table.hashfull(), 0);
+    }
+
+    #[test]
+    fn new_engine_keeps_the_table() {
+        let shared = Arc::new(SharedSearch::new(1));
+        let engine = Engine::with_shared(shared.clone());
+        assert!(Arc::ptr_eq(&engine.shared, &shared));
+    }
+}
+
+#[cfg(test)]
+mod late_move_tests {
+    use crate::evaluate::search_params::SearchParams;
+
+    #[test]
+    fn reductions_grow_with_depth_and_moves() {
+        let params = SearchParams::new();
+        assert_eq!(params.reduction(1, 30), 0);
+        assert_eq!(params.reduction(8, 1), 0);
+        assert!(params.reduction(3, 2) <= params.reduction(3, 20));
+        assert!(params.reduction(3, 20) <= params.reduction(20, 20));
+        assert!(params.reduction(20, 20) > 1);
+        // Past the end of the table
+        assert_eq!(params.reduction(200, 200), params.reduction(63, 63));
+    }
+
+    #[test]
+    fn lmr_options_rebuild_the_table() {
+        let mut params = SearchParams::new();
+        let default = params.reduction(10, 10);
+        params.set_lmr_base(300);
+        assert_eq!(params.reduction(10, 10), default + 2);
+        params.set_lmr_base(0);
+        params.set_lmr_divisor(1000);
+        assert_eq!(params.reduction(10, 10), 0);
+    }
+
+    #[test]
+    fn late_move_count_grows_with_depth() {
+        let mut params = SearchParams::new();
+        params.lmp_moves = 3;
+        assert_eq!(params.late_move_count(1), 4);
+        assert_eq!(params.late_move_count(3), 12);
+    }
+}
+
+#[cfg(test)]
+mod search_context_tests {
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Move, Piece, Square},
+        },
+        constants::START_POS,
+        evaluate::search_context::{SearchContext, MAX_HISTORY},
+        movegen::generator::MoveInfo,
+    };
+
+    fn quiet(from: u64, to: u64, piece: usize) -> MoveInfo {
+        MoveInfo {
+            m: Move::Normal(from, to),
+            piece,
+            captured_piece: None,
+        }
+    }
+
+    #[test]
+    fn history_rewards_cutoffs_and_punishes_the_moves_before() {
+        let pos = FenParser::fen_to_position(START_POS);
+        let knight = quiet(Square::G1, Square::F3, Piece::KNIGHT);
+        let pawn = quiet(Square::E2, Square::E4, Piece::PAWN);
+        let mut context = SearchContext::new();
+        assert_eq!(context.history(&pos, &knight), 0);
+
+        context.update_quiet_cutoff(&pos, 0, 3, &knight, std::slice::from_ref(&pawn));
+        assert_eq!(context.history(&pos, &knight), 9);
+        assert_eq!(context.history(&pos, &pawn), -9);
+
+        // Never past the limit, however many cutoffs
+        for _ in 0..10000 {
+            context.update_quiet_cutoff(&pos, 0, 20, &knight, &[]);
+        }
+        assert!(context.history(&pos, &knight) <= MAX_HISTORY);
+
+        context.clear();
+        assert_eq!(context.history(&pos, &knight), 0);
+    }
+
+    #[test]
+    fn killers_keep_the_last_two_cutoffs() {
+        let pos = FenParser::fen_to_position(START_POS);
+        let first = quiet(Square::G1, Square::F3, Piece::KNIGHT);
+        let second = quiet(Square::B1, Square::C3, Piece::KNIGHT);
+        let third = quiet(Square::E2, Square::E4, Piece::PAWN);
+        let mut context = SearchContext::new();
+        assert_eq!(context.killers(2), [None, None]);
+
+        context.update_quiet_cutoff(&pos, 2, 1, &first, &[]);
+        context.update_quiet_cutoff(&pos, 2, 1, &second, &[]);
+        context.update_quiet_cutoff(&pos, 2, 1, &second, &[]);
+        assert_eq!(context.killers(2), [Some(second.clone()), Some(first)]);
+        context.update_quiet_cutoff(&pos, 2, 1, &third, &[]);
+        assert_eq!(context.killers(2), [Some(third), Some(second)]);
+        // Other plies have their own
+        assert_eq!(context.killers(3), [None, None]);
+        assert_eq!(context.killers(1000), [None, None]);
+    }
+
+    #[test]
+    fn counter_moves_answer_the_previous_move() {
+        let mut engine = Engine::from_position(FenParser::fen_to_position(START_POS));
+        let e4 = quiet(Square::E2, Square::E4, Piece::PAWN);
+        let d4 = quiet(Square::D2, Square::D4, Piece::PAWN);
+        let answer = quiet(Square::B8, Square::C6, Piece::KNIGHT);
+        let mut context = SearchContext::new();
+
+        context.set_move(0, &engine.position, &e4);
+        engine.apply_move(&e4);
+        assert_eq!(context.counter_move(1), None);
+        context.update_quiet_cutoff(&engine.position, 1, 1, &answer, &[]);
+        assert_eq!(context.counter_move(1), Some(answer));
+        assert_eq!(context.counter_move(0), None);
+        engine.undo_move(&e4);
+
+        // Another move played before, the answer doesn't apply
+        context.set_move(0, &engine.position, &d4);
+        assert_eq!(context.counter_move(1), None);
+        context.set_null_move(0);
+        assert_eq!(context.counter_move(1), None);
+    }
+}
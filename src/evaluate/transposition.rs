@@ -0,0 +1,266 @@
+use std::{
+    fmt,
+    mem::size_of,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
+
+use crate::{
+    board::models::{Move, Piece, Position},
+    constants::MATE_VALUE,
+    movegen::generator::MoveInfo,
+};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 4096;
+
+// Mate scores count the plies from the root, the table keeps them counted from the node
+const MATE_BOUND: isize = MATE_VALUE - 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // The score is at least this much, the search failed high
+    Lower,
+    // The score is at most this much, no move raised alpha
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TtEntry {
+    pub depth: usize,
+    pub score: isize,
+    pub bound: Bound,
+    pub best_move: Option<MoveInfo>,
+}
+impl TtEntry {
+    // The result of a search of the node at this depth and window, if the entry already knows it
+    pub fn cutoff(&self, depth: usize, alpha: isize, beta: isize) -> Option<isize> {
+        if self.depth < depth {
+            return None;
+        }
+        match self.bound {
+            Bound::Exact => Some(self.score),
+            Bound::Lower if self.score >= beta => Some(beta),
+            Bound::Upper if self.score <= alpha => Some(alpha),
+            _ => None,
+        }
+    }
+}
+
+// An entry packed in two words, the key is stored xored with the data so that
+// a slot torn by two threads writing at once just fails to match
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// The first slot keeps the deepest result, the second one whatever came last
+type Bucket = [Slot; 2];
+
+// Data layout, from the lowest bit: move 16, depth 8, bound 2, age 6, score 32
+const MOVE_MASK: u64 = 0xFFFF;
+const DEPTH_SHIFT: u64 = 16;
+const BOUND_SHIFT: u64 = 24;
+const AGE_SHIFT: u64 = 26;
+const SCORE_SHIFT: u64 = 32;
+const AGE_MASK: u8 = 63;
+
+// Shared by every clone of an engine, so writes go through atomics
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    // Bumped at every search, entries from older searches are the first to go
+    age: AtomicU8,
+}
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let count = (size_mb.clamp(1, MAX_HASH_MB) << 20) / size_of::<Bucket>();
+        TranspositionTable {
+            buckets: (0..count).map(|_| Bucket::default()).collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn size_mb(&self) -> usize {
+        (self.buckets.len() * size_of::<Bucket>()) >> 20
+    }
+
+    pub fn clear(&self) {
+        for slot in self.buckets.iter().flatten() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    pub fn new_search(&self) {
+        let age = (self.age.load(Ordering::Relaxed) + 1) & AGE_MASK;
+        self.age.store(age, Ordering::Relaxed);
+    }
+
+    // The entry of the position, `ply` plies away from the root
+    pub fn probe(&self, pos: &Position, ply: usize) -> Option<TtEntry> {
+        let hash = pos.zobrist.hash;
+        let data = self.bucket(hash).iter().find_map(|slot| {
+            let data = slot.data.load(Ordering::Relaxed);
+            let matches = slot.key.load(Ordering::Relaxed) ^ data == hash;
+            (matches && bound(data).is_some()).then_some(data)
+        })?;
+
+        let score = (data >> SCORE_SHIFT) as u32 as i32 as isize;
+        Some(TtEntry {
+            depth: ((data >> DEPTH_SHIFT) & 0xFF) as usize,
+            score: score_from_table(score, ply),
+            bound: bound(data)?,
+            best_move: unpack_move(data & MOVE_MASK, pos),
+        })
+    }
+
+    pub fn store(
+        &self,
+        pos: &Position,
+        ply: usize,
+        depth: usize,
+        score: isize,
+        bound: Bound,
+        best_move: Option<&MoveInfo>,
+    ) {
+        let hash = pos.zobrist.hash;
+        let age = self.age.load(Ordering::Relaxed) as u64;
+        let bucket = self.bucket(hash);
+
+        let deepest = bucket[0].data.load(Ordering::Relaxed);
+        let same_position = bucket[0].key.load(Ordering::Relaxed) ^ deepest == hash;
+        let slot = if same_position
+            || bound_bits(deepest) == 0
+            || (deepest >> AGE_SHIFT) & AGE_MASK as u64 != age
+            || depth >= ((deepest >> DEPTH_SHIFT) & 0xFF) as usize
+        {
+            &bucket[0]
+        } else {
+            &bucket[1]
+        };
+
+        let old = slot.data.load(Ordering::Relaxed);
+        let mut move_bits = best_move.map_or(0, pack_move);
+        // A fail low has no best move, the one found before is still the best guess
+        if move_bits == 0 && slot.key.load(Ordering::Relaxed) ^ old == hash {
+            move_bits = old & MOVE_MASK;
+        }
+
+        let score = score_to_table(score, ply).clamp(-(i32::MAX as isize), i32::MAX as isize);
+        let data = move_bits
+            | (depth.min(0xFF) as u64) << DEPTH_SHIFT
+            | (bound as u64 + 1) << BOUND_SHIFT
+            | age << AGE_SHIFT
+            | (score as i32 as u32 as u64) << SCORE_SHIFT;
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    // Permille of the table filled by the current search, sampled from its start
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed) as u64;
+        let sample = self.buckets.iter().take(500).flatten();
+        let total = sample.clone().count();
+        let used = sample
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                bound_bits(data) != 0 && (data >> AGE_SHIFT) & AGE_MASK as u64 == age
+            })
+            .count();
+        used * 1000 / total.max(1)
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        // Maps the hash onto the table without needing a power of two size
+        let index = ((hash as u128 * self.buckets.len() as u128) >> 64) as usize;
+        &self.buckets[index]
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TranspositionTable({} MB)", self.size_mb())
+    }
+}
+
+fn bound_bits(data: u64) -> u64 {
+    (data >> BOUND_SHIFT) & 3
+}
+
+fn bound(data: u64) -> Option<Bound> {
+    match bound_bits(data) {
+        1 => Some(Bound::Exact),
+        2 => Some(Bound::Lower),
+        3 => Some(Bound::Upper),
+        _ => None,
+    }
+}
+
+fn score_to_table(score: isize, ply: usize) -> isize {
+    match score {
+        score if score > MATE_BOUND => score + ply as isize,
+        score if score < -MATE_BOUND => score - ply as isize,
+        score => score,
+    }
+}
+
+fn score_from_table(score: isize, ply: usize) -> isize {
+    match score {
+        score if score > MATE_BOUND => score - ply as isize,
+        score if score < -MATE_BOUND => score + ply as isize,
+        score => score,
+    }
+}
+
+// Squares take 6 bits each, then the kind of move, 0 meaning no move.
+// Castles keep their flag in place of the target square
+fn pack_move(m: &MoveInfo) -> u64 {
+    let square = |square: u64| square.trailing_zeros() as u64;
+    let (from, to, kind) = match m.m {
+        Move::Normal(from, to) => (square(from), square(to), 1),
+        Move::EnPassant(from, to) => (square(from), square(to), 2),
+        Move::Castle(castling) => (0, castling as u64, 3),
+        // Bishop to queen, 4 to 7
+        Move::Promotion(from, to, piece) => (square(from), square(to), 3 + piece as u64),
+    };
+    from | to << 6 | kind << 12
+}
+
+// Pieces aren't stored, they are read back from the position
+fn unpack_move(bits: u64, pos: &Position) -> Option<MoveInfo> {
+    let from = 1u64 << (bits & 63);
+    let to = 1u64 << ((bits >> 6) & 63);
+    let side = pos.side_to_move.0;
+    let piece_on = |side: usize, square: u64| {
+        (0..6).find(|piece| pos.board.pieces[side][*piece].0 & square > 0)
+    };
+
+    let (m, piece, captured_piece) = match bits >> 12 {
+        1 => (
+            Move::Normal(from, to),
+            piece_on(side, from)?,
+            piece_on(1 - side, to),
+        ),
+        2 => (Move::EnPassant(from, to), Piece::PAWN, Some(Piece::PAWN)),
+        3 => (Move::Castle((bits >> 6) as u8 & 63), Piece::KING, None),
+        kind @ 4..=7 => (
+            Move::Promotion(from, to, kind as usize - 3),
+            Piece::PAWN,
+            piece_on(1 - side, to),
+        ),
+        _ => return None,
+    };
+    Some(MoveInfo {
+        m,
+        piece,
+        captured_piece,
+    })
+}
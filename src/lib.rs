@@ -1,7 +1,11 @@
 pub mod board;
+pub mod book;
 pub mod movegen;
 pub mod constants;
+pub mod endgame;
 pub mod evaluate;
+pub mod pgn;
+pub mod syzygy;
 pub mod uci;
 
 #[cfg(feature = "mimalloc")]
@@ -0,0 +1,126 @@
+use crate::board::models::{Piece, Position, Side};
+
+use super::{
+    generator::MAGIC,
+    magic::{KING_LOOKUP, KNIGHTS_LOOKUP},
+};
+
+const FILE_A: u64 = 0x0101010101010101;
+const FILE_H: u64 = 0x8080808080808080;
+
+// All the squares in these functions are bitboards with a single bit set, like in moves
+impl Position {
+    // Pieces of `side` attacking the square, sliders are blocked by `occupancy`
+    pub fn attackers_to(&self, square: u64, side: usize, occupancy: u64) -> u64 {
+        let square_index = square.trailing_zeros() as usize;
+        let pieces = &self.board.pieces[side];
+        let diagonal = pieces[Piece::BISHOP].0 | pieces[Piece::QUEEN].0;
+        let straight = pieces[Piece::ROOK].0 | pieces[Piece::QUEEN].0;
+
+        // A pawn of `side` attacks the square if a pawn of the other side on it would attack the pawn
+        (pawn_attacks(square, 1 - side) & pieces[Piece::PAWN].0)
+            | (KNIGHTS_LOOKUP[square_index] & pieces[Piece::KNIGHT].0)
+            | (KING_LOOKUP[square_index] & pieces[Piece::KING].0)
+            | (MAGIC.get_bishop_attacks(square_index, occupancy) & diagonal)
+            | (MAGIC.get_rook_attacks(square_index, occupancy) & straight)
+    }
+
+    // Sliders of `side` that would attack the square once the first piece in their way is gone
+    pub fn x_ray_attackers(&self, square: u64, side: usize, occupancy: u64) -> u64 {
+        let square_index = square.trailing_zeros() as usize;
+        let pieces = &self.board.pieces[side];
+        let diagonal = pieces[Piece::BISHOP].0 | pieces[Piece::QUEEN].0;
+        let straight = pieces[Piece::ROOK].0 | pieces[Piece::QUEEN].0;
+
+        let bishop_attacks = MAGIC.get_bishop_attacks(square_index, occupancy);
+        let rook_attacks = MAGIC.get_rook_attacks(square_index, occupancy);
+        let bishop_x_ray = MAGIC.get_bishop_attacks(square_index, occupancy & !bishop_attacks);
+        let rook_x_ray = MAGIC.get_rook_attacks(square_index, occupancy & !rook_attacks);
+
+        (bishop_x_ray & !bishop_attacks & diagonal) | (rook_x_ray & !rook_attacks & straight)
+    }
+
+    pub fn is_square_attacked(&self, square: u64, side: usize) -> bool {
+        self.attackers_to(square, side, self.occupancy()) > 0
+    }
+
+    // Pieces giving check to the side to move
+    pub fn checkers(&self) -> u64 {
+        let king = self.board.pieces[self.side_to_move.0][Piece::KING].0;
+        if king == 0 {
+            return 0;
+        }
+        self.attackers_to(king, self.opposite_side(), self.occupancy())
+    }
+
+    // Pieces of `side` that can't leave the line between their king and an enemy slider
+    pub fn pinned(&self, side: usize) -> u64 {
+        let king = self.board.pieces[side][Piece::KING].0;
+        if king == 0 {
+            return 0;
+        }
+        let king_index = king.trailing_zeros() as usize;
+        let occupancy = self.occupancy();
+        let enemies = &self.board.pieces[1 - side];
+
+        // Sliders lined up with the king on an empty board
+        let mut snipers = (MAGIC.get_bishop_attacks(king_index, 0)
+            & (enemies[Piece::BISHOP].0 | enemies[Piece::QUEEN].0))
+            | (MAGIC.get_rook_attacks(king_index, 0)
+                & (enemies[Piece::ROOK].0 | enemies[Piece::QUEEN].0));
+
+        let mut pinned = 0;
+        while snipers > 0 {
+            let sniper = 1u64 << snipers.trailing_zeros();
+            snipers &= snipers - 1;
+
+            let blockers = between(king, sniper) & occupancy;
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & self.board.side_pieces[side].0;
+            }
+        }
+        pinned
+    }
+
+    pub fn occupancy(&self) -> u64 {
+        self.board.side_pieces[0].0 | self.board.side_pieces[1].0
+    }
+}
+
+// The squares a pawn of `side` standing on the square attacks
+pub fn pawn_attacks(square: u64, side: usize) -> u64 {
+    match side {
+        Side::WHITE => ((square << 7) & !FILE_H) | ((square << 9) & !FILE_A),
+        _ => ((square >> 7) & !FILE_A) | ((square >> 9) & !FILE_H),
+    }
+}
+
+// The squares strictly between two squares on the same line, empty if they aren't lined up
+pub fn between(a: u64, b: u64) -> u64 {
+    let a_index = a.trailing_zeros() as usize;
+    let b_index = b.trailing_zeros() as usize;
+    let bishop_attacks = MAGIC.get_bishop_attacks(a_index, b);
+    if bishop_attacks & b > 0 {
+        return bishop_attacks & MAGIC.get_bishop_attacks(b_index, a);
+    }
+    let rook_attacks = MAGIC.get_rook_attacks(a_index, b);
+    if rook_attacks & b > 0 {
+        return rook_attacks & MAGIC.get_rook_attacks(b_index, a);
+    }
+    0
+}
+
+// The whole line through two squares, empty if they aren't lined up
+pub fn line(a: u64, b: u64) -> u64 {
+    let a_index = a.trailing_zeros() as usize;
+    let b_index = b.trailing_zeros() as usize;
+    let bishop_line = MAGIC.get_bishop_attacks(a_index, 0);
+    if bishop_line & b > 0 {
+        return (bishop_line & MAGIC.get_bishop_attacks(b_index, 0)) | a | b;
+    }
+    let rook_line = MAGIC.get_rook_attacks(a_index, 0);
+    if rook_line & b > 0 {
+        return (rook_line & MAGIC.get_rook_attacks(b_index, 0)) | a | b;
+    }
+    0
+}
@@ -1,5 +1,6 @@
 use crate::{
     board::models::{Castling, Engine, Move, Piece, Position, Side, Square},
+    evaluate::search_context::MAX_HISTORY,
     movegen::magic::MagicBitboard,
 };
 
@@ -7,7 +8,11 @@ lazy_static::lazy_static! {
     pub static ref MAGIC: MagicBitboard = MagicBitboard::init();
 }
 
-use super::magic::{KING_LOOKUP, KNIGHTS_LOOKUP};
+use super::{
+    attacks::{between, line, pawn_attacks},
+    magic::{KING_LOOKUP, KNIGHTS_LOOKUP},
+    move_list::MoveList,
+};
 
 #[derive(Clone, PartialEq)]
 pub enum MoveGenKind {
@@ -18,6 +23,69 @@ pub enum MoveGenKind {
 
 pub struct MoveGenerator;
 
+// Where the pieces of the side to move can go without leaving their king in check
+pub struct LegalMasks {
+    pub king: u64,
+    // Squares answering a check: the checker and the squares in between, none in a double check
+    pub check: u64,
+    pub pinned: u64,
+    // Squares next to the king that aren't attacked
+    pub king_targets: u64,
+}
+impl LegalMasks {
+    // Lets every pseudo legal move through
+    pub fn none() -> LegalMasks {
+        LegalMasks {
+            king: 0,
+            check: u64::MAX,
+            pinned: 0,
+            king_targets: u64::MAX,
+        }
+    }
+
+    pub fn new(pos: &Position) -> LegalMasks {
+        let king = pos.board.pieces[pos.side_to_move.0][Piece::KING].0;
+        if king == 0 {
+            return Self::none();
+        }
+
+        let checkers = pos.checkers();
+        let check = match checkers.count_ones() {
+            0 => u64::MAX,
+            1 => checkers | between(king, checkers),
+            _ => 0,
+        };
+
+        // The king doesn't shield the squares behind itself from sliders
+        let occupancy = pos.occupancy() & !king;
+        let mut squares = KING_LOOKUP[king.trailing_zeros() as usize];
+        let mut king_targets = 0;
+        while squares > 0 {
+            let square = 1u64 << squares.trailing_zeros();
+            if pos.attackers_to(square, pos.opposite_side(), occupancy) == 0 {
+                king_targets |= square;
+            }
+            squares &= squares - 1;
+        }
+
+        LegalMasks {
+            king,
+            check,
+            pinned: pos.pinned(pos.side_to_move.0),
+            king_targets,
+        }
+    }
+
+    // A pinned piece can only move along the line of its pin
+    pub fn for_piece(&self, square: u64) -> u64 {
+        if self.pinned & square > 0 {
+            self.check & line(self.king, square)
+        } else {
+            self.check
+        }
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct MoveInfo {
     pub m: Move,
@@ -25,31 +93,40 @@ pub struct MoveInfo {
     pub captured_piece: Option<usize>,
 }
 impl MoveInfo {
-    pub fn get_value(&self, _e: &Engine) -> isize {
+    // Neither a capture nor a promotion
+    pub fn is_quiet(&self) -> bool {
+        self.captured_piece.is_none() && !matches!(self.m, Move::Promotion(_, _, _))
+    }
+
+    pub fn get_value(&self, e: &Engine) -> isize {
+        // Quiet moves are mostly ordered by their history, the other moves go before all of them
+        let quiet_range = MAX_HISTORY + 100;
         if let Move::Promotion(_, _, _) = self.m {
             // Promotions always go first
-            return 9999;
+            return quiet_range + 9999;
         }
+        let history = e.search_context.history(&e.position, self);
         if let Move::Castle(_) = self.m {
             // Castling is good
-            return 99;
+            return history + 99;
         }
         if self.captured_piece.is_some() {
-            let mut result: isize = 100;
+            let mut result: isize = quiet_range + 100;
             // The bigger the captured piece, the better
             result += (self.captured_piece.unwrap() + 10) as isize;
             // The smaller the moving piece, the better
             result -= self.piece as isize;
             result
         } else {
-            self.piece as isize
+            history + self.piece as isize
         }
     }
 }
 
 impl MoveGenerator {
-    pub fn get_ordered_moves(e: &mut Engine) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = Self::get_legal_moves(&mut e.position, &MoveGenKind::All);
+    #[inline]
+    pub fn get_ordered_moves(e: &mut Engine) -> MoveList {
+        let mut moves = Self::get_legal_moves(&mut e.position, &MoveGenKind::All);
         Self::sort_moves(&mut moves, e);
         moves
     }
@@ -58,8 +135,9 @@ impl MoveGenerator {
         moves.sort_by(|a, b| b.get_value(e).cmp(&a.get_value(e)));
     }
 
-    pub fn get_ordered_moves_by_kind(e: &mut Engine, move_gen_kind: MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = Self::get_legal_moves(&mut e.position, &move_gen_kind);
+    #[inline]
+    pub fn get_ordered_moves_by_kind(e: &mut Engine, move_gen_kind: MoveGenKind) -> MoveList {
+        let mut moves = Self::get_legal_moves(&mut e.position, &move_gen_kind);
 
         Self::sort_moves(&mut moves, e);
 
@@ -67,48 +145,8 @@ impl MoveGenerator {
     }
 
     pub fn is_legal(new_pos: &mut Position, m: &MoveInfo) -> bool {
-        if m.m == Move::Castle(Castling::WHITE_KING_SIDE) {
-            let king = new_pos.board.pieces[new_pos.side_to_move.0][Piece::KING].0;
-            let square_index = king.trailing_zeros();
-            let king_square = 1u64 << square_index;
-            if Self::are_attacked_for_castling(
-                new_pos,
-                king_square,
-                vec![Square::E1, Square::F1, Square::G1],
-            ) {
-                return false;
-            }
-        } else if m.m == Move::Castle(Castling::WHITE_QUEEN_SIDE) {
-            let king = new_pos.board.pieces[new_pos.side_to_move.0][Piece::KING].0;
-            let square_index = king.trailing_zeros();
-            let king_square = 1u64 << square_index;
-            if Self::are_attacked_for_castling(
-                new_pos,
-                king_square,
-                vec![Square::E1, Square::D1, Square::C1],
-            ) {
-                return false;
-            }
-        } else if m.m == Move::Castle(Castling::BLACK_KING_SIDE) {
-            let king = new_pos.board.pieces[new_pos.side_to_move.0][Piece::KING].0;
-            let square_index = king.trailing_zeros();
-            let king_square = 1u64 << square_index;
-            if Self::are_attacked_for_castling(
-                new_pos,
-                king_square,
-                vec![Square::E8, Square::F8, Square::G8],
-            ) {
-                return false;
-            }
-        } else if m.m == Move::Castle(Castling::BLACK_QUEEN_SIDE) {
-            let king = new_pos.board.pieces[new_pos.side_to_move.0][Piece::KING].0;
-            let square_index = king.trailing_zeros();
-            let king_square = 1u64 << square_index;
-            if Self::are_attacked_for_castling(
-                new_pos,
-                king_square,
-                vec![Square::E8, Square::D8, Square::C8],
-            ) {
+        if let Move::Castle(castling) = m.m {
+            if Self::are_attacked_for_castling(new_pos, castling) {
                 return false;
             }
         } else if Self::is_check(new_pos, m) {
@@ -118,125 +156,246 @@ impl MoveGenerator {
     }
 
     // Check if something can eat the king
+    // Positions are validated when parsed, so the king can never be captured
     pub fn is_check(pos: &mut Position, m: &MoveInfo) -> bool {
         pos.apply_move(m);
-
-        // Check if we just ate the opposite king (legal move, just needed for evaluation)
-        let enemy_king = pos.board.pieces[pos.side_to_move.0][Piece::KING].0;
-        if enemy_king == 0 {
-            // We need this move to be legal for evaluation reasons
-            // (otherwise we would need to add weird checks on evaluation, because here later
-            // we would try to generate for the non existant opposite king)
-            pos.undo_move(&m);
-            return true;
-        }
         let result = Self::is_position_check(pos);
-        pos.undo_move(&m);
+        pos.undo_move(m);
         result
     }
 
-    pub fn is_position_check(pos: &mut Position) -> bool {
+    // Check if the side to move can capture the opposite king
+    pub fn is_position_check(pos: &Position) -> bool {
         let king = pos.board.pieces[pos.opposite_side()][Piece::KING].0;
-        let square_index = king.trailing_zeros();
-        let king_square = 1u64 << square_index;
+        king > 0 && pos.is_square_attacked(king, pos.side_to_move.0)
+    }
 
-        let moves = MoveGenerator::get_pseudo_legal_moves(pos, &MoveGenKind::OnlyCaptures);
-        moves.iter().any(|m| match &m.m {
-            Move::Normal(_, to) => to & king_square > 0,
-            Move::Promotion(_, to, _) => to & king_square > 0,
-            _ => false,
-        })
-    }
-
-    pub fn are_attacked_for_castling(
-        pos: &mut Position,
-        king_square: u64,
-        squares: Vec<u64>,
-    ) -> bool {
-        for square in squares {
-            if Self::is_check(
-                pos,
-                &MoveInfo {
-                    m: Move::Normal(king_square, square),
-                    piece: Piece::KING,
-                    captured_piece: None,
-                },
-            ) {
+    // Check if any square the king goes through while castling is attacked, both ends included.
+    // The castling pieces are taken off the board first, as the rook can stand on the king's path in Chess960
+    pub fn are_attacked_for_castling(pos: &Position, castling: u8) -> bool {
+        let (king_from, king_to, rook_from, _) = pos.castling_squares(castling);
+        let occupancy = pos.occupancy() & !(king_from | rook_from);
+
+        let mut path = Self::rank_span(king_from, king_to);
+        while path > 0 {
+            let square = 1u64 << path.trailing_zeros();
+            if pos.attackers_to(square, pos.opposite_side(), occupancy) > 0 {
                 return true;
             }
+            path &= path - 1;
         }
         false
     }
 
-    pub fn get_pseudo_legal_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    // Everything between the king, the rook and their destinations must be empty,
+    // apart from the king and the rook themselves
+    fn is_castling_path_clear(pos: &Position, castling: u8) -> bool {
+        let (king_from, king_to, rook_from, rook_to) = pos.castling_squares(castling);
+        let path = Self::rank_span(king_from, king_to) | Self::rank_span(rook_from, rook_to);
+        Self::get_occupancy(pos) & path & !(king_from | rook_from) == 0
+    }
 
-        // Pawn
-        moves.extend(Self::get_pawn_moves(pos, move_gen_kind));
-        // Knights
-        moves.extend(Self::get_knight_moves(pos, move_gen_kind));
-        // King
-        moves.extend(Self::get_king_moves(pos, move_gen_kind));
-        // Rook
-        moves.extend(Self::get_rook_moves(pos, move_gen_kind));
-        // Bishop
-        moves.extend(Self::get_bishop_moves(pos, move_gen_kind));
-        // Queen
-        moves.extend(Self::get_queen_moves(pos, move_gen_kind));
+    fn rank_span(a: u64, b: u64) -> u64 {
+        let (low, high) = if a < b { (a, b) } else { (b, a) };
+        (high - low) | high
+    }
 
+    // The lists are big, inlining lets them be filled in place instead of copied on return
+    #[inline]
+    pub fn get_pseudo_legal_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> MoveList {
+        let mut moves = MoveList::new();
+        Self::generate_moves(pos, move_gen_kind, &LegalMasks::none(), &mut moves);
         moves
     }
 
-    pub fn get_legal_moves(mut pos: &mut Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    #[inline]
+    pub fn get_legal_moves(pos: &mut Position, move_gen_kind: &MoveGenKind) -> MoveList {
+        let mut moves = MoveList::new();
+        Self::add_legal_moves(pos, move_gen_kind, &mut moves);
+        moves
+    }
 
-        // Pawn
-        let pawn_moves: Vec<MoveInfo> = Self::get_pawn_moves(pos, move_gen_kind)
-            .into_iter()
-            .filter(|m| Self::is_legal(&mut pos, m))
-            .collect();
-        moves.extend(pawn_moves);
+    // Adds the legal moves to a list that may already hold some
+    pub fn add_legal_moves(pos: &Position, move_gen_kind: &MoveGenKind, moves: &mut MoveList) {
+        let start = moves.len();
+        Self::generate_moves(pos, move_gen_kind, &LegalMasks::new(pos), moves);
+        Self::retain_legal_special_moves(pos, moves, start);
+    }
 
-        // Knights
-        let knight_moves: Vec<MoveInfo> = Self::get_knight_moves(pos, move_gen_kind)
-            .into_iter()
-            .filter(|m| Self::is_legal(&mut pos, m))
-            .collect();
-        moves.extend(knight_moves);
+    // Adds the legal moves of one kind of piece
+    pub fn get_legal_piece_moves(
+        pos: &Position,
+        piece: usize,
+        move_gen_kind: &MoveGenKind,
+        moves: &mut MoveList,
+    ) {
+        let masks = LegalMasks::new(pos);
+        match piece {
+            Piece::PAWN => Self::get_pawn_moves(pos, move_gen_kind, &masks, moves),
+            Piece::KNIGHT => Self::get_knight_moves(pos, move_gen_kind, &masks, moves),
+            Piece::KING => Self::get_king_moves(pos, move_gen_kind, &masks, moves),
+            Piece::ROOK => Self::get_rook_moves(pos, move_gen_kind, &masks, moves),
+            Piece::BISHOP => Self::get_bishop_moves(pos, move_gen_kind, &masks, moves),
+            _ => Self::get_queen_moves(pos, move_gen_kind, &masks, moves),
+        }
+        Self::retain_legal_special_moves(pos, moves, 0);
+    }
 
-        // King
-        let king_moves: Vec<MoveInfo> = Self::get_king_moves(pos, move_gen_kind)
-            .into_iter()
-            .filter(|m| Self::is_legal(&mut pos, m))
-            .collect();
-        moves.extend(king_moves);
+    // Whether a move remembered from another position, like a killer, can be played here.
+    // Checked on its own instead of generating the moves, as the picker asks for every killer
+    pub fn is_legal_move(pos: &Position, m: &MoveInfo) -> bool {
+        let side = pos.side_to_move.0;
+        let (from, to) = match m.m {
+            Move::Castle(castling) => {
+                return m.piece == Piece::KING
+                    && m.captured_piece.is_none()
+                    && (castling == Castling::king_side(side)
+                        || castling == Castling::queen_side(side))
+                    && pos.state.castling.0 & castling > 0
+                    && Self::is_castling_path_clear(pos, castling)
+                    && !Self::are_attacked_for_castling(pos, castling);
+            }
+            Move::EnPassant(from, to) => {
+                return m.piece == Piece::PAWN
+                    && m.captured_piece == Some(Piece::PAWN)
+                    && pos.board.pieces[side][Piece::PAWN].0 & from > 0
+                    && to == pos.state.en_passant.0
+                    && to != Square::NONE
+                    && Self::is_square_empty(pos, to)
+                    && pawn_attacks(from, side) & to > 0
+                    && Self::is_legal_en_passant(pos, from, to);
+            }
+            Move::Normal(from, to) => (from, to),
+            Move::Promotion(from, to, promoted) => {
+                if m.piece != Piece::PAWN
+                    || !matches!(
+                        promoted,
+                        Piece::QUEEN | Piece::ROOK | Piece::KNIGHT | Piece::BISHOP
+                    )
+                {
+                    return false;
+                }
+                (from, to)
+            }
+        };
 
-        // Rook
-        let rook_moves: Vec<MoveInfo> = Self::get_rook_moves(pos, move_gen_kind)
-            .into_iter()
-            .filter(|m| Self::is_legal(&mut pos, m))
-            .collect();
-        moves.extend(rook_moves);
+        // Pseudo legal: the piece is there, its target isn't ours and nothing is in its way
+        if pos.board.pieces[side][m.piece].0 & from == 0
+            || pos.board.side_pieces[side].0 & to > 0
+            || m.captured_piece != Self::get_enemy_piece_in_square(pos, to, false)
+        {
+            return false;
+        }
+        let occupancy = Self::get_occupancy(pos);
+        let from_index = from.trailing_zeros() as usize;
+        let reachable = match m.piece {
+            Piece::PAWN => {
+                // Pawns promote exactly when they reach the last row
+                let is_promotion = matches!(m.m, Move::Promotion(_, _, _));
+                if is_promotion != (to & Square::EXTREME_ROWS > 0) {
+                    return false;
+                }
+                if m.captured_piece.is_some() {
+                    pawn_attacks(from, side)
+                } else {
+                    let (push, start_row) = if side == Side::WHITE {
+                        (from << 8, Square::SECOND_ROW)
+                    } else {
+                        (from >> 8, Square::SEVENTH_ROW)
+                    };
+                    let double_push = if from & start_row > 0 && push & occupancy == 0 {
+                        if side == Side::WHITE {
+                            push << 8
+                        } else {
+                            push >> 8
+                        }
+                    } else {
+                        0
+                    };
+                    (push | double_push) & !occupancy
+                }
+            }
+            Piece::KNIGHT => KNIGHTS_LOOKUP[from_index],
+            Piece::BISHOP => MAGIC.get_bishop_attacks(from_index, occupancy),
+            Piece::ROOK => MAGIC.get_rook_attacks(from_index, occupancy),
+            Piece::QUEEN => {
+                MAGIC.get_bishop_attacks(from_index, occupancy)
+                    | MAGIC.get_rook_attacks(from_index, occupancy)
+            }
+            _ => KING_LOOKUP[from_index],
+        };
+        if reachable & to == 0 {
+            return false;
+        }
 
-        // Bishop
-        let bishop_moves: Vec<MoveInfo> = Self::get_bishop_moves(pos, move_gen_kind)
-            .into_iter()
-            .filter(|m| Self::is_legal(&mut pos, m))
-            .collect();
-        moves.extend(bishop_moves);
+        // Legal: the king doesn't walk into an attack, the others answer checks and keep pins
+        let king = pos.board.pieces[side][Piece::KING].0;
+        if king == 0 {
+            return true;
+        }
+        if m.piece == Piece::KING {
+            // The king doesn't shield the squares behind itself from sliders
+            return pos.attackers_to(to, pos.opposite_side(), occupancy & !king) == 0;
+        }
+        let checkers = pos.checkers();
+        let answers_check = match checkers.count_ones() {
+            0 => true,
+            1 => to & (checkers | between(king, checkers)) > 0,
+            _ => false,
+        };
+        answers_check && (pos.pinned(side) & from == 0 || line(king, from) & to > 0)
+    }
 
+    // Castling and en passant are rare enough to be checked on their own
+    fn retain_legal_special_moves(pos: &Position, moves: &mut MoveList, start: usize) {
+        moves.retain_from(start, |m| match m.m {
+            Move::Castle(castling) => !Self::are_attacked_for_castling(pos, castling),
+            Move::EnPassant(from, to) => Self::is_legal_en_passant(pos, from, to),
+            _ => true,
+        });
+    }
+
+    fn generate_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
+        // Pawn
+        Self::get_pawn_moves(pos, move_gen_kind, masks, moves);
+        // Knights
+        Self::get_knight_moves(pos, move_gen_kind, masks, moves);
+        // King
+        Self::get_king_moves(pos, move_gen_kind, masks, moves);
+        // Rook
+        Self::get_rook_moves(pos, move_gen_kind, masks, moves);
+        // Bishop
+        Self::get_bishop_moves(pos, move_gen_kind, masks, moves);
         // Queen
-        let queen_moves: Vec<MoveInfo> = Self::get_queen_moves(pos, move_gen_kind)
-            .into_iter()
-            .filter(|m| Self::is_legal(&mut pos, m))
-            .collect();
-        moves.extend(queen_moves);
+        Self::get_queen_moves(pos, move_gen_kind, masks, moves);
+    }
 
-        moves
+    // En passant takes two pawns off the same rank, so it can uncover a check no pin covers
+    fn is_legal_en_passant(pos: &Position, from: u64, to: u64) -> bool {
+        let captured = if pos.side_to_move.0 == Side::WHITE {
+            to >> 8
+        } else {
+            to << 8
+        };
+        let king = pos.board.pieces[pos.side_to_move.0][Piece::KING].0;
+        if king == 0 {
+            return true;
+        }
+        let occupancy = (pos.occupancy() & !(from | captured)) | to;
+        pos.attackers_to(king, pos.opposite_side(), occupancy) & !captured == 0
     }
 
-    fn get_queen_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    fn get_queen_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
         let mut queens = pos.board.pieces[pos.side_to_move.0][Piece::QUEEN].0;
 
         let occupancy = Self::get_occupancy(pos);
@@ -246,8 +405,9 @@ impl MoveGenerator {
             let square = 1u64 << square_index;
 
             // Get all the possible squares from the lookup table
-            let mut move_mask = MAGIC.get_bishop_attacks(square_index as usize, occupancy)
-                | MAGIC.get_rook_attacks(square_index as usize, occupancy);
+            let mut move_mask = (MAGIC.get_bishop_attacks(square_index as usize, occupancy)
+                | MAGIC.get_rook_attacks(square_index as usize, occupancy))
+                & masks.for_piece(square);
             match move_gen_kind {
                 MoveGenKind::All => {
                     move_mask &= !pos.board.side_pieces[pos.side_to_move.0].0;
@@ -303,12 +463,14 @@ impl MoveGenerator {
             // Go to the next queen
             queens &= queens - 1
         }
-
-        moves
     }
 
-    fn get_bishop_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    fn get_bishop_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
         let mut bishops = pos.board.pieces[pos.side_to_move.0][Piece::BISHOP].0;
 
         let occupancy = Self::get_occupancy(pos);
@@ -320,7 +482,8 @@ impl MoveGenerator {
                 let square = 1u64 << square_index;
 
                 // Get all the possible squares from the lookup table
-                let mut move_mask = MAGIC.get_bishop_attacks(square_index as usize, occupancy);
+                let mut move_mask = MAGIC.get_bishop_attacks(square_index as usize, occupancy)
+                    & masks.for_piece(square);
                 match move_gen_kind {
                     MoveGenKind::All => {
                         move_mask &= !pos.board.side_pieces[pos.side_to_move.0].0;
@@ -381,12 +544,14 @@ impl MoveGenerator {
                 bishops &= bishops - 1;
             }
         }
-
-        moves
     }
 
-    fn get_rook_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    fn get_rook_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
         let mut rooks = pos.board.pieces[pos.side_to_move.0][Piece::ROOK].0;
 
         let occupancy = Self::get_occupancy(pos);
@@ -396,7 +561,8 @@ impl MoveGenerator {
             let square = 1u64 << square_index;
 
             // Get all the possible squares from the lookup table
-            let mut move_mask = MAGIC.get_rook_attacks(square_index as usize, occupancy);
+            let mut move_mask =
+                MAGIC.get_rook_attacks(square_index as usize, occupancy) & masks.for_piece(square);
             match move_gen_kind {
                 MoveGenKind::All => {
                     move_mask &= !pos.board.side_pieces[pos.side_to_move.0].0;
@@ -452,19 +618,21 @@ impl MoveGenerator {
             // Go to the next rook
             rooks &= rooks - 1;
         }
-
-        moves
     }
 
-    fn get_king_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    fn get_king_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
         let king = pos.board.pieces[pos.side_to_move.0][Piece::KING].0;
         // We have ONE king
         let square_index = king.trailing_zeros();
         let square = 1u64 << square_index;
 
         // Get all the possible squares from the lookup table
-        let mut move_mask = KING_LOOKUP[square_index as usize];
+        let mut move_mask = KING_LOOKUP[square_index as usize] & masks.king_targets;
         match move_gen_kind {
             MoveGenKind::All => {
                 move_mask &= !pos.board.side_pieces[pos.side_to_move.0].0;
@@ -515,63 +683,30 @@ impl MoveGenerator {
         }
 
         if *move_gen_kind == MoveGenKind::All || *move_gen_kind == MoveGenKind::OnlySilent {
-            // Check castling
-            if pos.side_to_move.0 == Side::WHITE {
-                if (pos.state.castling.0 & Castling::WHITE_KING_SIDE) > 0 {
-                    if Self::is_square_empty(pos, Square::F1)
-                        && Self::is_square_empty(pos, Square::G1)
-                    {
-                        moves.push(MoveInfo {
-                            m: Move::Castle(Castling::WHITE_KING_SIDE),
-                            piece: Piece::KING,
-                            captured_piece: None,
-                        });
-                    }
-                }
-                if (pos.state.castling.0 & Castling::WHITE_QUEEN_SIDE) > 0 {
-                    if Self::is_square_empty(pos, Square::B1)
-                        && Self::is_square_empty(pos, Square::C1)
-                        && Self::is_square_empty(pos, Square::D1)
-                    {
-                        moves.push(MoveInfo {
-                            m: Move::Castle(Castling::WHITE_QUEEN_SIDE),
-                            piece: Piece::KING,
-                            captured_piece: None,
-                        });
-                    }
-                }
-            } else {
-                if (pos.state.castling.0 & Castling::BLACK_KING_SIDE) > 0 {
-                    if Self::is_square_empty(pos, Square::F8)
-                        && Self::is_square_empty(pos, Square::G8)
-                    {
-                        moves.push(MoveInfo {
-                            m: Move::Castle(Castling::BLACK_KING_SIDE),
-                            piece: Piece::KING,
-                            captured_piece: None,
-                        });
-                    }
+            for castling in [
+                Castling::king_side(pos.side_to_move.0),
+                Castling::queen_side(pos.side_to_move.0),
+            ] {
+                if pos.state.castling.0 & castling == 0 {
+                    continue;
                 }
-                if (pos.state.castling.0 & Castling::BLACK_QUEEN_SIDE) > 0 {
-                    if Self::is_square_empty(pos, Square::B8)
-                        && Self::is_square_empty(pos, Square::C8)
-                        && Self::is_square_empty(pos, Square::D8)
-                    {
-                        moves.push(MoveInfo {
-                            m: Move::Castle(Castling::BLACK_QUEEN_SIDE),
-                            piece: Piece::KING,
-                            captured_piece: None,
-                        });
-                    }
+                if Self::is_castling_path_clear(pos, castling) {
+                    moves.push(MoveInfo {
+                        m: Move::Castle(castling),
+                        piece: Piece::KING,
+                        captured_piece: None,
+                    });
                 }
             }
         }
-
-        moves
     }
 
-    fn get_knight_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    fn get_knight_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
         let mut knights = pos.board.pieces[pos.side_to_move.0][Piece::KNIGHT].0;
         // Iterate through all the knights
         while knights > 0 {
@@ -579,7 +714,7 @@ impl MoveGenerator {
             let square = 1u64 << square_index;
 
             // Get all the possible squares from the lookup table
-            let mut move_mask = KNIGHTS_LOOKUP[square_index as usize];
+            let mut move_mask = KNIGHTS_LOOKUP[square_index as usize] & masks.for_piece(square);
             match move_gen_kind {
                 MoveGenKind::All => {
                     move_mask &= !pos.board.side_pieces[pos.side_to_move.0].0;
@@ -635,11 +770,14 @@ impl MoveGenerator {
             // Go to the next knight
             knights &= knights - 1;
         }
-        moves
     }
 
-    fn get_pawn_moves(pos: &Position, move_gen_kind: &MoveGenKind) -> Vec<MoveInfo> {
-        let mut moves: Vec<MoveInfo> = vec![];
+    fn get_pawn_moves(
+        pos: &Position,
+        move_gen_kind: &MoveGenKind,
+        masks: &LegalMasks,
+        moves: &mut MoveList,
+    ) {
         let mut pawns = pos.board.pieces[pos.side_to_move.0][Piece::PAWN].0;
         // Iterate through all the pawns
         while pawns > 0 {
@@ -653,6 +791,7 @@ impl MoveGenerator {
                 Square::SECOND_ROW
             };
             let is_promotion = (square & from_row_for_promotion) > 0;
+            let allowed = masks.for_piece(square);
 
             if *move_gen_kind == MoveGenKind::OnlySilent || *move_gen_kind == MoveGenKind::All {
                 // Check if can move forward
@@ -662,7 +801,7 @@ impl MoveGenerator {
                     square.wrapping_shr(8)
                 };
                 let is_front_square_empty = Self::is_square_empty(pos, to);
-                if to != 0 && is_front_square_empty {
+                if to != 0 && is_front_square_empty && to & allowed > 0 {
                     if !is_promotion {
                         moves.push(MoveInfo {
                             m: Move::Normal(square, to),
@@ -705,7 +844,8 @@ impl MoveGenerator {
                     } else {
                         square.wrapping_shr(16)
                     };
-                    if is_front_square_empty && Self::is_square_empty(pos, to2) {
+                    if is_front_square_empty && Self::is_square_empty(pos, to2) && to2 & allowed > 0
+                    {
                         moves.push(MoveInfo {
                             m: Move::Normal(square, to2),
                             piece: Piece::PAWN,
@@ -730,7 +870,7 @@ impl MoveGenerator {
                         square.wrapping_shr(9)
                     }
                 };
-                if to1 != 0 && Self::is_square_enemy(pos, to1) {
+                if to1 & allowed > 0 && Self::is_square_enemy(pos, to1) {
                     if !is_promotion {
                         moves.push(MoveInfo {
                             m: Move::Normal(square, to1),
@@ -774,7 +914,7 @@ impl MoveGenerator {
                         square.wrapping_shr(7)
                     }
                 };
-                if to2 != 0 && Self::is_square_enemy(pos, to2) {
+                if to2 & allowed > 0 && Self::is_square_enemy(pos, to2) {
                     if !is_promotion {
                         moves.push(MoveInfo {
                             m: Move::Normal(square, to2),
@@ -823,7 +963,6 @@ impl MoveGenerator {
             // Go to the next pawn
             pawns &= pawns - 1;
         }
-        moves
     }
 
     fn is_square_empty(pos: &Position, square: u64) -> bool {
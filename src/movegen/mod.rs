@@ -1,3 +1,7 @@
+pub mod attacks;
 pub mod generator;
 pub mod test;
-pub mod magic;
\ No newline at end of file
+pub mod magic;
+pub mod move_list;
+pub mod move_picker;
+pub mod see;
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use arrayvec::ArrayVec;
+
+use super::generator::MoveInfo;
+
+// No position has more legal moves than this
+pub const MAX_MOVES: usize = 256;
+
+// The moves of a position, kept on the stack so generating them allocates nothing.
+// Derefs to a slice for iterating, scoring and sorting in place
+#[derive(Clone, Default)]
+pub struct MoveList {
+    moves: ArrayVec<MoveInfo, MAX_MOVES>,
+}
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            moves: ArrayVec::new(),
+        }
+    }
+
+    pub fn push(&mut self, m: MoveInfo) {
+        self.moves.push(m);
+    }
+
+    // Keeps the moves matching the predicate, in the same order
+    pub fn retain(&mut self, f: impl FnMut(&MoveInfo) -> bool) {
+        self.retain_from(0, f);
+    }
+
+    // Like retain, leaving the moves before `start` alone
+    pub fn retain_from(&mut self, start: usize, mut f: impl FnMut(&MoveInfo) -> bool) {
+        let mut index = 0;
+        self.moves.retain(|m| {
+            index += 1;
+            index <= start || f(m)
+        });
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [MoveInfo];
+
+    fn deref(&self) -> &[MoveInfo] {
+        &self.moves
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [MoveInfo] {
+        &mut self.moves
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a MoveInfo;
+    type IntoIter = std::slice::Iter<'a, MoveInfo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = MoveInfo;
+    type IntoIter = arrayvec::IntoIter<MoveInfo, MAX_MOVES>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter()
+    }
+}
@@ -0,0 +1,248 @@
+use arrayvec::ArrayVec;
+
+use crate::{
+    board::models::{Engine, Move, Piece, Position},
+    constants::PIECE_VALUES,
+};
+
+use super::{
+    generator::{MoveGenKind, MoveGenerator, MoveInfo},
+    move_list::{MoveList, MAX_MOVES},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    TtMove,
+    GenerateCaptures,
+    GoodCaptures,
+    GeneratePromotions,
+    Promotions,
+    Killers,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// Hands out the moves of a position one at a time, best looking first.
+// Moves are only generated and sorted when the stage before them didn't cause a cutoff,
+// so a node refuted by its first capture never generates its quiet moves
+pub struct MovePicker {
+    stage: Stage,
+    only_captures: bool,
+    tt_move: Option<MoveInfo>,
+    killers: [Option<MoveInfo>; 2],
+    counter_move: Option<MoveInfo>,
+    // Moves already handed out before their stage was generated
+    picked: ArrayVec<MoveInfo, 4>,
+    // Captures first, then quiet promotions, then the other quiet moves
+    moves: MoveList,
+    scores: ArrayVec<isize, MAX_MOVES>,
+    // The part of the list the current stage picks from
+    index: usize,
+    end: usize,
+    captures_end: usize,
+    bad_captures_start: usize,
+    killer_index: usize,
+}
+impl MovePicker {
+    pub fn new(
+        tt_move: Option<MoveInfo>,
+        killers: [Option<MoveInfo>; 2],
+        counter_move: Option<MoveInfo>,
+    ) -> MovePicker {
+        MovePicker {
+            stage: Stage::TtMove,
+            only_captures: false,
+            tt_move,
+            killers,
+            counter_move,
+            picked: ArrayVec::new(),
+            moves: MoveList::new(),
+            scores: ArrayVec::new(),
+            index: 0,
+            end: 0,
+            captures_end: 0,
+            bad_captures_start: 0,
+            killer_index: 0,
+        }
+    }
+
+    // Only the captures, good ones before bad ones, for the quiescence search
+    pub fn captures() -> MovePicker {
+        let mut picker = Self::new(None, [None, None], None);
+        picker.stage = Stage::GenerateCaptures;
+        picker.only_captures = true;
+        picker
+    }
+
+    pub fn next(&mut self, engine: &Engine) -> Option<MoveInfo> {
+        let pos = &engine.position;
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if let Some(m) = self.tt_move.take() {
+                        if MoveGenerator::is_legal_move(pos, &m) {
+                            self.picked.push(m.clone());
+                            return Some(m);
+                        }
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    MoveGenerator::add_legal_moves(
+                        pos,
+                        &MoveGenKind::OnlyCaptures,
+                        &mut self.moves,
+                    );
+                    for m in self.moves.iter() {
+                        self.scores.push(Self::capture_score(pos, m));
+                    }
+                    self.captures_end = self.moves.len();
+                    self.end = self.captures_end;
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    match self.pick_best() {
+                        Some(score) if score >= 0 => {
+                            if let Some(m) = self.take_unpicked() {
+                                return Some(m);
+                            }
+                        }
+                        // Everything left loses material, it waits for the quiet moves
+                        _ => {
+                            self.bad_captures_start = self.index;
+                            self.stage = if self.only_captures {
+                                Stage::BadCaptures
+                            } else {
+                                Stage::GeneratePromotions
+                            };
+                        }
+                    }
+                }
+                Stage::GeneratePromotions => {
+                    let mut pawn_moves = MoveList::new();
+                    MoveGenerator::get_legal_piece_moves(
+                        pos,
+                        Piece::PAWN,
+                        &MoveGenKind::OnlySilent,
+                        &mut pawn_moves,
+                    );
+                    self.index = self.moves.len();
+                    for m in pawn_moves {
+                        if let Move::Promotion(_, _, piece) = m.m {
+                            self.scores.push(PIECE_VALUES[piece]);
+                            self.moves.push(m);
+                        }
+                    }
+                    self.end = self.moves.len();
+                    self.stage = Stage::Promotions;
+                }
+                Stage::Promotions => {
+                    if self.pick_best().is_none() {
+                        self.stage = Stage::Killers;
+                    } else if let Some(m) = self.take_unpicked() {
+                        return Some(m);
+                    }
+                }
+                Stage::Killers => {
+                    if self.killer_index == self.killers.len() {
+                        self.stage = Stage::CounterMove;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_index].take();
+                    self.killer_index += 1;
+                    if let Some(m) = self.take_quiet(pos, killer) {
+                        return Some(m);
+                    }
+                }
+                Stage::CounterMove => {
+                    self.stage = Stage::GenerateQuiets;
+                    let counter_move = self.counter_move.take();
+                    if let Some(m) = self.take_quiet(pos, counter_move) {
+                        return Some(m);
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    self.index = self.moves.len();
+                    MoveGenerator::add_legal_moves(pos, &MoveGenKind::OnlySilent, &mut self.moves);
+                    for m in self.moves[self.index..].iter() {
+                        self.scores.push(m.get_value(engine));
+                    }
+                    self.end = self.moves.len();
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    if self.pick_best().is_none() {
+                        self.index = self.bad_captures_start;
+                        self.end = self.captures_end;
+                        self.stage = Stage::BadCaptures;
+                    } else if let Move::Promotion(_, _, _) = self.moves[self.index].m {
+                        // Already handed out in their own stage
+                        self.index += 1;
+                    } else if let Some(m) = self.take_unpicked() {
+                        return Some(m);
+                    }
+                }
+                Stage::BadCaptures => {
+                    if self.pick_best().is_none() {
+                        self.stage = Stage::Done;
+                    } else if let Some(m) = self.take_unpicked() {
+                        return Some(m);
+                    }
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    // Most valuable victim first, least valuable attacker second.
+    // Captures that lose material by static exchange get a negative score
+    fn capture_score(pos: &Position, m: &MoveInfo) -> isize {
+        let mut victim = m.captured_piece.map_or(0, |piece| PIECE_VALUES[piece]);
+        if let Move::Promotion(_, _, piece) = m.m {
+            victim += PIECE_VALUES[piece];
+        }
+        // The king would outweigh everything, take it as a queen
+        let attacker = PIECE_VALUES[m.piece].min(PIECE_VALUES[Piece::QUEEN]);
+        let score = 10 * victim - attacker;
+        if pos.see(m) >= 0 {
+            score
+        } else {
+            score - 100 * PIECE_VALUES[Piece::QUEEN]
+        }
+    }
+
+    // Brings the best scored move left in the current part of the list to its front
+    fn pick_best(&mut self) -> Option<isize> {
+        if self.index >= self.end {
+            return None;
+        }
+        let best = (self.index..self.end).max_by_key(|i| self.scores[*i])?;
+        self.moves.swap(self.index, best);
+        self.scores.swap(self.index, best);
+        Some(self.scores[self.index])
+    }
+
+    // Moves past the front of the current part of the list,
+    // returning it unless it was already handed out
+    fn take_unpicked(&mut self) -> Option<MoveInfo> {
+        let m = &self.moves[self.index];
+        self.index += 1;
+        if self.picked.contains(m) {
+            return None;
+        }
+        Some(m.clone())
+    }
+
+    // Killers and counter-moves come from other positions, so they may not be legal here
+    fn take_quiet(&mut self, pos: &Position, m: Option<MoveInfo>) -> Option<MoveInfo> {
+        let m = m?;
+        if !m.is_quiet() || self.picked.contains(&m) || !MoveGenerator::is_legal_move(pos, &m) {
+            return None;
+        }
+        self.picked.push(m.clone());
+        Some(m)
+    }
+}
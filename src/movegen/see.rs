@@ -0,0 +1,83 @@
+use crate::{
+    board::models::{Move, Piece, Position, Side},
+    constants::PIECE_VALUES,
+};
+
+use super::generator::MoveInfo;
+
+// Pieces in the order they join an exchange, the cheapest first
+const EXCHANGE_ORDER: [usize; 6] = [
+    Piece::PAWN,
+    Piece::KNIGHT,
+    Piece::BISHOP,
+    Piece::ROOK,
+    Piece::QUEEN,
+    Piece::KING,
+];
+
+impl Position {
+    // Static exchange evaluation: the material won or lost by the side to move
+    // if both sides keep capturing on the target square with their cheapest piece
+    pub fn see(&self, m: &MoveInfo) -> isize {
+        let (from, to, promotion) = match m.m {
+            Move::Normal(from, to) | Move::EnPassant(from, to) => (from, to, None),
+            Move::Promotion(from, to, piece) => (from, to, Some(piece)),
+            Move::Castle(_) => return 0,
+        };
+
+        let mut occupancy = self.occupancy() & !from;
+        if let Move::EnPassant(_, _) = m.m {
+            let captured = if self.side_to_move.0 == Side::WHITE {
+                to >> 8
+            } else {
+                to << 8
+            };
+            occupancy &= !captured;
+        }
+
+        let mut gain = [0isize; 32];
+        gain[0] = m.captured_piece.map_or(0, |piece| PIECE_VALUES[piece]);
+        // The piece standing on the target square, about to be taken
+        let mut on_target = PIECE_VALUES[m.piece];
+        if let Some(piece) = promotion {
+            gain[0] += PIECE_VALUES[piece] - PIECE_VALUES[Piece::PAWN];
+            on_target = PIECE_VALUES[piece];
+        }
+
+        let mut side = self.opposite_side();
+        let mut depth = 0;
+        while depth < gain.len() - 1 {
+            // Sliders behind the pieces that already captured join in
+            let attackers = self.attackers_to(to, side, occupancy) & occupancy;
+            let Some((piece, square)) = self.cheapest_attacker(attackers, side) else {
+                break;
+            };
+            // The king can only take if nothing takes it back
+            if piece == Piece::KING
+                && self.attackers_to(to, 1 - side, occupancy & !square) & occupancy > 0
+            {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = on_target - gain[depth - 1];
+            occupancy &= !square;
+            on_target = PIECE_VALUES[piece];
+            side = 1 - side;
+        }
+
+        // Either side can stop capturing when going on would lose more
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    fn cheapest_attacker(&self, attackers: u64, side: usize) -> Option<(usize, u64)> {
+        EXCHANGE_ORDER.into_iter().find_map(|piece| {
+            let pieces = attackers & self.board.pieces[side][piece].0;
+            (pieces > 0).then(|| (piece, 1u64 << pieces.trailing_zeros()))
+        })
+    }
+}
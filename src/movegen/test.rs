@@ -111,6 +111,61 @@ mod movegen_tests {
         assert!(moves.iter().any(|m| format!("{m}") == "g5h5"));
         assert!(moves.iter().any(|m| format!("{m}") == "g5g6"));
     }
+
+    #[test]
+    fn legal_moves_match_playing_every_move() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            // Double check, only the king can move
+            "4k3/8/8/8/8/5n2/4r3/4K3 w - - 0 1",
+            // En passant would uncover a check along the rank
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+            // Pinned pieces moving along the pin
+            "4r2k/8/8/8/1b2R3/8/3N4/4K3 w - - 0 1",
+        ];
+        for fen in fens {
+            let mut pos = FenParser::fen_to_position(fen);
+            let expected: Vec<MoveInfo> =
+                MoveGenerator::get_pseudo_legal_moves(&pos, &MoveGenKind::All)
+                    .into_iter()
+                    .filter(|m| MoveGenerator::is_legal(&mut pos, m))
+                    .collect();
+            let moves = MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All);
+            assert_eq!(moves.to_vec(), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn moves_from_other_positions_are_checked_like_the_legal_moves() {
+        let fens = [
+            START_POS,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "4k3/8/8/8/8/5n2/4r3/4K3 w - - 0 1",
+            "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+            "4r2k/8/8/8/1b2R3/8/3N4/4K3 w - - 0 1",
+            // Promotions, with and without captures
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1",
+            // In check from a pawn that could be taken en passant
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        ];
+        let mut positions: Vec<_> = fens.iter().map(|f| FenParser::fen_to_position(f)).collect();
+        let all_moves: Vec<MoveInfo> = positions
+            .iter_mut()
+            .flat_map(|pos| MoveGenerator::get_legal_moves(pos, &MoveGenKind::All).to_vec())
+            .collect();
+        for (pos, fen) in positions.iter_mut().zip(fens) {
+            let legal = MoveGenerator::get_legal_moves(pos, &MoveGenKind::All);
+            for m in all_moves.iter() {
+                assert_eq!(
+                    MoveGenerator::is_legal_move(pos, m),
+                    legal.contains(m),
+                    "{fen} {m}"
+                );
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -332,3 +387,260 @@ mod perft_tests {
         assert_eq!(perft(&mut sut, 4, true, false, true), 23527);
     }
 }
+
+#[cfg(test)]
+mod chess960_perft_tests {
+    // Positions and values taken from https://www.chessprogramming.org/Chess960_Perft_Results
+    use crate::board::{fen::FenParser, models::Engine, utils::perft};
+
+    fn perft_960(fen: &str, depth: u8) -> usize {
+        let mut sut = Engine::from_position(FenParser::try_parse_chess960(fen).unwrap());
+        perft(&mut sut, depth, true, false, true)
+    }
+
+    #[test]
+    fn check_perft_960_position_1() {
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        assert_eq!(perft_960(fen, 1), 21);
+        assert_eq!(perft_960(fen, 2), 528);
+        assert_eq!(perft_960(fen, 3), 12189);
+        assert_eq!(perft_960(fen, 4), 326672);
+    }
+
+    #[test]
+    fn check_perft_960_position_2() {
+        let fen = "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9";
+        assert_eq!(perft_960(fen, 1), 21);
+        assert_eq!(perft_960(fen, 2), 807);
+        assert_eq!(perft_960(fen, 3), 18002);
+        assert_eq!(perft_960(fen, 4), 667366);
+    }
+
+    #[test]
+    fn check_perft_960_position_3() {
+        let fen = "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9";
+        assert_eq!(perft_960(fen, 1), 20);
+        assert_eq!(perft_960(fen, 2), 479);
+        assert_eq!(perft_960(fen, 3), 10471);
+        assert_eq!(perft_960(fen, 4), 273318);
+    }
+}
+
+#[cfg(test)]
+mod attacks_tests {
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Side, Square},
+        },
+        constants::START_POS,
+    };
+
+    #[test]
+    fn attackers_and_attacked_squares() {
+        let pos = FenParser::fen_to_position(START_POS);
+        assert_eq!(
+            pos.attackers_to(Square::F3, Side::WHITE, pos.occupancy()),
+            Square::E2 | Square::G2 | Square::G1
+        );
+        let occupancy = pos.occupancy();
+        assert_eq!(pos.attackers_to(Square::E4, Side::WHITE, occupancy), 0);
+        assert!(pos.is_square_attacked(Square::E3, Side::WHITE));
+        assert!(!pos.is_square_attacked(Square::E4, Side::WHITE));
+        assert!(pos.is_square_attacked(Square::E6, Side::BLACK));
+    }
+
+    #[test]
+    fn checkers() {
+        let pos = FenParser::fen_to_position("4k3/8/8/8/8/5n2/4r3/4K3 w - - 0 1");
+        assert_eq!(pos.checkers(), Square::E2 | Square::F3);
+        let pos = FenParser::fen_to_position(START_POS);
+        assert_eq!(pos.checkers(), 0);
+    }
+
+    #[test]
+    fn pinned_pieces() {
+        let pos = FenParser::fen_to_position("4r2k/8/8/8/1b2R3/8/3N4/4K3 w - - 0 1");
+        assert_eq!(pos.pinned(Side::WHITE), Square::D2 | Square::E4);
+        assert_eq!(pos.pinned(Side::BLACK), 0);
+        // Two pieces in the way, neither is pinned
+        let pos = FenParser::fen_to_position("4r2k/8/8/8/1b2R3/4P3/3N4/4K3 w - - 0 1");
+        assert_eq!(pos.pinned(Side::WHITE), Square::D2);
+    }
+
+    #[test]
+    fn x_ray_attackers() {
+        let pos = FenParser::fen_to_position("k7/8/8/8/8/8/4R3/4R1K1 w - - 0 1");
+        let occupancy = pos.occupancy();
+        assert_eq!(pos.attackers_to(Square::E7, Side::WHITE, occupancy), Square::E2);
+        assert_eq!(pos.x_ray_attackers(Square::E7, Side::WHITE, occupancy), Square::E1);
+    }
+}
+
+#[cfg(test)]
+mod move_list_tests {
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Move, Piece, Square},
+        },
+        constants::START_POS,
+        movegen::{
+            generator::{MoveGenKind, MoveGenerator, MoveInfo},
+            move_list::MoveList,
+        },
+    };
+
+    fn pawn_move(from: u64, to: u64) -> MoveInfo {
+        MoveInfo {
+            m: Move::Normal(from, to),
+            piece: Piece::PAWN,
+            captured_piece: None,
+        }
+    }
+
+    #[test]
+    fn push_retain_and_iterate() {
+        let mut moves = MoveList::new();
+        assert!(moves.is_empty());
+        moves.push(pawn_move(Square::A2, Square::A3));
+        moves.push(pawn_move(Square::B2, Square::B3));
+        moves.push(pawn_move(Square::C2, Square::C3));
+        assert_eq!(moves.len(), 3);
+        assert_eq!(moves[1], pawn_move(Square::B2, Square::B3));
+
+        moves.retain(|m| m.m != Move::Normal(Square::B2, Square::B3));
+        assert_eq!(
+            moves.into_iter().collect::<Vec<MoveInfo>>(),
+            vec![pawn_move(Square::A2, Square::A3), pawn_move(Square::C2, Square::C3)]
+        );
+    }
+
+    #[test]
+    fn moves_are_sorted_in_place() {
+        let mut engine = Engine::from_position(FenParser::fen_to_position(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ));
+        let moves = MoveGenerator::get_ordered_moves(&mut engine);
+        assert_eq!(moves.len(), 48);
+        let values: Vec<isize> = moves.iter().map(|m| m.get_value(&engine)).collect();
+        assert!(values.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        let moves = MoveGenerator::get_legal_moves(
+            &mut FenParser::fen_to_position(START_POS),
+            &MoveGenKind::All,
+        );
+        assert_eq!(moves.len(), 20);
+    }
+}
+
+#[cfg(test)]
+mod move_picker_tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Move, Piece, Square},
+        },
+        movegen::{
+            generator::{MoveGenKind, MoveGenerator, MoveInfo},
+            move_picker::MovePicker,
+        },
+    };
+
+    fn new_move(m: Move, piece: usize, captured_piece: Option<usize>) -> MoveInfo {
+        MoveInfo {
+            m,
+            piece,
+            captured_piece,
+        }
+    }
+
+    fn picked_moves(engine: &Engine, mut picker: MovePicker) -> Vec<MoveInfo> {
+        let mut moves = vec![];
+        while let Some(m) = picker.next(engine) {
+            moves.push(m);
+        }
+        moves
+    }
+
+    #[test]
+    fn static_exchange_evaluation() {
+        // Pawn takes a knight defended by a pawn
+        let pos = FenParser::fen_to_position("4k3/8/3p4/4n3/3P4/8/8/4K3 w - - 0 1");
+        let m = new_move(
+            Move::Normal(Square::D4, Square::E5),
+            Piece::PAWN,
+            Some(Piece::KNIGHT),
+        );
+        assert_eq!(pos.see(&m), 223);
+
+        // Queen takes a pawn defended by a pawn
+        let pos = FenParser::fen_to_position("4k3/8/3p4/4p3/8/8/7Q/4K3 w - - 0 1");
+        let m = new_move(
+            Move::Normal(Square::H2, Square::E5),
+            Piece::QUEEN,
+            Some(Piece::PAWN),
+        );
+        assert_eq!(pos.see(&m), -800);
+
+        // The rook behind the first one wins the pawn
+        let pos = FenParser::fen_to_position("4k3/4r3/8/4p3/8/8/4R3/4R1K1 w - - 0 1");
+        let m = new_move(
+            Move::Normal(Square::E2, Square::E5),
+            Piece::ROOK,
+            Some(Piece::PAWN),
+        );
+        assert_eq!(pos.see(&m), 100);
+    }
+
+    #[test]
+    fn picks_every_legal_move_once() {
+        let mut engine = Engine::from_position(FenParser::fen_to_position(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ));
+        let tt_move = new_move(
+            Move::Normal(Square::E5, Square::F7),
+            Piece::KNIGHT,
+            Some(Piece::PAWN),
+        );
+        let killer = new_move(Move::Normal(Square::A2, Square::A3), Piece::PAWN, None);
+        // The bishop stands on e2
+        let illegal_killer = new_move(Move::Normal(Square::E1, Square::E2), Piece::KING, None);
+        let picker = MovePicker::new(
+            Some(tt_move.clone()),
+            [Some(illegal_killer), Some(killer.clone())],
+            None,
+        );
+        let picked = picked_moves(&engine, picker);
+        assert_eq!(picked[0], tt_move);
+        assert!(picked.contains(&killer));
+
+        let legal_moves = MoveGenerator::get_legal_moves(&mut engine.position, &MoveGenKind::All);
+        assert_eq!(picked.len(), legal_moves.len());
+        let picked: HashSet<MoveInfo> = picked.into_iter().collect();
+        let legal_moves: HashSet<MoveInfo> = legal_moves.into_iter().collect();
+        assert_eq!(picked, legal_moves);
+
+        let captures = picked_moves(&engine, MovePicker::captures());
+        let legal_captures =
+            MoveGenerator::get_legal_moves(&mut engine.position, &MoveGenKind::OnlyCaptures);
+        assert_eq!(captures.len(), legal_captures.len());
+    }
+
+    #[test]
+    fn losing_captures_go_last() {
+        let engine = Engine::from_position(FenParser::fen_to_position(
+            "4k3/8/3p4/4p3/8/8/7Q/4K3 w - - 0 1",
+        ));
+        let bad_capture = new_move(
+            Move::Normal(Square::H2, Square::E5),
+            Piece::QUEEN,
+            Some(Piece::PAWN),
+        );
+        let picked = picked_moves(&engine, MovePicker::new(None, [None, None], None));
+        assert!(picked.len() > 1);
+        assert_eq!(picked.last(), Some(&bad_capture));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    board::{fen::FenParser, models::Position, models::Side},
+    constants::{MATE_VALUE, START_POS},
+    movegen::generator::MoveInfo,
+};
+
+use super::reader::{is_chess960, PgnGame};
+
+// Tags every PGN export starts with, in this order
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+const MAX_LINE_LENGTH: usize = 80;
+
+// Scores this close to MATE_VALUE are mates, the difference being the plies to mate
+const MATE_THRESHOLD: isize = MATE_VALUE - 1000;
+
+// The record of a game: where it started, the moves played and how it ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start_fen: String,
+    pub moves: Vec<GameMove>,
+    // As written in a PGN, "*" while the game is going on
+    pub result: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameMove {
+    pub m: MoveInfo,
+    pub comment: Option<String>,
+    // In centipawns, from white's point of view
+    pub eval: Option<isize>,
+}
+
+impl Game {
+    pub fn new(start: &Position) -> Game {
+        let mut game = Game {
+            tags: vec![],
+            start_fen: start.to_fen(),
+            moves: vec![],
+            result: String::from("*"),
+        };
+        if start.is_chess960 {
+            game.set_tag("Variant", "Chess960");
+        }
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some(tag) => tag.1 = String::from(value),
+            None => self.tags.push((String::from(name), String::from(value))),
+        }
+    }
+
+    pub fn push_move(&mut self, m: MoveInfo, comment: Option<String>, eval: Option<isize>) {
+        self.moves.push(GameMove { m, comment, eval });
+    }
+
+    pub fn start_position(&self) -> Position {
+        if is_chess960(self.tag("Variant")) {
+            FenParser::try_parse_chess960(&self.start_fen).unwrap()
+        } else {
+            FenParser::fen_to_position(&self.start_fen)
+        }
+    }
+
+    // The position after the last move
+    pub fn position(&self) -> Position {
+        let mut pos = self.start_position();
+        for game_move in self.moves.iter() {
+            pos.apply_move(&game_move.m);
+        }
+        pos
+    }
+
+    // Export the game as PGN: seven tag roster first, SAN moves, lines wrapped at 80 columns
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        // Tags
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                "Date" => self.tag(name).unwrap_or("????.??.??"),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            pgn.push_str(&format_tag(name, value));
+        }
+        if self.start_fen != START_POS {
+            pgn.push_str(&format_tag("SetUp", "1"));
+            pgn.push_str(&format_tag("FEN", &self.start_fen));
+        }
+        for (name, value) in self.tags.iter() {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+                pgn.push_str(&format_tag(name, value));
+            }
+        }
+        pgn.push('\n');
+
+        // Movetext
+        let mut movetext: Vec<String> = vec![];
+        let mut pos = self.start_position();
+        let mut after_comment = false;
+        for (index, game_move) in self.moves.iter().enumerate() {
+            let move_number = pos.half_move_number / 2 + 1;
+            if pos.side_to_move.0 == Side::WHITE {
+                movetext.push(format!("{move_number}."));
+            } else if index == 0 || after_comment {
+                movetext.push(format!("{move_number}..."));
+            }
+            movetext.push(game_move.m.to_san(&pos));
+            after_comment = false;
+
+            let eval = game_move.eval.map(|eval| format!("[%eval {}]", format_eval(eval)));
+            let comment = game_move
+                .comment
+                .as_ref()
+                .map(|comment| comment.replace('}', ")"));
+            let comment: Vec<String> = eval.into_iter().chain(comment).collect();
+            if !comment.is_empty() {
+                movetext.push(format!("{{{}}}", comment.join(" ")));
+                after_comment = true;
+            }
+            pos.apply_move(&game_move.m);
+        }
+        movetext.push(self.result.clone());
+
+        // Wrap, comments can be split on any space
+        let mut line = String::new();
+        for word in movetext.iter().flat_map(|token| token.split_whitespace()) {
+            if !line.is_empty() && line.len() + 1 + word.len() > MAX_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+// The main line of a game read from a PGN, evals are taken out of the "[%eval]" comments.
+// The Result, SetUp and FEN tags are not kept, the export writes them from the game itself
+impl From<&PgnGame> for Game {
+    fn from(pgn_game: &PgnGame) -> Game {
+        let mut game = Game::new(&pgn_game.start);
+        game.tags = pgn_game
+            .tags
+            .iter()
+            .filter(|(name, _)| !["Result", "SetUp", "FEN"].contains(&name.as_str()))
+            .cloned()
+            .collect();
+        game.result = pgn_game.result.clone();
+        for pgn_move in pgn_game.moves.iter() {
+            let mut eval = None;
+            let mut comments = vec![];
+            for comment in pgn_move.comments.iter() {
+                let (comment_eval, text) = parse_eval(comment);
+                eval = eval.or(comment_eval);
+                if !text.is_empty() {
+                    comments.push(text);
+                }
+            }
+            let comment = if comments.is_empty() {
+                None
+            } else {
+                Some(comments.join(" "))
+            };
+            game.push_move(pgn_move.m.clone(), comment, eval);
+        }
+        game
+    }
+}
+
+fn format_tag(name: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("[{name} \"{value}\"]\n")
+}
+
+// Pawns with two decimals, or "#n" for a mate in n moves (negative if white gets mated)
+fn format_eval(eval: isize) -> String {
+    if eval.abs() >= MATE_THRESHOLD {
+        let moves = (MATE_VALUE - eval.abs() + 1) / 2;
+        return format!("#{}{}", if eval < 0 { "-" } else { "" }, moves.max(1));
+    }
+    format!("{:.2}", eval as f64 / 100.0)
+}
+
+// Split a comment in its "[%eval]" command, if any, and the rest of the text
+fn parse_eval(comment: &str) -> (Option<isize>, String) {
+    let Some(start) = comment.find("[%eval ") else {
+        return (None, String::from(comment));
+    };
+    let Some(length) = comment[start..].find(']') else {
+        return (None, String::from(comment));
+    };
+    let value = comment[start + "[%eval ".len()..start + length].trim();
+    let eval = match value.strip_prefix('#') {
+        Some(moves) => moves.parse::<isize>().ok().map(|moves| {
+            let plies = moves.abs() * 2 - 1;
+            (MATE_VALUE - plies) * moves.signum()
+        }),
+        None => value
+            .parse::<f64>()
+            .ok()
+            .map(|pawns| (pawns * 100.0).round() as isize),
+    };
+    let text = format!("{}{}", &comment[..start], &comment[start + length + 1..]);
+    (eval, String::from(text.trim()))
+}
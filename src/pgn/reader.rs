@@ -0,0 +1,468 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    iter::Peekable,
+    path::Path,
+    str::Chars,
+};
+
+use crate::{
+    board::{
+        fen::{FenError, FenParser},
+        models::Position,
+        san::SanError,
+    },
+    constants::START_POS,
+    movegen::generator::MoveInfo,
+};
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// A single game read from a PGN file, already replayed from its starting position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    // Tag pairs in the order they appear
+    pub tags: Vec<(String, String)>,
+    // From the FEN tag if there is one, the standard starting position otherwise
+    pub start: Position,
+    pub moves: Vec<PgnMove>,
+    // Comments before the first move
+    pub comments: Vec<String>,
+    // The game termination marker, "*" if the game doesn't have one
+    pub result: String,
+}
+
+// A move of the main line or of a variation, with everything annotating it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    // As written in the file
+    pub san: String,
+    pub m: MoveInfo,
+    // Numeric annotation glyphs, "!" and "?" style suffixes are converted to their NAG
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    // Alternatives to this move, each one starting from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+// A game that could not be read, the reader moves on to the next one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    // Line of the file where the game starts
+    pub line: usize,
+    pub kind: PgnErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    // Reading the file failed, no more games will follow
+    Io(String),
+    InvalidTag(String),
+    InvalidFen(FenError),
+    // The move can't be played, the number is the ply from the start of its line
+    InvalidMove(usize, SanError),
+    // A null move ("--" or "Z0") at this ply, games with them can't be replayed
+    NullMove(usize),
+    UnterminatedComment,
+    UnbalancedVariation,
+    // A variation or a NAG before any move it could refer to
+    MisplacedAnnotation,
+}
+impl std::error::Error for PgnError {}
+
+// Streams games out of a PGN source, one at a time.
+// Only the game being read is kept in memory
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    line_number: usize,
+    // A tag line already read that belongs to the next game
+    pending_line: Option<String>,
+    failed: bool,
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PgnReader<BufReader<File>>> {
+        Ok(PgnReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> PgnReader<R> {
+        PgnReader {
+            reader,
+            line_number: 0,
+            pending_line: None,
+            failed: false,
+        }
+    }
+
+    // Next line without the line ending. PGN files are not always UTF-8, so be lenient
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.pending_line.take() {
+            return Ok(Some(line));
+        }
+        let mut buffer = vec![];
+        if self.reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(None);
+        }
+        self.line_number += 1;
+        let line = String::from_utf8_lossy(&buffer);
+        Ok(Some(String::from(line.trim_end_matches(['\n', '\r']))))
+    }
+
+    // Raw tag lines and movetext of the next game, with the line it starts on
+    fn read_game_text(&mut self) -> io::Result<Option<(usize, Vec<String>, String)>> {
+        let mut tag_lines = vec![];
+        let mut movetext = String::new();
+        let mut start_line = 0;
+        let mut in_comment = false;
+        let mut after_blank_line = false;
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim();
+            // Escaped lines are for other programs
+            if !in_comment && line.starts_with('%') {
+                continue;
+            }
+            // A tag after a blank line starts the next game, even if a comment was never closed.
+            // This game fails to parse, the next ones aren't lost in it
+            if in_comment && after_blank_line && trimmed.starts_with('[') {
+                self.pending_line = Some(line);
+                break;
+            }
+            after_blank_line = trimmed.is_empty();
+            if trimmed.is_empty() && !in_comment {
+                continue;
+            }
+            if start_line == 0 {
+                start_line = self.line_number;
+            }
+            if !in_comment && trimmed.starts_with('[') {
+                if !movetext.is_empty() {
+                    // The previous game had no termination marker
+                    self.pending_line = Some(line);
+                    break;
+                }
+                tag_lines.push(String::from(trimmed));
+                continue;
+            }
+
+            movetext.push_str(&line);
+            movetext.push('\n');
+            in_comment = Self::ends_in_comment(&line, in_comment);
+            if !in_comment && Self::ends_with_result(&line) {
+                break;
+            }
+        }
+        if start_line == 0 {
+            return Ok(None);
+        }
+        Ok(Some((start_line, tag_lines, movetext)))
+    }
+
+    // Check if a brace comment is still open at the end of the line
+    fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+        for c in line.chars() {
+            match c {
+                '{' if !in_comment => in_comment = true,
+                '}' if in_comment => in_comment = false,
+                ';' if !in_comment => break,
+                _ => {}
+            }
+        }
+        in_comment
+    }
+
+    fn ends_with_result(line: &str) -> bool {
+        let line = line.split(';').next().unwrap_or_default();
+        line.split_whitespace()
+            .last()
+            .is_some_and(|token| RESULTS.contains(&token))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.read_game_text() {
+            Ok(Some((line, tag_lines, movetext))) => Some(
+                PgnGame::parse(&tag_lines, &movetext).map_err(|kind| PgnError { line, kind }),
+            ),
+            Ok(None) => None,
+            Err(error) => {
+                self.failed = true;
+                Some(Err(PgnError {
+                    line: self.line_number,
+                    kind: PgnErrorKind::Io(error.to_string()),
+                }))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Move(String, Vec<u8>),
+    Nag(u8),
+    Comment(String),
+    OpenVariation,
+    CloseVariation,
+    Result(String),
+}
+
+// Chess960 games are marked by their Variant tag
+pub fn is_chess960(variant: Option<&str>) -> bool {
+    variant.is_some_and(|variant| {
+        variant.eq_ignore_ascii_case("chess960") || variant.eq_ignore_ascii_case("fischerandom")
+    })
+}
+
+impl PgnGame {
+    pub fn parse(tag_lines: &[String], movetext: &str) -> Result<PgnGame, PgnErrorKind> {
+        let mut tags = vec![];
+        for line in tag_lines {
+            Self::parse_tags(line, &mut tags)?;
+        }
+
+        let variant = tags
+            .iter()
+            .find(|(name, _)| name == "Variant")
+            .map(|(_, variant)| variant.as_str());
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) if is_chess960(variant) => {
+                FenParser::try_parse_chess960(fen).map_err(PgnErrorKind::InvalidFen)?
+            }
+            Some((_, fen)) => FenParser::try_parse(fen).map_err(PgnErrorKind::InvalidFen)?,
+            None => FenParser::fen_to_position(START_POS),
+        };
+
+        let tokens = Self::tokenize(movetext)?;
+        let mut tokens = tokens.into_iter().peekable();
+        let mut comments = vec![];
+        while let Some(Token::Comment(comment)) = tokens.peek() {
+            comments.push(comment.clone());
+            tokens.next();
+        }
+        let moves = Self::parse_line(&mut tokens, start.clone(), false)?;
+        let result = match tokens.next() {
+            Some(Token::Result(result)) => result,
+            Some(Token::CloseVariation) => return Err(PgnErrorKind::UnbalancedVariation),
+            _ => String::from("*"),
+        };
+
+        Ok(PgnGame {
+            tags,
+            start,
+            moves,
+            comments,
+            result,
+        })
+    }
+
+    // The value of a tag, if the game has it
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The starting position followed by the position after every move of the main line
+    pub fn positions(&self) -> impl Iterator<Item = Position> + '_ {
+        let start = self.start.clone();
+        std::iter::once(start.clone()).chain(self.moves.iter().scan(start, |pos, pgn_move| {
+            pos.apply_move(&pgn_move.m);
+            Some(pos.clone())
+        }))
+    }
+
+    // All the tag pairs on a line, like [Event "Casual game"]
+    fn parse_tags(line: &str, tags: &mut Vec<(String, String)>) -> Result<(), PgnErrorKind> {
+        let invalid = || PgnErrorKind::InvalidTag(String::from(line));
+        let mut chars = line.trim().chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c != '[' {
+                return Err(invalid());
+            }
+            let name: String = chars
+                .by_ref()
+                .skip_while(|c| c.is_whitespace())
+                .take_while(|c| *c != '"')
+                .collect();
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(invalid());
+            }
+            // Quotes and backslashes in the value are escaped with a backslash
+            let mut value = String::new();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    _ => value.push(c),
+                }
+            }
+            if !closed {
+                return Err(invalid());
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.next() != Some(']') {
+                return Err(invalid());
+            }
+            tags.push((String::from(name), value));
+        }
+        Ok(())
+    }
+
+    fn tokenize(movetext: &str) -> Result<Vec<Token>, PgnErrorKind> {
+        let mut tokens = vec![];
+        let mut chars = movetext.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut comment = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => comment.push(c),
+                            None => return Err(PgnErrorKind::UnterminatedComment),
+                        }
+                    }
+                    tokens.push(Token::Comment(Self::clean_comment(&comment)));
+                }
+                ';' => {
+                    let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                    tokens.push(Token::Comment(Self::clean_comment(&comment)));
+                }
+                '(' => tokens.push(Token::OpenVariation),
+                ')' => tokens.push(Token::CloseVariation),
+                '$' => {
+                    let nag = Self::take_word(&mut chars);
+                    if let Ok(nag) = nag.parse() {
+                        tokens.push(Token::Nag(nag));
+                    }
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let word = format!("{c}{}", Self::take_word(&mut chars));
+                    if RESULTS.contains(&word.as_str()) {
+                        tokens.push(Token::Result(word));
+                        continue;
+                    }
+                    // Move numbers, possibly glued to the move as in "1.e4"
+                    let digits_end = word.find(|c: char| !c.is_ascii_digit()).unwrap_or(word.len());
+                    let san = if word[digits_end..].starts_with('.') {
+                        word[digits_end..].trim_start_matches('.')
+                    } else if digits_end == word.len() {
+                        ""
+                    } else {
+                        &word
+                    };
+                    if san.is_empty() {
+                        continue;
+                    }
+                    let annotation_start = san.find(['!', '?']).unwrap_or(san.len());
+                    let nags = match &san[annotation_start..] {
+                        "!" => vec![1],
+                        "?" => vec![2],
+                        "!!" => vec![3],
+                        "??" => vec![4],
+                        "!?" => vec![5],
+                        "?!" => vec![6],
+                        _ => vec![],
+                    };
+                    tokens.push(Token::Move(String::from(&san[..annotation_start]), nags));
+                }
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn take_word(chars: &mut Peekable<Chars>) -> String {
+        let mut word = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}();$".contains(*c)) {
+            word.push(c);
+        }
+        word
+    }
+
+    // Comments spanning more lines are joined in a single line
+    fn clean_comment(comment: &str) -> String {
+        comment.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+
+    // Replay a line of moves from the given position, recursing into its variations.
+    // Stops before the result, or after the closing parenthesis of a variation
+    fn parse_line(
+        tokens: &mut Peekable<std::vec::IntoIter<Token>>,
+        mut pos: Position,
+        is_variation: bool,
+    ) -> Result<Vec<PgnMove>, PgnErrorKind> {
+        let mut moves: Vec<PgnMove> = vec![];
+        // Comments before the first move of a variation go with that move
+        let mut leading_comments = vec![];
+        let mut prev_pos: Option<Position> = None;
+        loop {
+            match tokens.peek() {
+                None | Some(Token::Result(_)) => {
+                    if is_variation {
+                        return Err(PgnErrorKind::UnbalancedVariation);
+                    }
+                    return Ok(moves);
+                }
+                Some(Token::CloseVariation) => {
+                    if is_variation {
+                        tokens.next();
+                    }
+                    return Ok(moves);
+                }
+                _ => {}
+            }
+            match tokens.next().unwrap() {
+                Token::Move(san, _) if san == "--" || san == "Z0" => {
+                    return Err(PgnErrorKind::NullMove(moves.len() + 1));
+                }
+                Token::Move(san, nags) => {
+                    let m = pos
+                        .parse_san(&san)
+                        .map_err(|error| PgnErrorKind::InvalidMove(moves.len() + 1, error))?;
+                    prev_pos = Some(pos.clone());
+                    pos.apply_move(&m);
+                    moves.push(PgnMove {
+                        san,
+                        m,
+                        nags,
+                        comments: std::mem::take(&mut leading_comments),
+                        variations: vec![],
+                    });
+                }
+                Token::Nag(nag) => match moves.last_mut() {
+                    Some(last) => last.nags.push(nag),
+                    None => return Err(PgnErrorKind::MisplacedAnnotation),
+                },
+                Token::Comment(comment) => match moves.last_mut() {
+                    Some(last) => last.comments.push(comment),
+                    None => leading_comments.push(comment),
+                },
+                Token::OpenVariation => {
+                    let (Some(last), Some(prev_pos)) = (moves.last_mut(), &prev_pos) else {
+                        return Err(PgnErrorKind::MisplacedAnnotation);
+                    };
+                    let variation = Self::parse_line(tokens, prev_pos.clone(), true)?;
+                    last.variations.push(variation);
+                }
+                Token::CloseVariation | Token::Result(_) => unreachable!(),
+            }
+        }
+    }
+}
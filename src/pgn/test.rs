@@ -0,0 +1,275 @@
+#[cfg(test)]
+mod reader_tests {
+    use std::io::Cursor;
+
+    use crate::{
+        board::{fen::FenParser, san::SanError},
+        constants::START_POS,
+        pgn::reader::{PgnErrorKind, PgnGame, PgnReader},
+    };
+
+    fn read_all(pgn: &str) -> Vec<Result<PgnGame, PgnErrorKind>> {
+        PgnReader::new(Cursor::new(pgn))
+            .map(|game| game.map_err(|error| error.kind))
+            .collect()
+    }
+
+    const OPERA_GAME: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 {This is a weak move already.} 4. dxe5 Bxf3 5. Qxf3
+dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7
+12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0
+"#;
+
+    #[test]
+    fn tags_moves_and_result() {
+        let games = read_all(OPERA_GAME);
+        assert_eq!(games.len(), 1);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tags.len(), 6);
+        assert_eq!(game.tag("White"), Some("Paul Morphy"));
+        assert_eq!(game.tag("Opening"), None);
+        assert_eq!(game.moves.len(), 33);
+        assert_eq!(game.moves[22].san, "O-O-O");
+        assert_eq!(game.moves[5].comments, vec!["This is a weak move already."]);
+        assert_eq!(game.result, "1-0");
+
+        let positions: Vec<_> = game.positions().collect();
+        assert_eq!(positions.len(), 34);
+        assert_eq!(positions[0].to_fen(), START_POS);
+        let mut last = positions[33].clone();
+        assert_eq!(
+            last.to_fen(),
+            "1n1Rkb1r/p4ppp/4q3/4p1B1/4P3/8/PPP2PPP/2K5 b k - 1 17"
+        );
+        assert!(last.outcome().is_some());
+    }
+
+    #[test]
+    fn comments_nags_and_variations() {
+        let pgn = r#"{Starts here} 1. e4 $1 e5!? (1... c5 {Sicilian} 2. Nf3 (2. c3 d5) d6) (1... e6)
+2. Nf3 ; rest of the line
+Nc6 3. a3?? *"#;
+        let games = read_all(pgn);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.comments, vec!["Starts here"]);
+        assert_eq!(game.moves.len(), 5);
+        assert_eq!(game.moves[0].nags, vec![1]);
+        assert_eq!(game.moves[1].san, "e5");
+        assert_eq!(game.moves[1].nags, vec![5]);
+        assert_eq!(game.moves[2].comments, vec!["rest of the line"]);
+        assert_eq!(game.moves[4].nags, vec![4]);
+        assert_eq!(game.result, "*");
+
+        let variations = &game.moves[1].variations;
+        assert_eq!(variations.len(), 2);
+        assert_eq!(variations[0].len(), 3);
+        assert_eq!(variations[0][0].comments, vec!["Sicilian"]);
+        assert_eq!(format!("{}", variations[0][2].m), "d7d6");
+        // Nested, replacing 2. Nf3
+        assert_eq!(variations[0][1].variations[0].len(), 2);
+        assert_eq!(format!("{}", variations[0][1].variations[0][1].m), "d7d5");
+        assert_eq!(variations[1][0].san, "e6");
+    }
+
+    #[test]
+    fn many_games() {
+        let pgn = format!(
+            "{OPERA_GAME}\n[Event \"Second\"]\n\n1.d4 d5 1/2-1/2\n\n[Event \"Third\"]\n1. c4\n[Event \"Fourth\"]\n1. Nf3 0-1\n"
+        );
+        let games = read_all(&pgn);
+        assert_eq!(games.len(), 4);
+        let second = games[1].as_ref().unwrap();
+        assert_eq!(second.tag("Event"), Some("Second"));
+        assert_eq!(second.moves.len(), 2);
+        assert_eq!(second.result, "1/2-1/2");
+        // No termination marker, the next tag starts a new game
+        let third = games[2].as_ref().unwrap();
+        assert_eq!(third.moves.len(), 1);
+        assert_eq!(third.result, "*");
+        assert_eq!(games[3].as_ref().unwrap().result, "0-1");
+    }
+
+    #[test]
+    fn fen_tag() {
+        let pgn = r#"[SetUp "1"]
+[FEN "7k/8/6K1/8/8/8/8/1Q6 w - - 0 1"]
+
+1. Qb8# 1-0"#;
+        let games = read_all(pgn);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(
+            game.start,
+            FenParser::fen_to_position("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1")
+        );
+        assert_eq!(game.moves[0].san, "Qb8#");
+    }
+
+    #[test]
+    fn malformed_games_are_skipped() {
+        let pgn = format!(
+            "[Event \"Bad move\"]\n\n1. e4 e5 2. Ke3 *\n\n\
+             [Event \"Bad tag]\n\n1. e4 *\n\n\
+             [FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n1. e4 *\n\n\
+             1. e4 (1. d4 *\n\n\
+             1. e4 e5 ) *\n\n\
+             {OPERA_GAME}\n\
+             {{ never closed 1. e4 *\n\n\
+             [Event \"After the comment\"]\n\n1. e4 *\n"
+        );
+        let games = read_all(&pgn);
+        assert_eq!(games.len(), 8);
+        assert_eq!(
+            games[0],
+            Err(PgnErrorKind::InvalidMove(
+                3,
+                SanError::IllegalMove(String::from("Ke3"))
+            ))
+        );
+        assert_eq!(
+            games[1],
+            Err(PgnErrorKind::InvalidTag(String::from("[Event \"Bad tag]")))
+        );
+        assert!(matches!(games[2], Err(PgnErrorKind::InvalidFen(_))));
+        assert_eq!(games[3], Err(PgnErrorKind::UnbalancedVariation));
+        assert_eq!(games[4], Err(PgnErrorKind::UnbalancedVariation));
+        assert!(games[5].is_ok());
+        // The comment ends with its game, the next one is still read
+        assert_eq!(games[6], Err(PgnErrorKind::UnterminatedComment));
+        assert_eq!(
+            games[7].as_ref().unwrap().tag("Event"),
+            Some("After the comment")
+        );
+
+        let games = read_all(&format!("1. e4 e5 2. Ke3 *\n\n{OPERA_GAME}"));
+        assert!(games[0].is_err());
+        assert!(games[1].is_ok());
+    }
+
+    #[test]
+    fn null_moves_are_reported() {
+        let pgn = format!("1. e4 -- 2. d4 *\n\n1. d4 d5 (1... Z0) *\n\n{OPERA_GAME}");
+        let games = read_all(&pgn);
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0], Err(PgnErrorKind::NullMove(2)));
+        assert_eq!(games[1], Err(PgnErrorKind::NullMove(1)));
+        assert!(games[2].is_ok());
+    }
+
+    #[test]
+    fn error_line_numbers() {
+        let pgn = format!("{OPERA_GAME}\n[Event \"Bad\"]\n\n1. e5 *\n");
+        let errors: Vec<usize> = PgnReader::new(Cursor::new(pgn))
+            .filter_map(|game| game.err())
+            .map(|error| error.line)
+            .collect();
+        assert_eq!(errors, vec![12]);
+    }
+}
+
+#[cfg(test)]
+mod game_tests {
+    use std::io::Cursor;
+
+    use crate::{
+        board::fen::FenParser,
+        constants::{MATE_VALUE, START_POS},
+        pgn::{game::Game, reader::PgnReader},
+    };
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for san in moves {
+            let m = game.position().parse_san(san).unwrap();
+            game.push_move(m, None, None);
+        }
+    }
+
+    fn read_back(pgn: &str) -> Game {
+        let pgn_game = PgnReader::new(Cursor::new(pgn)).next().unwrap().unwrap();
+        Game::from(&pgn_game)
+    }
+
+    // Tags get the placeholders of the seven tag roster once exported
+    fn assert_same_game(left: &Game, right: &Game) {
+        assert_eq!(left.start_fen, right.start_fen);
+        assert_eq!(left.moves, right.moves);
+        assert_eq!(left.result, right.result);
+    }
+
+    #[test]
+    fn export() {
+        let mut game = Game::new(&FenParser::fen_to_position(START_POS));
+        game.set_tag("White", "Someone");
+        game.set_tag("Annotator", "Rusty \"The\" Pigeon");
+        play(&mut game, &["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        game.result = String::from("1/2-1/2");
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"Someone\"]\n\
+             [Black \"?\"]\n\
+             [Result \"1/2-1/2\"]\n\
+             [Annotator \"Rusty \\\"The\\\" Pigeon\"]\n\
+             \n\
+             1. e4 e5 2. Nf3 Nc6 3. Bb5 1/2-1/2\n"
+        );
+        let read = read_back(&game.to_pgn());
+        assert_same_game(&read, &game);
+        assert_eq!(read.tag("Annotator"), Some("Rusty \"The\" Pigeon"));
+        assert_eq!(read.tag("Result"), None);
+    }
+
+    #[test]
+    fn custom_start_position() {
+        let fen = "7k/8/6K1/8/8/8/8/1Q6 b - - 0 1";
+        let mut game = Game::new(&FenParser::fen_to_position(fen));
+        play(&mut game, &["Kg8", "Qb8#"]);
+        game.result = String::from("1-0");
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"7k/8/6K1/8/8/8/8/1Q6 b - - 0 1\"]\n"));
+        assert!(pgn.ends_with("\n1... Kg8 2. Qb8# 1-0\n"));
+        assert_same_game(&read_back(&pgn), &game);
+    }
+
+    #[test]
+    fn comments_and_evals() {
+        let mut game = Game::new(&FenParser::fen_to_position(START_POS));
+        play(&mut game, &["e4", "e5", "Nf3"]);
+        game.moves[0].eval = Some(35);
+        game.moves[0].comment = Some(String::from("Best by test"));
+        game.moves[1].eval = Some(-120);
+        game.moves[2].eval = Some(MATE_VALUE - 3);
+        let pgn = game.to_pgn();
+        assert!(pgn.ends_with(
+            "\n1. e4 {[%eval 0.35] Best by test} 1... e5 {[%eval -1.20]} 2. Nf3 {[%eval #2]} *\n"
+        ));
+        assert_same_game(&read_back(&pgn), &game);
+    }
+
+    #[test]
+    fn long_games_are_wrapped() {
+        let pgn = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17. Rd8# 1-0
+"#;
+        let game = read_back(pgn);
+        let exported = game.to_pgn();
+        assert!(exported.lines().all(|line| line.len() <= 80));
+        assert_eq!(exported.lines().count(), 11);
+        assert_eq!(read_back(&exported), game);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    env, fs, io,
+    ops::Neg,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    board::models::{Castling, Piece, Position},
+    constants::TB_WIN_VALUE,
+    movegen::generator::{MoveGenKind, MoveGenerator, MoveInfo},
+};
+
+use super::table::{DtzLookup, Material, Table, DTZ_SUFFIX, MAX_PIECES, WDL_SUFFIX};
+
+pub const DEFAULT_PROBE_LIMIT: usize = MAX_PIECES;
+
+// Root moves are ranked like this, wins first and the quickest to zero the fifty move counter
+// among them
+const MAX_DTZ: i32 = 1 << 18;
+
+// The result of a position for the side to move. Cursed wins and blessed losses are
+// wins and losses the fifty move rule turns into draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+impl Wdl {
+    fn from_value(value: i8) -> Option<Wdl> {
+        match value {
+            -2 => Some(Wdl::Loss),
+            -1 => Some(Wdl::BlessedLoss),
+            0 => Some(Wdl::Draw),
+            1 => Some(Wdl::CursedWin),
+            2 => Some(Wdl::Win),
+            _ => None,
+        }
+    }
+
+    // The search score of the result, as seen `ply` plies away from the root. Below the
+    // mate scores, a won ending is not a mate in so many moves
+    pub fn score(self, ply: usize) -> isize {
+        match self {
+            Wdl::Win => TB_WIN_VALUE - ply as isize,
+            Wdl::Loss => -(TB_WIN_VALUE - ply as isize),
+            _ => 0,
+        }
+    }
+
+    // The plies to zero the counter for a move that zeroes it, counting that move
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1,
+            Wdl::Draw => 0,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::BlessedLoss => Wdl::CursedWin,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::CursedWin => Wdl::BlessedLoss,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+// A table file found on disk, read the first time a position needs it
+#[derive(Debug)]
+struct TableFile {
+    path: PathBuf,
+    material: Material,
+    is_dtz: bool,
+    table: OnceLock<Option<Table>>,
+}
+impl TableFile {
+    fn table(&self) -> Option<&Table> {
+        self.table
+            .get_or_init(|| Table::parse(fs::read(&self.path).ok()?, self.material, self.is_dtz))
+            .as_ref()
+    }
+}
+
+// The WDL and DTZ tables in some directories, by their material with either color ahead
+#[derive(Debug, Default)]
+pub struct Tablebases {
+    files: Vec<TableFile>,
+    wdl: FxHashMap<Material, usize>,
+    dtz: FxHashMap<Material, usize>,
+    // The most pieces of a WDL table found
+    pub max_pieces: usize,
+}
+impl Tablebases {
+    // Directories separated like in PATH. Only the names of the files are read here
+    pub fn open(paths: &str) -> io::Result<Tablebases> {
+        let mut tablebases = Tablebases::default();
+        for dir in env::split_paths(paths) {
+            for entry in fs::read_dir(&dir)? {
+                tablebases.add(&entry?.path());
+            }
+        }
+        Ok(tablebases)
+    }
+
+    fn add(&mut self, path: &Path) {
+        let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+            return;
+        };
+        let is_dtz = match extension {
+            WDL_SUFFIX => false,
+            DTZ_SUFFIX => true,
+            _ => return,
+        };
+        let Some(material) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(Material::parse)
+        else {
+            return;
+        };
+        if material.count() > MAX_PIECES {
+            return;
+        }
+
+        let index = self.files.len();
+        self.files.push(TableFile {
+            path: path.to_path_buf(),
+            material,
+            is_dtz,
+            table: OnceLock::new(),
+        });
+        let tables = if is_dtz { &mut self.dtz } else { &mut self.wdl };
+        tables.insert(material, index);
+        tables.insert(material.flipped(), index);
+        if !is_dtz {
+            self.max_pieces = self.max_pieces.max(material.count());
+        }
+    }
+
+    // How many table files there are, WDL and DTZ
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn table(&self, pos: &Position, is_dtz: bool) -> Option<&Table> {
+        let tables = if is_dtz { &self.dtz } else { &self.wdl };
+        self.files[*tables.get(&Material::of(pos))?].table()
+    }
+
+    fn probe_wdl_table(&self, pos: &Position) -> Option<Wdl> {
+        // Bare kings have no table
+        if pos.occupancy().count_ones() == 2 {
+            return Some(Wdl::Draw);
+        }
+        Wdl::from_value(self.table(pos, false)?.probe_wdl(pos)?)
+    }
+
+    // The result with best play, None without the tables for the position or any ending
+    // it can turn into with a capture. Castling rights are not in the tables
+    pub fn probe_wdl(&self, pos: &mut Position) -> Option<Wdl> {
+        self.search(pos, false).map(|(wdl, _)| wdl)
+    }
+
+    // The tables know nothing about en passant, so captures are tried first, and with
+    // `check_zeroing` pawn moves too. Also says if the best move is one of those
+    fn search(&self, pos: &mut Position, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = MoveGenerator::get_legal_moves(pos, &MoveGenKind::All);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for m in &moves {
+            if m.captured_piece.is_none() && (!check_zeroing || m.piece != Piece::PAWN) {
+                continue;
+            }
+            searched += 1;
+            pos.apply_move(m);
+            let wdl = self.search(pos, false).map(|(wdl, _)| -wdl);
+            pos.undo_move(m);
+            let wdl = wdl?;
+            if wdl > best {
+                best = wdl;
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // With every move searched, the table could only be wrong
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let wdl = if no_more_moves {
+            best
+        } else {
+            self.probe_wdl_table(pos)?
+        };
+        if best >= wdl {
+            // The table may not have a real value here
+            return Some((best, best > Wdl::Draw || no_more_moves));
+        }
+        Some((wdl, false))
+    }
+
+    // Plies to the next capture or pawn move with best play, positive if the side to move
+    // wins, negative if it loses, 0 for draws. Cursed wins and blessed losses are 100 more
+    pub fn probe_dtz(&self, pos: &mut Position) -> Option<i32> {
+        let (wdl, zeroing_is_best) = self.search(pos, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        // A zeroing move does best, the table may not even have this position right
+        if zeroing_is_best {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        let sign = (wdl as i32).signum();
+        if let DtzLookup::Plies(dtz) = self.table(pos, true)?.probe_dtz(pos, wdl as i8)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * sign);
+        }
+
+        // The table only has the other side to move, look one ply ahead for the move
+        // with the best distance
+        let mut best = None;
+        for m in &MoveGenerator::get_legal_moves(pos, &MoveGenKind::All) {
+            let zeroing = m.captured_piece.is_some() || m.piece == Piece::PAWN;
+            pos.apply_move(m);
+            let dtz = if zeroing {
+                self.probe_wdl(pos).map(|wdl| -wdl.dtz_before_zeroing())
+            } else {
+                self.probe_dtz(pos).map(|dtz| -dtz)
+            };
+            let is_mate = dtz == Some(1) && pos.checkers() != 0 && Self::has_no_moves(pos);
+            pos.undo_move(m);
+
+            let mut dtz = dtz?;
+            if is_mate {
+                best = Some(1);
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz.signum() == sign && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        // No moves, so it's mate
+        Some(best.unwrap_or(-1))
+    }
+
+    fn has_no_moves(pos: &mut Position) -> bool {
+        MoveGenerator::get_legal_moves(pos, &MoveGenKind::All).is_empty()
+    }
+
+    // The move that keeps a won or lost ending on track under the fifty move rule, None in
+    // drawn ones or without the tables, those are left to the search
+    pub fn root_move(&self, pos: &Position) -> Option<(MoveInfo, Wdl)> {
+        let mut pos = pos.clone();
+        let halfmove_clock = pos.state.halfmove_clock as i32;
+        let mut ranked = vec![];
+        for m in MoveGenerator::get_legal_moves(&mut pos, &MoveGenKind::All) {
+            pos.apply_move(&m);
+            let dtz = if pos.state.halfmove_clock == 0 {
+                self.probe_wdl(&mut pos).map(|wdl| (-wdl).dtz_before_zeroing())
+            } else if pos.is_repetition(2) || pos.state.halfmove_clock >= 100 {
+                Some(0)
+            } else {
+                self.probe_dtz(&mut pos).map(|dtz| -dtz - dtz.signum())
+            };
+            let is_mate = pos.checkers() != 0 && Self::has_no_moves(&mut pos);
+            pos.undo_move(&m);
+
+            let dtz = match dtz? {
+                2 if is_mate => 1,
+                dtz => dtz,
+            };
+            // Quicker wins and slower losses rank higher, as long as the fifty move rule
+            // doesn't get in the way
+            let rank = match dtz {
+                dtz if dtz > 0 && dtz + halfmove_clock <= 99 => MAX_DTZ - dtz,
+                dtz if dtz > 0 => MAX_DTZ / 2 - (dtz + halfmove_clock),
+                dtz if dtz < 0 && -dtz * 2 + halfmove_clock < 100 => -MAX_DTZ - dtz,
+                dtz if dtz < 0 => -MAX_DTZ / 2 + (-dtz + halfmove_clock),
+                _ => 0,
+            };
+            ranked.push((m, rank));
+        }
+
+        let (m, rank) = ranked.into_iter().max_by_key(|(_, rank)| *rank)?;
+        let bound = MAX_DTZ / 2 - 100;
+        if rank >= bound {
+            Some((m, Wdl::Win))
+        } else if rank <= -bound {
+            Some((m, Wdl::Loss))
+        } else {
+            None
+        }
+    }
+}
+
+// The Syzygy UCI options, with the tables found in SyzygyPath
+#[derive(Debug, Clone)]
+pub struct SyzygySettings {
+    pub path: String,
+    // No tables are probed in positions with more pieces than this
+    pub probe_limit: usize,
+    pub tables: Option<Arc<Tablebases>>,
+}
+impl SyzygySettings {
+    pub fn new() -> SyzygySettings {
+        SyzygySettings {
+            path: String::new(),
+            probe_limit: DEFAULT_PROBE_LIMIT,
+            tables: None,
+        }
+    }
+
+    // Looks for the tables again after SyzygyPath changes
+    pub fn load(&mut self) -> io::Result<()> {
+        self.tables = None;
+        if !self.path.is_empty() {
+            self.tables = Some(Arc::new(Tablebases::open(&self.path)?));
+        }
+        Ok(())
+    }
+
+    fn usable_tables(&self, pos: &Position) -> Option<&Tablebases> {
+        let tables = self.tables.as_ref()?;
+        let pieces = pos.occupancy().count_ones() as usize;
+        if pieces > self.probe_limit.min(tables.max_pieces)
+            || pos.state.castling.0 != Castling::NO_CASTLING
+        {
+            return None;
+        }
+        Some(tables)
+    }
+
+    // The result of an ending right after a capture or a pawn move, when the tables are
+    // there. Later on the fifty move counter is running and the result may not hold
+    pub fn probe_in_search(&self, pos: &mut Position) -> Option<Wdl> {
+        if pos.state.halfmove_clock != 0 {
+            return None;
+        }
+        self.usable_tables(pos)?.probe_wdl(pos)
+    }
+
+    pub fn root_move(&self, pos: &Position) -> Option<(MoveInfo, Wdl)> {
+        self.usable_tables(pos)?.root_move(pos)
+    }
+}
+
+impl Default for SyzygySettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
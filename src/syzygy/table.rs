@@ -0,0 +1,872 @@
+use crate::board::models::{Piece, Position, Side};
+
+// Layout of the .rtbw and .rtbz files as written by the Syzygy generator, the decoding
+// follows the reference probing code
+pub const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+pub const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+pub const WDL_SUFFIX: &str = "rtbw";
+pub const DTZ_SUFFIX: &str = "rtbz";
+
+// The biggest tables there are
+pub const MAX_PIECES: usize = 7;
+
+// Flags of the file header
+const HEADER_SPLIT: u8 = 1;
+const HEADER_HAS_PAWNS: u8 = 2;
+
+// Flags of every subtable
+pub const FLAG_STM: u8 = 1;
+pub const FLAG_MAPPED: u8 = 2;
+pub const FLAG_WIN_PLIES: u8 = 4;
+pub const FLAG_LOSS_PLIES: u8 = 8;
+pub const FLAG_WIDE: u8 = 16;
+pub const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Piece codes in the files: pawn, knight, bishop, rook, queen and king are 1 to 6,
+// black pieces have the 8 bit set. Indexed by Piece
+const PIECE_CODE: [u8; 6] = [1, 3, 2, 4, 5, 6];
+const BLACK_CODE: u8 = 8;
+
+// The pieces of both sides, counted by Piece
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Material {
+    pub counts: [[u8; 6]; 2],
+}
+impl Material {
+    // Pieces in the order they are written in the file names
+    const NAME_ORDER: [(usize, char); 6] = [
+        (Piece::KING, 'K'),
+        (Piece::QUEEN, 'Q'),
+        (Piece::ROOK, 'R'),
+        (Piece::BISHOP, 'B'),
+        (Piece::KNIGHT, 'N'),
+        (Piece::PAWN, 'P'),
+    ];
+
+    pub fn of(pos: &Position) -> Material {
+        let mut counts = [[0; 6]; 2];
+        for (side, pieces) in pos.board.pieces.iter().enumerate() {
+            for (piece, bitboard) in pieces.iter().enumerate() {
+                counts[side][piece] = bitboard.0.count_ones() as u8;
+            }
+        }
+        Material { counts }
+    }
+
+    // From a table name like KRPvKR, white's pieces first
+    pub fn parse(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+        for (side, pieces) in [white, black].into_iter().enumerate() {
+            for c in pieces.chars() {
+                let (piece, _) = Self::NAME_ORDER.iter().find(|(_, letter)| *letter == c)?;
+                counts[side][*piece] += 1;
+            }
+            if counts[side][Piece::KING] != 1 {
+                return None;
+            }
+        }
+        Some(Material { counts })
+    }
+
+    pub fn name(&self) -> String {
+        let side_name = |side: usize| -> String {
+            Self::NAME_ORDER
+                .iter()
+                .flat_map(|(piece, letter)| (0..self.counts[side][*piece]).map(move |_| *letter))
+                .collect()
+        };
+        format!("{}v{}", side_name(Side::WHITE), side_name(Side::BLACK))
+    }
+
+    // The same pieces with the colors swapped
+    pub fn flipped(&self) -> Material {
+        Material {
+            counts: [self.counts[Side::BLACK], self.counts[Side::WHITE]],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.counts.iter().flatten().map(|count| *count as usize).sum()
+    }
+
+    fn pawns(&self, side: usize) -> usize {
+        self.counts[side][Piece::PAWN] as usize
+    }
+}
+
+// Tables to map the pieces to an index, built once
+struct Encoding {
+    // Squares below the a1-h8 diagonal to 0..28
+    b1h1h7: [usize; 64],
+    // Squares of the a1-d1-d4 triangle to 0..10, the diagonal last
+    a1d1d4: [usize; 64],
+    // The 462 placements of two kings with the first in the triangle
+    kk: [[usize; 64]; 10],
+    binomial: [[u64; 64]; 7],
+    // Pawn squares to 0..48, higher toward the edge and the second rank
+    pawns: [usize; 64],
+    lead_pawn_index: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+lazy_static::lazy_static! {
+    static ref ENCODING: Encoding = Encoding::new();
+}
+
+fn rank_of(square: usize) -> usize {
+    square >> 3
+}
+
+fn file_of(square: usize) -> usize {
+    square & 7
+}
+
+// Positive above the a1-h8 diagonal, negative below
+fn off_diagonal(square: usize) -> isize {
+    rank_of(square) as isize - file_of(square) as isize
+}
+
+fn flip_diagonal(square: usize) -> usize {
+    ((square >> 3) | (square << 3)) & 63
+}
+
+fn kings_touch(a: usize, b: usize) -> bool {
+    rank_of(a).abs_diff(rank_of(b)) <= 1 && file_of(a).abs_diff(file_of(b)) <= 1
+}
+
+impl Encoding {
+    fn new() -> Encoding {
+        let mut encoding = Encoding {
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 7],
+            pawns: [0; 64],
+            lead_pawn_index: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                encoding.b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = vec![];
+        code = 0;
+        for square in 0..=27 {
+            if off_diagonal(square) < 0 && file_of(square) <= 3 {
+                encoding.a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && file_of(square) <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            encoding.a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // With the first king on the diagonal the second one is not above it,
+        // both on the diagonal come last
+        let mut both_on_diagonal = vec![];
+        code = 0;
+        for index in 0..10 {
+            for first in 0..=27 {
+                if encoding.a1d1d4[first] != index || (index == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    if kings_touch(first, second)
+                        || (off_diagonal(first) == 0 && off_diagonal(second) > 0)
+                    {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index, second));
+                    } else {
+                        encoding.kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            encoding.kk[index][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..7.min(n + 1) {
+                encoding.binomial[k][n] = if k > 0 { encoding.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { encoding.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        let mut available = 48;
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = 8 * rank + file;
+                    if lead_pawns == 1 {
+                        encoding.pawns[square] = available - 1;
+                        encoding.pawns[square ^ 7] = available - 2;
+                        available -= 2;
+                    }
+                    encoding.lead_pawn_index[lead_pawns][square] = index;
+                    index += encoding.binomial[lead_pawns - 1][encoding.pawns[square]];
+                }
+                encoding.lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+        encoding
+    }
+}
+
+// One table of values: for a side to move and, with pawns, a file of the leading pawn
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    pieces: [u8; MAX_PIECES],
+    // Pieces encoded together, the first group is the leading one
+    group_len: [usize; MAX_PIECES + 1],
+    group_index: [u64; MAX_PIECES + 1],
+    flags: u8,
+    // The value itself for single value tables
+    min_sym_len: u8,
+    block_size: usize,
+    span: u64,
+    sparse_index_size: usize,
+    blocks: usize,
+    block_length_size: usize,
+    // Canonical Huffman codes of every length, left aligned
+    base64: Vec<u64>,
+    // How many values every symbol expands to, minus one
+    symlen: Vec<u8>,
+    // Offsets in the file
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    // Where the four DTZ maps of a mapped table start, as offsets from the map
+    map_index: [usize; 4],
+}
+
+// What a DTZ table says about a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DtzLookup {
+    // Plies to the next capture, pawn move or mate
+    Plies(i32),
+    // The table only has the other side to move
+    OtherSide,
+}
+
+// A WDL or DTZ table read into memory
+#[derive(Debug, Clone)]
+pub struct Table {
+    bytes: Vec<u8>,
+    // As in the file name, white is the side listed first
+    material: Material,
+    is_symmetric: bool,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    piece_count: usize,
+    // Pawns of the leading color and of the other one
+    pawn_count: [usize; 2],
+    is_dtz: bool,
+    // By side to move, then by file of the leading pawn
+    pairs: Vec<Vec<PairsData>>,
+    map: usize,
+}
+
+fn byte(bytes: &[u8], offset: usize) -> Option<usize> {
+    bytes.get(offset).map(|byte| *byte as usize)
+}
+
+fn u16_le(bytes: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_le(bytes: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+fn u32_be(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u32::from_be_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?) as u64)
+}
+
+fn u64_be(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+impl Table {
+    // None if the bytes are not a table for this material
+    pub fn parse(bytes: Vec<u8>, material: Material, is_dtz: bool) -> Option<Table> {
+        let magic = if is_dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.get(0..4)? != magic || material.count() > MAX_PIECES {
+            return None;
+        }
+
+        let has_pawns = material.pawns(Side::WHITE) + material.pawns(Side::BLACK) > 0;
+        let has_unique_pieces = material
+            .counts
+            .iter()
+            .any(|counts| (0..6).any(|piece| piece != Piece::KING && counts[piece] == 1));
+        // The leading color is the one with fewer pawns, white if it's the same
+        let white_leads = material.pawns(Side::BLACK) == 0
+            || (material.pawns(Side::WHITE) > 0
+                && material.pawns(Side::BLACK) >= material.pawns(Side::WHITE));
+        let lead = if white_leads { Side::WHITE } else { Side::BLACK };
+        let mut table = Table {
+            bytes: vec![],
+            material,
+            is_symmetric: material == material.flipped(),
+            has_pawns,
+            has_unique_pieces,
+            piece_count: material.count(),
+            pawn_count: [material.pawns(lead), material.pawns(1 - lead)],
+            is_dtz,
+            pairs: vec![],
+            map: 0,
+        };
+
+        let header = byte(&bytes, 4)? as u8;
+        if (header & HEADER_HAS_PAWNS != 0) != has_pawns
+            || (header & HEADER_SPLIT != 0) == table.is_symmetric
+        {
+            return None;
+        }
+        table.read_layout(&bytes, 5)?;
+        table.bytes = bytes;
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        if !self.is_dtz && !self.is_symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn files(&self) -> usize {
+        if self.has_pawns {
+            4
+        } else {
+            1
+        }
+    }
+
+    fn read_layout(&mut self, bytes: &[u8], mut offset: usize) -> Option<()> {
+        let (sides, files) = self.subtables();
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        self.pairs = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let first = byte(bytes, offset)?;
+            let second = if both_pawns { byte(bytes, offset + 1)? } else { 0xFF };
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            offset += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                let pieces = byte(bytes, offset)? as u8;
+                for side in 0..sides {
+                    self.pairs[side][file].pieces[k] =
+                        if side == 0 { pieces & 0xF } else { pieces >> 4 };
+                }
+                offset += 1;
+            }
+            for (side, order) in order.iter().enumerate().take(sides) {
+                self.set_groups(side, file, *order);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                offset = self.set_sizes(bytes, side, file, offset)?;
+            }
+        }
+        if self.is_dtz {
+            offset = self.set_dtz_map(bytes, offset)?;
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let pairs = &mut self.pairs[side][file];
+                pairs.sparse_index = offset;
+                offset += pairs.sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let pairs = &mut self.pairs[side][file];
+                pairs.block_length = offset;
+                offset += pairs.block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                offset = (offset + 0x3F) & !0x3F;
+                let pairs = &mut self.pairs[side][file];
+                pairs.data = offset;
+                offset += pairs.blocks * pairs.block_size;
+            }
+        }
+        (offset <= bytes.len()).then_some(())
+    }
+
+    // Splits the pieces in groups and works out what every group is worth in the index.
+    // order[0] is where the leading group goes among the others, order[1] the other pawns
+    fn set_groups(&mut self, side: usize, file: usize, order: [usize; 2]) {
+        let encoding = &*ENCODING;
+        let has_pawns = self.has_pawns;
+        let both_pawns = has_pawns && self.pawn_count[1] > 0;
+        let unique = self.has_unique_pieces;
+        let piece_count = self.piece_count;
+        let pairs = &mut self.pairs[side][file];
+
+        let mut n = 0;
+        let mut first_len: isize = if has_pawns { 0 } else if unique { 3 } else { 2 };
+        pairs.group_len[0] = 1;
+        for i in 1..piece_count {
+            first_len -= 1;
+            if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+                pairs.group_len[n] += 1;
+            } else {
+                n += 1;
+                pairs.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        pairs.group_len[n] = 0;
+
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+        let mut index: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                pairs.group_index[0] = index;
+                index *= if has_pawns {
+                    encoding.lead_pawns_size[pairs.group_len[0]][file]
+                } else if unique {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                pairs.group_index[1] = index;
+                index *= encoding.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+            } else {
+                pairs.group_index[next] = index;
+                index *= encoding.binomial[pairs.group_len[next]][free_squares];
+                free_squares -= pairs.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs.group_index[n] = index;
+    }
+
+    fn set_sizes(
+        &mut self,
+        bytes: &[u8],
+        side: usize,
+        file: usize,
+        mut offset: usize,
+    ) -> Option<usize> {
+        let size = self.size(side, file);
+        let pairs = &mut self.pairs[side][file];
+        pairs.flags = byte(bytes, offset)? as u8;
+        offset += 1;
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            pairs.min_sym_len = byte(bytes, offset)? as u8;
+            return Some(offset + 1);
+        }
+
+        pairs.block_size = 1 << byte(bytes, offset)?;
+        pairs.span = 1 << byte(bytes, offset + 1)?;
+        pairs.sparse_index_size = size.div_ceil(pairs.span) as usize;
+        let padding = byte(bytes, offset + 2)?;
+        pairs.blocks = u32_le(bytes, offset + 3)?;
+        pairs.block_length_size = pairs.blocks + padding;
+        let max_sym_len = byte(bytes, offset + 7)?;
+        let min_sym_len = byte(bytes, offset + 8)?;
+        if min_sym_len == 0 || max_sym_len < min_sym_len || max_sym_len > 32 {
+            return None;
+        }
+        pairs.min_sym_len = min_sym_len as u8;
+        offset += 9;
+        pairs.lowest_sym = offset;
+
+        // Longer codes have lower values, base64[i] is the lowest code of length
+        // min_sym_len + i, left aligned to 64 bits
+        let lengths = max_sym_len - min_sym_len + 1;
+        pairs.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = u16_le(bytes, offset + 2 * i)? as u64;
+            let next_lowest = u16_le(bytes, offset + 2 * (i + 1))? as u64;
+            pairs.base64[i] =
+                pairs.base64[i + 1].wrapping_add(lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in pairs.base64.iter_mut().enumerate() {
+            *base <<= 64 - i - min_sym_len;
+        }
+        offset += lengths * 2;
+
+        let symbols = u16_le(bytes, offset)?;
+        offset += 2;
+        pairs.btree = offset;
+        bytes.get(offset..offset + symbols * 3)?;
+        pairs.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                let len = set_symlen(bytes, pairs, symbol, &mut visited)?;
+                pairs.symlen[symbol] = len;
+            }
+        }
+        Some(offset + symbols * 3 + (symbols & 1))
+    }
+
+    // The maps from stored DTZ values to real ones, one per WDL result
+    fn set_dtz_map(&mut self, bytes: &[u8], mut offset: usize) -> Option<usize> {
+        self.map = offset;
+        for file in 0..self.files() {
+            let pairs = &mut self.pairs[0][file];
+            if pairs.flags & FLAG_MAPPED == 0 {
+                continue;
+            }
+            if pairs.flags & FLAG_WIDE != 0 {
+                offset += offset & 1;
+                for i in 0..4 {
+                    pairs.map_index[i] = (offset - self.map) / 2 + 1;
+                    offset += 2 * u16_le(bytes, offset)? + 2;
+                }
+            } else {
+                for i in 0..4 {
+                    pairs.map_index[i] = offset - self.map + 1;
+                    offset += byte(bytes, offset)? + 1;
+                }
+            }
+        }
+        Some(offset + (offset & 1))
+    }
+
+    // -2 to 2, from a loss to a win for the side to move
+    pub fn probe_wdl(&self, pos: &Position) -> Option<i8> {
+        let (stm, file, index) = self.index(pos)?;
+        let value = self.decompress(&self.pairs[stm % self.sides()][file], index)?;
+        Some(value as i8 - 2)
+    }
+
+    // The position must have the result `wdl` according to the WDL table
+    pub fn probe_dtz(&self, pos: &Position, wdl: i8) -> Option<DtzLookup> {
+        let (stm, file, index) = self.index(pos)?;
+        let pairs = &self.pairs[0][file];
+        // Symmetric tables without pawns look the same from both sides
+        let has_side =
+            pairs.flags & FLAG_STM == stm as u8 || (self.is_symmetric && !self.has_pawns);
+        if !has_side {
+            return Some(DtzLookup::OtherSide);
+        }
+        let mut value = self.decompress(pairs, index)? as usize;
+
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+        if pairs.flags & FLAG_MAPPED != 0 {
+            let map_index = pairs.map_index[WDL_MAP[(wdl + 2) as usize]] + value;
+            value = if pairs.flags & FLAG_WIDE != 0 {
+                u16_le(&self.bytes, self.map + 2 * map_index)?
+            } else {
+                byte(&self.bytes, self.map + map_index)?
+            };
+        }
+
+        // Stored in moves unless the flags say plies
+        let in_moves = match wdl {
+            2 => pairs.flags & FLAG_WIN_PLIES == 0,
+            -2 => pairs.flags & FLAG_LOSS_PLIES == 0,
+            _ => wdl != 0,
+        };
+        let plies = if in_moves { 2 * value } else { value };
+        Some(DtzLookup::Plies(plies as i32 + 1))
+    }
+
+    // The side to move and the file of the leading pawn as the table sees them, along with
+    // the index of the position in that subtable
+    pub(crate) fn index(&self, pos: &Position) -> Option<(usize, usize, u64)> {
+        let encoding = &*ENCODING;
+        // Tables have the stronger side as white. If black has those pieces, or the table
+        // only has white to move, look the position up with the colors swapped
+        let swap = Material::of(pos) != self.material
+            || (self.is_symmetric && pos.side_to_move.0 == Side::BLACK);
+        let flip_color = if swap { BLACK_CODE } else { 0 };
+        let flip_squares = if swap { 56 } else { 0 };
+        let stm = swap as usize ^ pos.side_to_move.0;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns_count = 0;
+        let mut lead_pawns = 0;
+        let mut file = 0;
+
+        if self.has_pawns {
+            // The leading pawns are of the color of the first piece in the table
+            let code = self.pairs[0][0].pieces[0] ^ flip_color;
+            let side = if code & BLACK_CODE != 0 { Side::BLACK } else { Side::WHITE };
+            lead_pawns = pos.board.pieces[side][Piece::PAWN].0;
+            let mut pawns = lead_pawns;
+            while pawns != 0 {
+                squares[size] = pawns.trailing_zeros() as usize ^ flip_squares;
+                size += 1;
+                pawns &= pawns - 1;
+            }
+            lead_pawns_count = size;
+            // The leading pawn is the one nearest to the edge, then to the second rank
+            let lead = (0..size).max_by_key(|i| encoding.pawns[squares[*i]])?;
+            squares.swap(0, lead);
+            file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+
+        let mut others = pos.occupancy() ^ lead_pawns;
+        while others != 0 {
+            let square = others.trailing_zeros() as u64;
+            let bitboard = 1u64 << square;
+            let side = if pos.board.side_pieces[Side::WHITE].0 & bitboard != 0 {
+                Side::WHITE
+            } else {
+                Side::BLACK
+            };
+            let piece = (0..6).find(|piece| pos.board.pieces[side][*piece].0 & bitboard != 0)?;
+            if size == MAX_PIECES {
+                return None;
+            }
+            squares[size] = square as usize ^ flip_squares;
+            pieces[size] = (PIECE_CODE[piece] | if side == Side::BLACK { BLACK_CODE } else { 0 })
+                ^ flip_color;
+            size += 1;
+            others &= others - 1;
+        }
+        if size != self.piece_count {
+            return None;
+        }
+
+        let pairs = &self.pairs[stm % self.sides()][file];
+
+        // Same order as the pieces of the table
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|j| pairs.pieces[i] == pieces[*j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // The leading piece goes in the a1-d1-d4 triangle
+        if file_of(squares[0]) > 3 {
+            for square in squares.iter_mut().take(size) {
+                *square ^= 7;
+            }
+        }
+
+        let mut index: u64;
+        if self.has_pawns {
+            index = encoding.lead_pawn_index[lead_pawns_count][squares[0]];
+            squares[1..lead_pawns_count].sort_by_key(|square| encoding.pawns[*square]);
+            for (i, square) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                index += encoding.binomial[i][encoding.pawns[*square]];
+            }
+        } else {
+            if rank_of(squares[0]) > 3 {
+                for square in squares.iter_mut().take(size) {
+                    *square ^= 56;
+                }
+            }
+            // The first piece of the leading group off the diagonal goes below it
+            for i in 0..pairs.group_len[0] {
+                if off_diagonal(squares[i]) == 0 {
+                    continue;
+                }
+                if off_diagonal(squares[i]) > 0 {
+                    for square in squares.iter_mut().take(size).skip(i) {
+                        *square = flip_diagonal(*square);
+                    }
+                }
+                break;
+            }
+
+            index = if self.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as usize;
+                let adjust2 =
+                    (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+                if off_diagonal(squares[0]) != 0 {
+                    (encoding.a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2]
+                        - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank_of(squares[0]) * 28 + encoding.b1h1h7[squares[1]]) * 62
+                        + squares[2]
+                        - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank_of(squares[0]) * 7 * 28
+                        + (rank_of(squares[1]) - adjust1) * 28
+                        + encoding.b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank_of(squares[0]) * 7 * 6
+                        + (rank_of(squares[1]) - adjust1) * 6
+                        + (rank_of(squares[2]) - adjust2)
+                }
+            } else {
+                encoding.kk[encoding.a1d1d4[squares[0]]][squares[1]]
+            } as u64;
+        }
+
+        // The rest of the groups, each one as a combination of the squares still free
+        index *= pairs.group_index[0];
+        let mut group_start = pairs.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while pairs.group_len[next] != 0 {
+            let group_end = group_start + pairs.group_len[next];
+            squares[group_start..group_end].sort_unstable();
+            let mut n = 0;
+            for i in 0..pairs.group_len[next] {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|s| square > **s).count();
+                let free = square - adjust - if remaining_pawns { 8 } else { 0 };
+                n += encoding.binomial[i + 1][free];
+            }
+            remaining_pawns = false;
+            index += n * pairs.group_index[next];
+            group_start = group_end;
+            next += 1;
+        }
+        Some((stm, file, index))
+    }
+
+    // How many positions a subtable has room for, the index past the last group
+    pub(crate) fn size(&self, side: usize, file: usize) -> u64 {
+        let pairs = &self.pairs[side % self.sides()][file];
+        let groups = pairs.group_len.iter().position(|len| *len == 0).unwrap_or(0);
+        pairs.group_index[groups]
+    }
+
+    pub(crate) fn subtables(&self) -> (usize, usize) {
+        (self.sides(), self.files())
+    }
+
+    // The value at the index, the blocks of symbols are found through the sparse index
+    fn decompress(&self, pairs: &PairsData, index: u64) -> Option<u16> {
+        let bytes = &self.bytes;
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(pairs.min_sym_len as u16);
+        }
+
+        // The sparse index knows where the values at k * span + span / 2 are
+        let k = (index / pairs.span) as usize;
+        if k >= pairs.sparse_index_size {
+            return None;
+        }
+        let mut block = u32_le(bytes, pairs.sparse_index + 6 * k)?;
+        let mut offset = u16_le(bytes, pairs.sparse_index + 6 * k + 4)? as i64;
+        offset += (index % pairs.span) as i64 - (pairs.span / 2) as i64;
+
+        let block_length = |block: usize| -> Option<i64> {
+            if block >= pairs.block_length_size {
+                return None;
+            }
+            Some(u16_le(bytes, pairs.block_length + 2 * block)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        let min_sym_len = pairs.min_sym_len as usize;
+        let mut pointer = pairs.data + block * pairs.block_size;
+        let mut buffer = u64_be(bytes, pointer)?;
+        pointer += 8;
+        let mut buffer_size = 64;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while buffer < pairs.base64[len] {
+                len += 1;
+                if len == pairs.base64.len() {
+                    return None;
+                }
+            }
+            symbol = ((buffer - pairs.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            symbol += u16_le(bytes, pairs.lowest_sym + 2 * len)?;
+            let symlen = *pairs.symlen.get(symbol)? as i64;
+            if offset < symlen + 1 {
+                break;
+            }
+            offset -= symlen + 1;
+            len += min_sym_len;
+            buffer <<= len;
+            buffer_size -= len;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                // Past the last block there may be nothing left to read
+                buffer |= u32_be(bytes, pointer).unwrap_or(0) << (64 - buffer_size);
+                pointer += 4;
+            }
+        }
+
+        // Symbols stand for pairs of symbols, down to single values
+        while pairs.symlen[symbol] != 0 {
+            let (left, right) = btree(bytes, pairs, symbol)?;
+            let left_len = pairs.symlen[left] as i64;
+            if offset < left_len + 1 {
+                symbol = left;
+            } else {
+                offset -= left_len + 1;
+                symbol = right;
+            }
+        }
+        Some(btree(bytes, pairs, symbol)?.0 as u16)
+    }
+}
+
+// The two symbols a symbol stands for, 12 bits each. A single value is on the left
+fn btree(bytes: &[u8], pairs: &PairsData, symbol: usize) -> Option<(usize, usize)> {
+    let offset = pairs.btree + 3 * symbol;
+    let entry = bytes.get(offset..offset + 3)?;
+    let left = ((entry[1] as usize & 0xF) << 8) | entry[0] as usize;
+    let right = ((entry[2] as usize) << 4) | (entry[1] as usize >> 4);
+    Some((left, right))
+}
+
+fn set_symlen(
+    bytes: &[u8],
+    pairs: &mut PairsData,
+    symbol: usize,
+    visited: &mut [bool],
+) -> Option<u8> {
+    visited[symbol] = true;
+    let (left, right) = btree(bytes, pairs, symbol)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            let len = set_symlen(bytes, pairs, child, visited)?;
+            pairs.symlen[child] = len;
+        }
+    }
+    Some(pairs.symlen[left].wrapping_add(pairs.symlen[right]).wrapping_add(1))
+}
@@ -0,0 +1,460 @@
+#[cfg(test)]
+mod syzygy_tests {
+    use std::{
+        env, fs,
+        iter::repeat_n,
+        path::{Path, PathBuf},
+        process,
+        sync::OnceLock,
+    };
+
+    use crate::{
+        board::{
+            fen::FenParser,
+            models::{Engine, Piece, Position, Side},
+        },
+        constants::{MATE_VALUE, TB_WIN_VALUE},
+        endgame::table::{Dtm, EndgameTable, Material as EndgameMaterial},
+        evaluate::evaluator::Evaluate,
+        syzygy::{
+            probe::{SyzygySettings, Tablebases, Wdl},
+            table::{
+                Material, Table, DTZ_MAGIC, DTZ_SUFFIX, FLAG_SINGLE_VALUE, FLAG_STM, WDL_MAGIC,
+                WDL_SUFFIX,
+            },
+        },
+        uci::options::GoOptions,
+    };
+
+    // Piece codes of the files
+    const CODES: [u8; 6] = [1, 3, 2, 4, 5, 6];
+    // Bits per value and bytes per block of the tables written here
+    const SYMBOL_BITS: usize = 6;
+    const BLOCK_SIZE_LOG: u8 = 6;
+    const SPAN_LOG: u8 = 10;
+
+    // Writes tables in the Syzygy format, every value with a code of the same length and
+    // no pairs of symbols, which the probing code reads like any other table
+    struct TableWriter {
+        material: Material,
+        is_dtz: bool,
+        // Pieces in the order they are encoded, as (side, piece)
+        pieces: Vec<(usize, usize)>,
+    }
+    impl TableWriter {
+        fn has_pawns(&self) -> bool {
+            self.pieces.iter().any(|(_, piece)| *piece == Piece::PAWN)
+        }
+
+        // With no values, every subtable holds a single 0
+        fn bytes(&self, layout: Option<&Table>, values: &[Vec<Vec<u8>>]) -> Vec<u8> {
+            let is_symmetric = self.material == self.material.flipped();
+            let (sides, files) = match layout {
+                Some(table) => table.subtables(),
+                None => (
+                    if self.is_dtz || is_symmetric { 1 } else { 2 },
+                    if self.has_pawns() { 4 } else { 1 },
+                ),
+            };
+            let mut bytes = if self.is_dtz { DTZ_MAGIC } else { WDL_MAGIC }.to_vec();
+            bytes.push(!is_symmetric as u8 | (self.has_pawns() as u8) << 1);
+            // The same pieces for both sides to move
+            let pieces: Vec<u8> = self
+                .pieces
+                .iter()
+                .map(|(side, piece)| CODES[*piece] | if *side == Side::BLACK { 8 } else { 0 })
+                .map(|code| code | code << 4)
+                .collect();
+            for _ in 0..files {
+                // The leading group comes first
+                bytes.push(0);
+                bytes.extend(&pieces);
+            }
+            pad_to(&mut bytes, 2);
+
+            let per_block = (8 << BLOCK_SIZE_LOG) / SYMBOL_BITS;
+            let span = 1 << SPAN_LOG;
+            let padding = span / per_block + 1;
+            let mut sections = vec![];
+            for file in 0..files {
+                for side in 0..sides {
+                    let values = values.get(side).map(|values| &values[file]);
+                    let stm = if self.is_dtz { FLAG_STM & side as u8 } else { 0 };
+                    match values {
+                        Some(values) if values.iter().any(|value| *value != values[0]) => {
+                            let blocks = values.len().div_ceil(per_block);
+                            bytes.push(stm);
+                            bytes.extend([BLOCK_SIZE_LOG, SPAN_LOG, padding as u8]);
+                            bytes.extend((blocks as u32).to_le_bytes());
+                            bytes.extend([SYMBOL_BITS as u8, SYMBOL_BITS as u8]);
+                            bytes.extend(0u16.to_le_bytes());
+                            // Every symbol is a value of its own
+                            let symbols = 1 << SYMBOL_BITS;
+                            bytes.extend((symbols as u16).to_le_bytes());
+                            for symbol in 0..symbols {
+                                bytes.extend([symbol as u8, (symbol >> 8) as u8 | 0xF0, 0xFF]);
+                            }
+                            if symbols % 2 == 1 {
+                                bytes.push(0);
+                            }
+                            sections.push(Some((values, blocks)));
+                        }
+                        _ => {
+                            let value = values.map_or(0, |values| values[0]);
+                            bytes.extend([FLAG_SINGLE_VALUE | stm, value]);
+                            sections.push(None);
+                        }
+                    }
+                }
+            }
+            if self.is_dtz {
+                pad_to(&mut bytes, 2);
+            }
+
+            // The value in the middle of every span, the blocks are all full
+            for (values, _) in sections.iter().flatten() {
+                for k in 0..values.len().div_ceil(span) {
+                    let middle = k * span + span / 2;
+                    bytes.extend(((middle / per_block) as u32).to_le_bytes());
+                    bytes.extend(((middle % per_block) as u16).to_le_bytes());
+                }
+            }
+            for (_, blocks) in sections.iter().flatten() {
+                for _ in 0..blocks + padding {
+                    bytes.extend((per_block as u16 - 1).to_le_bytes());
+                }
+            }
+            for section in &sections {
+                pad_to(&mut bytes, 64);
+                if let Some((values, blocks)) = section {
+                    for block in 0..*blocks {
+                        let mut data = vec![0u8; 1 << BLOCK_SIZE_LOG];
+                        let start = block * per_block;
+                        let end = values.len().min(start + per_block);
+                        for (i, value) in values[start..end].iter().enumerate() {
+                            for bit in 0..SYMBOL_BITS {
+                                if value >> (SYMBOL_BITS - 1 - bit) & 1 != 0 {
+                                    let at = i * SYMBOL_BITS + bit;
+                                    data[at / 8] |= 0x80 >> (at % 8);
+                                }
+                            }
+                        }
+                        bytes.extend(data);
+                    }
+                }
+            }
+            bytes
+        }
+
+        // The table for the values `value` gives to the positions in the placements of
+        // the pieces, as the table sees them: the pieces in `self.pieces` are white's
+        fn write(&self, dir: &Path, value: impl Fn(&Position) -> Option<u8>) {
+            let layout = Table::parse(self.bytes(None, &[]), self.material, self.is_dtz).unwrap();
+            let (sides, files) = layout.subtables();
+            let mut values: Vec<Vec<Vec<Option<u8>>>> = (0..sides)
+                .map(|side| {
+                    (0..files)
+                        .map(|file| vec![None; layout.size(side, file) as usize])
+                        .collect()
+                })
+                .collect();
+
+            let mut pos = Position::empty();
+            let count = self.pieces.len();
+            for placement in 0..64usize.pow(count as u32) {
+                let squares: Vec<usize> =
+                    (0..count).map(|i| placement / 64usize.pow(i as u32) % 64).collect();
+                if !place(&mut pos, &self.pieces, &squares) {
+                    continue;
+                }
+                for side_to_move in [Side::WHITE, Side::BLACK] {
+                    pos.side_to_move.0 = side_to_move;
+                    let Some(value) = value(&pos) else {
+                        continue;
+                    };
+                    let (stm, file, index) = layout.index(&pos).unwrap();
+                    if stm >= sides {
+                        continue;
+                    }
+                    // Positions the same but for symmetry share their place in the table
+                    let slot = &mut values[stm][file][index as usize];
+                    assert!(slot.is_none() || *slot == Some(value), "{}", pos.to_fen());
+                    *slot = Some(value);
+                }
+            }
+
+            let values: Vec<Vec<Vec<u8>>> = values
+                .into_iter()
+                .map(|files| {
+                    files
+                        .into_iter()
+                        .map(|values| values.into_iter().map(|value| value.unwrap_or(0)).collect())
+                        .collect()
+                })
+                .collect();
+            let suffix = if self.is_dtz { DTZ_SUFFIX } else { WDL_SUFFIX };
+            let path = dir.join(format!("{}.{suffix}", self.material.name()));
+            fs::write(path, self.bytes(Some(&layout), &values)).unwrap();
+        }
+    }
+
+    fn pad_to(bytes: &mut Vec<u8>, alignment: usize) {
+        while !bytes.len().is_multiple_of(alignment) {
+            bytes.push(0);
+        }
+    }
+
+    // Puts the pieces on the squares, false if two share one, the kings touch or a pawn is
+    // on the first or last rank
+    fn place(pos: &mut Position, pieces: &[(usize, usize)], squares: &[usize]) -> bool {
+        let mut occupancy = 0u64;
+        let mut kings = vec![];
+        for ((_, piece), square) in pieces.iter().zip(squares) {
+            if occupancy & 1 << square != 0
+                || (*piece == Piece::PAWN && !(8..56).contains(square))
+            {
+                return false;
+            }
+            occupancy |= 1 << square;
+            if *piece == Piece::KING {
+                kings.push(*square);
+            }
+        }
+        let (a, b) = (kings[0], kings[1]);
+        if (a / 8).abs_diff(b / 8) <= 1 && (a % 8).abs_diff(b % 8) <= 1 {
+            return false;
+        }
+
+        pos.board.side_pieces = Default::default();
+        pos.board.pieces = Default::default();
+        for ((side, piece), square) in pieces.iter().zip(squares) {
+            pos.board.side_pieces[*side].0 |= 1 << square;
+            pos.board.pieces[*side][*piece].0 |= 1 << square;
+        }
+        true
+    }
+
+    // The same position set up from scratch, so it can be played on
+    fn playable(pos: &Position) -> Position {
+        FenParser::fen_to_position(&pos.to_fen())
+    }
+
+    fn wdl_value(pos: &Position) -> Option<u8> {
+        match EndgameTable::probe(pos)? {
+            Dtm::Win(_) => Some(4),
+            Dtm::Draw => Some(2),
+            Dtm::Loss(_) => Some(0),
+        }
+    }
+
+    // In moves, wins only: a mate in n has n - 1
+    fn dtz_value(pos: &Position) -> Option<u8> {
+        match EndgameTable::probe(pos)? {
+            Dtm::Win(plies) => Some((plies as u8 - 1) / 2),
+            _ => Some(0),
+        }
+    }
+
+    fn writer(name: &str, is_dtz: bool) -> TableWriter {
+        let material = Material::parse(name).unwrap();
+        // Pawns lead, otherwise the kings do
+        let mut pieces = vec![(Side::WHITE, Piece::KING), (Side::BLACK, Piece::KING)];
+        for piece in [Piece::QUEEN, Piece::ROOK, Piece::BISHOP, Piece::KNIGHT, Piece::PAWN] {
+            for side in [Side::WHITE, Side::BLACK] {
+                let count = material.counts[side][piece] as usize;
+                pieces.extend(repeat_n((side, piece), count));
+            }
+        }
+        pieces.sort_by_key(|(_, piece)| *piece != Piece::PAWN);
+        TableWriter {
+            material,
+            is_dtz,
+            pieces,
+        }
+    }
+
+    // KQvK, KRvK and KPvK from the endgame tables, and KBvK, which has only draws
+    fn tables_dir() -> &'static PathBuf {
+        static DIR: OnceLock<PathBuf> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = env::temp_dir().join(format!("rustypigeon_syzygy_{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for name in ["KQvK", "KRvK", "KPvK"] {
+                writer(name, false).write(&dir, wdl_value);
+            }
+            writer("KQvK", true).write(&dir, |pos| {
+                (pos.side_to_move.0 == Side::WHITE).then(|| dtz_value(pos)).flatten()
+            });
+            writer("KBvK", false).write(&dir, |_| Some(2));
+            dir
+        })
+    }
+
+    fn tablebases() -> Tablebases {
+        Tablebases::open(tables_dir().to_str().unwrap()).unwrap()
+    }
+
+    // Positions of the ending spread over the board, with both sides to move and either
+    // color as the strong one
+    fn sample_positions(material: EndgameMaterial, step: usize) -> Vec<Position> {
+        let extra = material.pieces();
+        let mut pieces = vec![(Side::WHITE, Piece::KING), (Side::BLACK, Piece::KING)];
+        pieces.extend(extra.iter().map(|piece| (Side::WHITE, *piece)));
+        let mut positions = vec![];
+        let mut pos = Position::empty();
+        for placement in (0..64usize.pow(pieces.len() as u32)).step_by(step) {
+            let squares: Vec<usize> =
+                (0..pieces.len()).map(|i| placement / 64usize.pow(i as u32) % 64).collect();
+            for strong in [Side::WHITE, Side::BLACK] {
+                let pieces: Vec<(usize, usize)> = pieces
+                    .iter()
+                    .map(|(side, piece)| (*side ^ strong, *piece))
+                    .collect();
+                // Black pawns go down the board
+                let squares: Vec<usize> = squares
+                    .iter()
+                    .map(|square| if strong == Side::BLACK { square ^ 56 } else { *square })
+                    .collect();
+                if !place(&mut pos, &pieces, &squares) {
+                    continue;
+                }
+                for side_to_move in [Side::WHITE, Side::BLACK] {
+                    pos.side_to_move.0 = side_to_move;
+                    if EndgameTable::probe(&pos).is_some() {
+                        positions.push(playable(&pos));
+                    }
+                }
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn names_tables_by_material() {
+        let material = Material::parse("KRPvKR").unwrap();
+        assert_eq!(material.counts[Side::WHITE][Piece::PAWN], 1);
+        assert_eq!(material.count(), 5);
+        assert_eq!(material.name(), "KRPvKR");
+        assert_eq!(material.flipped().name(), "KRvKRP");
+        assert_eq!(Material::parse("KQv"), None);
+        assert_eq!(Material::parse("KQK"), None);
+
+        let pos = FenParser::fen_to_position("8/8/4k3/8/8/8/2KQ4/8 b - - 0 1");
+        assert_eq!(Material::of(&pos).name(), "KQvK");
+    }
+
+    #[test]
+    fn tables_have_room_for_every_placement() {
+        let layout = |name: &str| {
+            let writer = writer(name, false);
+            Table::parse(writer.bytes(None, &[]), writer.material, false).unwrap()
+        };
+        // Three unique pieces, then two kings and a pair of knights on the other squares
+        assert_eq!(layout("KQvK").size(0, 0), 31332);
+        assert_eq!(layout("KNNvK").size(0, 0), 462 * 62 * 61 / 2);
+        // A pawn on one of the six ranks of its file, then the kings
+        let pawns = layout("KPvK");
+        for file in 0..4 {
+            assert_eq!(pawns.size(1, file), 6 * 63 * 62);
+        }
+    }
+
+    #[test]
+    fn wdl_matches_the_endgame_tables() {
+        let tablebases = tablebases();
+        for (material, step) in [
+            (EndgameMaterial::KQK, 293),
+            (EndgameMaterial::KRK, 293),
+            (EndgameMaterial::KPK, 271),
+        ] {
+            for mut pos in sample_positions(material, step) {
+                let expected = match EndgameTable::probe(&pos).unwrap() {
+                    Dtm::Win(_) => Wdl::Win,
+                    Dtm::Draw => Wdl::Draw,
+                    Dtm::Loss(_) => Wdl::Loss,
+                };
+                assert_eq!(tablebases.probe_wdl(&mut pos), Some(expected), "{}", pos.to_fen());
+            }
+        }
+    }
+
+    #[test]
+    fn dtz_counts_the_plies_to_mate_without_captures() {
+        let tablebases = tablebases();
+        for mut pos in sample_positions(EndgameMaterial::KQK, 251) {
+            let expected = match EndgameTable::probe(&pos).unwrap() {
+                Dtm::Win(plies) => plies as i32,
+                Dtm::Loss(0) => -1,
+                Dtm::Loss(plies) => -(plies as i32),
+                Dtm::Draw => 0,
+            };
+            assert_eq!(tablebases.probe_dtz(&mut pos), Some(expected), "{}", pos.to_fen());
+        }
+    }
+
+    #[test]
+    fn positions_missing_from_the_tables_are_not_probed() {
+        let tablebases = tablebases();
+        // No KQvKR table
+        let mut pos = FenParser::fen_to_position("r7/8/4k3/8/8/8/2KQ4/8 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut pos), None);
+        // Bare kings need no table
+        let mut pos = FenParser::fen_to_position("8/8/4k3/8/8/8/2K5/8 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut pos), Some(Wdl::Draw));
+        // A winning capture into a table is enough, a drawing one isn't
+        let mut pos = FenParser::fen_to_position("8/8/4k3/8/8/2r5/2KQ4/8 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut pos), Some(Wdl::Win));
+        let mut pos = FenParser::fen_to_position("8/8/4k3/8/8/2b5/2KB4/8 w - - 0 1");
+        assert_eq!(tablebases.probe_wdl(&mut pos), None);
+    }
+
+    #[test]
+    fn root_moves_keep_the_win_on_track() {
+        let tablebases = tablebases();
+        let pos = FenParser::fen_to_position("8/8/8/4k3/8/8/8/1Q2K3 w - - 0 1");
+        let Some(Dtm::Win(plies)) = EndgameTable::probe(&pos) else {
+            panic!("KQvK is a win");
+        };
+        let (m, wdl) = tablebases.root_move(&pos).unwrap();
+        assert_eq!(wdl, Wdl::Win);
+        let mut after = pos.clone();
+        after.apply_move(&m);
+        assert_eq!(EndgameTable::probe(&after), Some(Dtm::Loss(plies - 1)));
+
+        // Nothing to play for in a draw
+        let pos = FenParser::fen_to_position("8/8/4k3/8/8/8/2KB4/8 w - - 0 1");
+        assert!(tablebases.root_move(&pos).is_none());
+    }
+
+    #[test]
+    fn settings_keep_to_the_probe_limit() {
+        let mut settings = SyzygySettings::new();
+        let mut pos = FenParser::fen_to_position("8/8/4k3/8/8/8/2KQ4/8 b - - 0 1");
+        assert_eq!(settings.probe_in_search(&mut pos), None);
+
+        settings.path = tables_dir().to_str().unwrap().to_string();
+        settings.load().unwrap();
+        assert_eq!(settings.tables.as_ref().unwrap().max_pieces, 3);
+        assert_eq!(settings.probe_in_search(&mut pos), Some(Wdl::Loss));
+        assert!(Wdl::Loss.score(3) < -MATE_VALUE / 2);
+        assert_eq!(Wdl::Win.score(3), TB_WIN_VALUE - 3);
+
+        settings.probe_limit = 2;
+        assert_eq!(settings.probe_in_search(&mut pos), None);
+        // The fifty move counter must be fresh
+        settings.probe_limit = 3;
+        let mut pos = FenParser::fen_to_position("8/8/4k3/8/8/8/2KQ4/8 b - - 12 40");
+        assert_eq!(settings.probe_in_search(&mut pos), None);
+    }
+
+    #[test]
+    fn search_counts_table_hits() {
+        let mut engine =
+            Engine::from_position(FenParser::fen_to_position("8/8/4k3/8/8/2p5/3B4/2K5 w - - 0 1"));
+        engine.is_quiet = true;
+        engine.syzygy.path = tables_dir().to_str().unwrap().to_string();
+        engine.syzygy.load().unwrap();
+        Evaluate::search(&mut engine, GoOptions::depth(4), None);
+        assert!(engine.shared.tb_hits.load(std::sync::atomic::Ordering::Relaxed) > 0);
+    }
+}
@@ -10,11 +10,27 @@ use std::{
 };
 
 use crate::{
-    board::{fen::FenParser, models::Engine},
+    board::{
+        fen::FenParser,
+        models::{Engine, SharedSearch},
+        utils::move_to_algebraic,
+    },
+    book::reader::{DEFAULT_BOOK_DEPTH, MAX_BOOK_DEPTH},
     constants::START_POS,
-    evaluate::evaluator::Evaluate,
+    endgame::table::EndgameTable,
+    evaluate::{
+        evaluator::{Evaluate, SEARCH_STACK_SIZE},
+        search_params::{
+            DEFAULT_LMP_DEPTH, DEFAULT_LMP_MOVES, DEFAULT_LMR_BASE, DEFAULT_LMR_DIVISOR,
+        },
+        transposition::{DEFAULT_HASH_MB, MAX_HASH_MB},
+    },
+    movegen::generator::{MoveGenKind, MoveGenerator},
+    syzygy::{probe::DEFAULT_PROBE_LIMIT, table::MAX_PIECES},
 };
 
+const MAX_THREADS: usize = 256;
+
 // Implementation of the UCI protocol
 pub struct UCI {
     engine: Arc<Mutex<Engine>>,
@@ -44,6 +60,7 @@ impl UCI {
         match &command[..] {
             "uci" => {
                 Self::uci();
+                EndgameTable::solve_on_demand();
             }
             "isready" => {
                 let e = self.engine.clone();
@@ -95,6 +112,11 @@ impl UCI {
                         Self::position(e, &command);
                     });
                 }
+                // setoption
+                else if command.starts_with("setoption") {
+                    let mut engine = self.engine.lock().unwrap();
+                    Self::setoption(&mut engine, &command);
+                }
                 // go
                 else if command.starts_with("go") {
                     let rx = self.rx.clone();
@@ -105,9 +127,12 @@ impl UCI {
                         thread::sleep(time::Duration::from_millis(100));
                     }
                     let e = self.engine.clone();
-                    thread::spawn(move || {
-                        Self::go(e, &command, rx);
-                    });
+                    thread::Builder::new()
+                        .stack_size(SEARCH_STACK_SIZE)
+                        .spawn(move || {
+                            Self::go(e, &command, rx);
+                        })
+                        .expect("Failed to spawn the search thread");
                 }
                 /* Unknown command, ignore it, as from the specification */
             }
@@ -117,50 +142,199 @@ impl UCI {
     fn uci() {
         println!("id name Rusty Pigeon");
         println!("id author TrinTragula (https://github.com/TrinTragula)");
+        println!("option name UCI_Chess960 type check default false");
+        println!("option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}");
+        println!("option name Clear Hash type button");
+        println!("option name Threads type spin default 1 min 1 max {MAX_THREADS}");
+        // Search tuning, the defaults are what the engine plays with
+        println!("option name LMR Base type spin default {DEFAULT_LMR_BASE} min 0 max 500");
+        println!("option name LMR Divisor type spin default {DEFAULT_LMR_DIVISOR} min 50 max 1000");
+        println!("option name LMP Depth type spin default {DEFAULT_LMP_DEPTH} min 0 max 10");
+        println!("option name LMP Moves type spin default {DEFAULT_LMP_MOVES} min 0 max 100");
+        // Polyglot opening book
+        println!("option name OwnBook type check default false");
+        println!("option name BookFile type string default <empty>");
+        println!("option name BookDepth type spin default {DEFAULT_BOOK_DEPTH} min 0 max {MAX_BOOK_DEPTH}");
+        println!("option name BookVariety type check default false");
+        // Syzygy endgame tablebases, directories separated like in PATH
+        println!("option name SyzygyPath type string default <empty>");
+        println!(
+            "option name SyzygyProbeLimit type spin default {DEFAULT_PROBE_LIMIT} min 0 max {MAX_PIECES}"
+        );
         println!("uciok");
     }
 
+    // setoption name <name> [value <value>], unknown options are ignored
+    fn setoption(engine: &mut Engine, command: &str) {
+        let mut name: Vec<&str> = vec![];
+        let mut value: Vec<&str> = vec![];
+        let mut section = "";
+        for arg in command.split_whitespace().skip(1) {
+            match arg {
+                "name" | "value" => section = arg,
+                _ => match section {
+                    "name" => name.push(arg),
+                    "value" => value.push(arg),
+                    _ => {}
+                },
+            }
+        }
+        let name = name.join(" ").to_lowercase();
+        match name.as_str() {
+            "uci_chess960" => {
+                engine.is_chess960 = value.join(" ") == "true";
+                engine.position.is_chess960 = engine.is_chess960;
+            }
+            "hash" => {
+                if let Ok(size_mb) = value.join(" ").parse::<usize>() {
+                    engine.shared = Arc::new(SharedSearch::new(size_mb));
+                }
+            }
+            "clear hash" => engine.shared.transposition_table.clear(),
+            "threads" => {
+                if let Ok(threads) = value.join(" ").parse::<usize>() {
+                    engine.threads = threads.clamp(1, MAX_THREADS);
+                }
+            }
+            "lmr base" => {
+                if let Ok(base) = value.join(" ").parse::<usize>() {
+                    engine.search_params.set_lmr_base(base.min(500));
+                }
+            }
+            "lmr divisor" => {
+                if let Ok(divisor) = value.join(" ").parse::<usize>() {
+                    engine.search_params.set_lmr_divisor(divisor.clamp(50, 1000));
+                }
+            }
+            "lmp depth" => {
+                if let Ok(depth) = value.join(" ").parse::<usize>() {
+                    engine.search_params.lmp_depth = depth.min(10);
+                }
+            }
+            "lmp moves" => {
+                if let Ok(moves) = value.join(" ").parse::<usize>() {
+                    engine.search_params.lmp_moves = moves.min(100);
+                }
+            }
+            "ownbook" => {
+                engine.book.own_book = value.join(" ") == "true";
+                Self::load_book(engine);
+            }
+            "bookfile" => {
+                let file = value.join(" ");
+                engine.book.file = if file == "<empty>" { String::new() } else { file };
+                Self::load_book(engine);
+            }
+            "bookdepth" => {
+                if let Ok(depth) = value.join(" ").parse::<usize>() {
+                    engine.book.depth = depth.min(MAX_BOOK_DEPTH);
+                }
+            }
+            "bookvariety" => engine.book.variety = value.join(" ") == "true",
+            "syzygypath" => {
+                let path = value.join(" ");
+                engine.syzygy.path = if path == "<empty>" { String::new() } else { path };
+                Self::load_syzygy(engine);
+            }
+            "syzygyprobelimit" => {
+                if let Ok(limit) = value.join(" ").parse::<usize>() {
+                    engine.syzygy.probe_limit = limit.min(MAX_PIECES);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn load_book(engine: &mut Engine) {
+        if let Err(error) = engine.book.load() {
+            println!("info string could not read book {}: {error}", engine.book.file);
+        }
+    }
+
+    fn load_syzygy(engine: &mut Engine) {
+        match engine.syzygy.load() {
+            Ok(()) => {
+                if let Some(tables) = &engine.syzygy.tables {
+                    println!("info string found {} tablebases", tables.len());
+                }
+            }
+            Err(error) => {
+                println!("info string could not read tablebases {}: {error}", engine.syzygy.path)
+            }
+        }
+    }
+
     fn isready() {
         println!("readyok");
     }
 
     fn ucinewgame(e: Arc<Mutex<Engine>>) {
         let mut engine = e.lock().unwrap();
-        *engine = Engine::empty();
+        // Options are kept between games, the hash table only keeps its size
+        let shared = engine.shared.clone();
+        shared.transposition_table.clear();
+        *engine = Engine {
+            is_chess960: engine.is_chess960,
+            threads: engine.threads,
+            search_params: engine.search_params.clone(),
+            book: engine.book.clone(),
+            syzygy: engine.syzygy.clone(),
+            ..Engine::with_shared(shared)
+        };
     }
 
     fn startpos(e: Arc<Mutex<Engine>>) {
         let mut engine = e.lock().unwrap();
         engine.is_configuring = true;
         engine.position = FenParser::fen_to_position(START_POS);
+        engine.position.is_chess960 = engine.is_chess960;
         engine.is_configuring = false;
     }
 
     fn position(e: Arc<Mutex<Engine>>, command: &str) {
-        let args = command.split(' ').skip(1);
         let mut engine = e.lock().unwrap();
         engine.is_configuring = true;
-        let mut is_fen = false;
-        let mut is_fen_parsed = false;
-        for arg in args {
-            if arg == "startpos" {
-                engine.position = FenParser::fen_to_position(START_POS);
-            } else if arg == "moves" {
-                is_fen = false;
-            } else if arg == "fen" {
-                is_fen = true;
-            } else {
-                if is_fen {
-                    if !is_fen_parsed {
-                        let fen = command.split(' ').skip(2).collect::<Vec<&str>>().join(" ");
-                        engine.position = FenParser::fen_to_position(&fen);
-                        is_fen_parsed = true;
-                    }
-                    continue;
-                } else {
-                    engine.apply_algebraic_move(arg);
+        let mut section = "";
+        let mut fen: Vec<&str> = vec![];
+        let mut moves: Vec<&str> = vec![];
+        for arg in command.split_whitespace().skip(1) {
+            match arg {
+                "startpos" | "fen" | "moves" => section = arg,
+                _ => match section {
+                    "fen" => fen.push(arg),
+                    "moves" => moves.push(arg),
+                    _ => {}
+                },
+            }
+        }
+        let fen = if fen.is_empty() {
+            String::from(START_POS)
+        } else {
+            fen.join(" ")
+        };
+        let position = if engine.is_chess960 {
+            FenParser::try_parse_chess960(&fen)
+        } else {
+            FenParser::try_parse(&fen)
+        };
+        match position {
+            Ok(position) => {
+                engine.position = position;
+                for m in moves {
+                    let legal_moves =
+                        MoveGenerator::get_legal_moves(&mut engine.position, &MoveGenKind::All);
+                    let Some(legal_move) = legal_moves
+                        .into_iter()
+                        .find(|legal_move| move_to_algebraic(legal_move, &engine.position) == m)
+                    else {
+                        // The rest of the moves were played from a different position
+                        println!("info string invalid move {m}");
+                        break;
+                    };
+                    engine.apply_move(&legal_move);
                 }
             }
+            Err(error) => println!("info string invalid fen: {error}"),
         }
         engine.is_configuring = false;
     }
@@ -172,9 +346,7 @@ impl UCI {
 
     pub fn bestmove(engine: &Engine) {
         let best_move = match &engine.current_best_move {
-            Some(m) => {
-                format!("{}", m)
-            }
+            Some(m) => move_to_algebraic(m, &engine.position),
             None => String::from("0000"),
         };
         println!("bestmove {best_move}");
@@ -1,8 +1,10 @@
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use rustypigeonlib::{
-    board::{fen::FenParser, models::Engine},
+    board::{fen::FenParser, models::Engine, utils::square_to_algebraic},
+    book::reader::{Book, BookSettings},
     constants::START_POS,
+    endgame::table::EndgameTable,
     evaluate::evaluator::Evaluate,
     uci::options::GoOptions,
 };
@@ -16,13 +18,63 @@ lazy_static! {
 #[wasm_bindgen]
 pub fn startpos() {
     let mut e = ENGINE.lock().unwrap();
-    *e = Engine::from_position(FenParser::fen_to_position(START_POS));
+    // A new game, the table is kept but not what's in it
+    e.shared.transposition_table.clear();
+    *e = Engine {
+        position: FenParser::fen_to_position(START_POS),
+        book: e.book.clone(),
+        ..Engine::with_shared(e.shared.clone())
+    };
 }
 
+// Empty if the position was set, otherwise why the FEN is invalid
 #[wasm_bindgen]
-pub fn set_pos(fen: String) {
+pub fn set_pos(fen: String) -> String {
     let mut e = ENGINE.lock().unwrap();
-    *e = Engine::from_position(FenParser::fen_to_position(&fen));
+    match FenParser::try_parse(&fen) {
+        Ok(position) => {
+            // Called after every move, the table carries on from the last search
+            *e = Engine {
+                position,
+                book: e.book.clone(),
+                ..Engine::with_shared(e.shared.clone())
+            };
+            String::new()
+        }
+        Err(error) => format!("{error}"),
+    }
+}
+
+// Check a FEN from the board editor without touching the engine.
+// Empty if the position is valid, otherwise why it is not
+#[wasm_bindgen]
+pub fn validate_pos(fen: String) -> String {
+    match FenParser::try_parse(&fen) {
+        Ok(_) => String::new(),
+        Err(error) => format!("{error}"),
+    }
+}
+
+// Solves the small endgame tables, the search only uses tables solved beforehand.
+// Takes a moment, so it is called once from the worker
+#[wasm_bindgen]
+pub fn solve_endgame_tables() {
+    EndgameTable::solve_small();
+}
+
+// Plays from a Polyglot book for the rest of the session, the worker fetches the file.
+// Returns how many entries the book has
+#[wasm_bindgen]
+pub fn load_book(bytes: Vec<u8>) -> usize {
+    let mut e = ENGINE.lock().unwrap();
+    let book = Book::from_bytes(&bytes);
+    let entries = book.len();
+    e.book = BookSettings {
+        own_book: true,
+        book: Some(Arc::new(book)),
+        ..BookSettings::new()
+    };
+    entries
 }
 
 #[wasm_bindgen]
@@ -31,9 +83,45 @@ pub fn show() -> String {
     format!("{}", e.position)
 }
 
+// Empty if the game is still going on, otherwise how it ended
+#[wasm_bindgen]
+pub fn game_status() -> String {
+    let mut e = ENGINE.lock().unwrap();
+    match e.game_status() {
+        Some(outcome) => format!("{outcome}"),
+        None => String::new(),
+    }
+}
+
+// Pieces giving check to the side to move, as space separated squares
+#[wasm_bindgen]
+pub fn checkers() -> String {
+    let e = ENGINE.lock().unwrap();
+    squares_to_string(e.position.checkers())
+}
+
+// Pinned pieces of the side to move, as space separated squares
+#[wasm_bindgen]
+pub fn pinned() -> String {
+    let e = ENGINE.lock().unwrap();
+    squares_to_string(e.position.pinned(e.position.side_to_move.0))
+}
+
+fn squares_to_string(mut bitboard: u64) -> String {
+    let mut squares = vec![];
+    while bitboard > 0 {
+        squares.push(square_to_algebraic(1u64 << bitboard.trailing_zeros()));
+        bitboard &= bitboard - 1;
+    }
+    squares.join(" ")
+}
+
 #[wasm_bindgen]
 pub fn get_move(movetime: isize) -> String {
     let mut e = ENGINE.lock().unwrap();
+    if e.game_status().is_some() {
+        return String::from("0000");
+    }
     Evaluate::search(
         &mut e,
         GoOptions {
@@ -42,5 +130,8 @@ pub fn get_move(movetime: isize) -> String {
         },
         None,
     );
-    format!("{}", e.current_best_move.as_ref().unwrap())
+    match &e.current_best_move {
+        Some(m) => format!("{}", m),
+        None => String::from("0000"),
+    }
 }
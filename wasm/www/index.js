@@ -37,6 +37,17 @@ function resizeBoard() {
 
 const rustyWorker = new Worker();
 rustyWorker.onmessage = function (e) {
+    if (e.data.name == "game_over") {
+        document.getElementById("status").innerHTML = e.data.argument;
+        document.getElementById("playAsWhite").removeAttribute("disabled");
+        document.getElementById("playAsBlack").removeAttribute("disabled");
+    }
+    if (e.data.name == "invalid_pos") {
+        document.getElementById("status").innerHTML = "Invalid position: " + e.data.argument;
+    }
+    if (e.data.name == "pos_set") {
+        loadPos(e.data.argument);
+    }
     if (e.data.name == "move") {
         let move = e.data.argument;
         move = game.move(move, { sloppy: true });
@@ -50,6 +61,7 @@ rustyWorker.onmessage = function (e) {
         });
         rustyWorker.postMessage({ name: "set_pos", argument: game.fen() });
         document.getElementById("status").innerHTML = "Your turn!";
+        rustyWorker.postMessage({ name: "game_status" });
         document.getElementById("playAsWhite").removeAttribute("disabled");
         document.getElementById("playAsBlack").removeAttribute("disabled");
     }
@@ -111,3 +123,27 @@ function resetPos(playAsBlack) {
         });
     }
 }
+
+// The engine checks the position first, the player moves next from it
+document.getElementById("setPosition").onclick = () => {
+    const fen = document.getElementById("fen").value.trim();
+    rustyWorker.postMessage({ name: "edit_pos", argument: fen });
+};
+function loadPos(fen) {
+    if (!game.load(fen)) {
+        document.getElementById("status").innerHTML = "Invalid position: " + game.validate_fen(fen).error;
+        return;
+    }
+    board.set({
+        turnColor: toColor(game),
+        orientation: toColor(game),
+        movable: {
+            color: toColor(game),
+            dests: toDests(game)
+        },
+        fen: game.fen(),
+        lastMove: null
+    });
+    document.getElementById("status").innerHTML = "Your turn!";
+    rustyWorker.postMessage({ name: "game_status" });
+}
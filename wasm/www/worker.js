@@ -1,9 +1,35 @@
 import("rustypigeonwasm").then(function (rusty) {
+    rusty.solve_endgame_tables();
+    // The opening book is optional, without it every move is searched
+    fetch("book.bin")
+        .then(response => response.ok ? response.arrayBuffer() : null)
+        .then(buffer => buffer && rusty.load_book(new Uint8Array(buffer)))
+        .catch(() => {});
     onmessage = function (e) {
         if (e.data.name == "set_pos") {
             rusty.set_pos(e.data.argument);
         }
+        if (e.data.name == "edit_pos") {
+            let error = rusty.validate_pos(e.data.argument);
+            if (error) {
+                postMessage({ name: "invalid_pos", argument: error });
+                return;
+            }
+            rusty.set_pos(e.data.argument);
+            postMessage({ name: "pos_set", argument: e.data.argument });
+        }
+        if (e.data.name == "game_status") {
+            let status = rusty.game_status();
+            if (status) {
+                postMessage({ name: "game_over", argument: status });
+            }
+        }
         if (e.data.name == "get_move") {
+            let status = rusty.game_status();
+            if (status) {
+                postMessage({ name: "game_over", argument: status });
+                return;
+            }
             let move = rusty.get_move(e.data.argument);
             postMessage({ name: "move", argument: move });
         }